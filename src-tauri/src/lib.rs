@@ -15,6 +15,7 @@ pub async fn run() {
         .output_device()
         .expect("Failed to get master output device");
     let state_mixer = project::states::StateMixer::new(master_output_device);
+    let transport = state_mixer.transport.clone();
     let state_mixer_guard = project::states::StateMixerGuard(Arc::new(Mutex::new(state_mixer)));
 
     Builder::default()
@@ -23,6 +24,8 @@ pub async fn run() {
         .setup(move |app| {
             let menu = menus::menu_builders::build_menus(app);
             app.set_menu(menu)?;
+            project::transport::spawn_position_emitter(app.handle().clone(), transport);
+            track::processors::dynamics::spawn_gain_reduction_emitter(app.handle().clone());
             Ok(())
         })
         .on_menu_event(|app, event| {
@@ -36,11 +39,59 @@ pub async fn run() {
         .invoke_handler(generate_handler!(
             track::commands::get_track_list,
             track::commands::update_track,
+            track::commands::import_clip,
+            track::commands::move_clip,
+            track::commands::remove_clip,
+            track::commands::split_clip,
+            track::commands::trim_clip,
+            track::commands::duplicate_clip,
+            track::commands::slip_clip,
+            track::commands::set_clip_gain,
+            track::commands::reverse_clip,
+            track::commands::set_clip_fades,
+            track::commands::set_crossfade,
+            track::commands::select_take,
+            track::commands::set_comp,
+            track::commands::set_pan_law,
+            track::commands::set_solo_mode,
+            track::commands::add_insert,
+            track::commands::remove_insert,
+            track::commands::move_insert,
+            track::commands::set_insert_bypass,
+            track::commands::load_insert_file,
+            track::commands::set_insert_key,
+            track::commands::set_send,
+            track::commands::remove_send,
+            track::commands::set_track_output,
+            track::commands::set_automation_points,
+            track::commands::release_automation,
+            track::commands::create_group,
+            track::commands::remove_group,
+            track::commands::set_track_group,
+            track::commands::set_insert_parameter,
+            track::commands::get_frequency_response,
             menus::commands::add_empty_track,
+            menus::commands::add_bus_track,
             project::commands::start_stream,
             project::commands::stop_stream,
+            project::commands::record_stream,
+            project::commands::pause_stream,
+            project::commands::return_to_zero,
+            project::commands::locate_transport,
+            project::commands::get_transport_info,
+            project::commands::set_loop_range,
+            project::commands::set_punch_range,
             project::commands::load_project,
             project::commands::save_project,
+            project::commands::export_mixdown,
+            project::commands::undo,
+            project::commands::redo,
+            project::commands::set_tempo_map,
+            project::commands::set_metronome,
+            project::commands::samples_to_bar_beat_tick,
+            project::commands::bar_beat_tick_to_samples,
+            project::commands::get_bar_lines,
+            project::commands::snap_to_grid,
         ))
         .on_window_event(move |w, e| match e {
             WindowEvent::CloseRequested { .. } => {
@@ -1,3 +1,5 @@
+use std::sync::{Arc, Mutex};
+
 use tauri::{AppHandle, Manager};
 use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 
@@ -18,10 +20,12 @@ pub fn add_empty_track(app_handle: AppHandle) -> Result<(), ()> {
                     app_handle.clone(),
                     name.clone(),
                 );
-                let track = track::tracks::InputTrack::new(&name, Box::new(source));
-                let new_track_name = format!("track-{}", number);
-                track_list.add_track(&new_track_name, track);
-                Ok(())
+                let track = Arc::new(Mutex::new(track::tracks::InputTrack::new(
+                    &name,
+                    Some(Box::new(source)),
+                )));
+                track_list.add_arc_mut_track(&name, track.clone());
+                Ok(project::history::Edit::AddTrack { name, track })
             } else {
                 app_handle
                     .dialog()
@@ -52,5 +56,50 @@ pub fn add_empty_track(app_handle: AppHandle) -> Result<(), ()> {
             .blocking_show();
         Err(())
     };
-    res
+
+    let edit = res?;
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.record_edit(edit);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn add_bus_track(app_handle: AppHandle) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let mut state_mixer = match state_mixer_guard.0.lock() {
+        Ok(state_mixer) => state_mixer,
+        Err(_) => {
+            app_handle
+                .dialog()
+                .message("Failed to lock state mixer")
+                .title("State Error")
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::Ok)
+                .blocking_show();
+            return Err(());
+        }
+    };
+
+    let edit = if let Ok(mut track_list) = state_mixer.track_list.lock() {
+        let mut number = 1;
+        while track_list.get_track(&format!("bus-{}", number)).is_some() {
+            number += 1;
+        }
+        let name = format!("bus-{}", number);
+        let track = Arc::new(Mutex::new(track::tracks::InputTrack::bus(&name)));
+        track_list.add_arc_mut_track(&name, track.clone());
+        project::history::Edit::AddTrack { name, track }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock track list")
+            .title("Track List Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+        return Err(());
+    };
+    state_mixer.record_edit(edit);
+    Ok(())
 }
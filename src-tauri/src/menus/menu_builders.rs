@@ -20,9 +20,15 @@ fn build_file_menu(app: &App<Wry>) -> Submenu<Wry> {
         .build(app)
         .unwrap();
 
+    let export_mixdown = MenuItemBuilder::new("Export mixdown")
+        .id("file-export-mixdown")
+        .accelerator("CmdOrCtrl+E")
+        .build(app)
+        .unwrap();
+
     let file_menu = SubmenuBuilder::new(app, "File")
         .id("file")
-        .items(&[&open_file])
+        .items(&[&open_file, &export_mixdown])
         .quit()
         .build()
         .unwrap();
@@ -30,6 +36,28 @@ fn build_file_menu(app: &App<Wry>) -> Submenu<Wry> {
     file_menu
 }
 
+fn build_edit_menu(app: &App<Wry>) -> Submenu<Wry> {
+    let undo = MenuItemBuilder::new("Undo")
+        .id("edit-undo")
+        .accelerator("CmdOrCtrl+Z")
+        .build(app)
+        .unwrap();
+
+    let redo = MenuItemBuilder::new("Redo")
+        .id("edit-redo")
+        .accelerator("CmdOrCtrl+Shift+Z")
+        .build(app)
+        .unwrap();
+
+    let edit_menu = SubmenuBuilder::new(app, "Edit")
+        .id("edit")
+        .items(&[&undo, &redo])
+        .build()
+        .unwrap();
+
+    edit_menu
+}
+
 fn build_preferences_menu(app: &App<Wry>) -> Submenu<Wry> {
     let audio_context = app.state::<project::states::StateAudioContext>();
     let output_device_registry = audio_context.output_device_registry.clone();
@@ -93,12 +121,23 @@ fn build_project_menu(app: &App<Wry>) -> Submenu<Wry> {
         .build(app)
         .unwrap();
 
+    let add_bus = MenuItemBuilder::new("Add Bus")
+        .accelerator("CmdOrCtrl+Shift+A")
+        .id("project-add-bus")
+        .build(app)
+        .unwrap();
+
     let save_project = MenuItemBuilder::new("Save project")
         .accelerator("CmdOrCtrl+S")
         .id("project-save-project")
         .build(app)
         .unwrap();
 
+    let save_project_binary = MenuItemBuilder::new("Save project as binary")
+        .id("project-save-project-binary")
+        .build(app)
+        .unwrap();
+
     let load_project = MenuItemBuilder::new("Open project")
         .accelerator("CmdOrCtrl+L")
         .id("project-open-project")
@@ -106,7 +145,13 @@ fn build_project_menu(app: &App<Wry>) -> Submenu<Wry> {
         .unwrap();
 
     let project_menu = SubmenuBuilder::new(app, "Project")
-        .items(&[&add_track, &save_project, &load_project])
+        .items(&[
+            &add_track,
+            &add_bus,
+            &save_project,
+            &save_project_binary,
+            &load_project,
+        ])
         .build()
         .unwrap();
     project_menu
@@ -114,10 +159,11 @@ fn build_project_menu(app: &App<Wry>) -> Submenu<Wry> {
 
 pub fn build_menus(app: &App<Wry>) -> Menu<Wry> {
     let file_menu = build_file_menu(app);
+    let edit_menu = build_edit_menu(app);
     let project_menu = build_project_menu(app);
     let preferences_menu = build_preferences_menu(app);
     MenuBuilder::new(app)
-        .items(&[&file_menu, &project_menu, &preferences_menu])
+        .items(&[&file_menu, &edit_menu, &project_menu, &preferences_menu])
         .build()
         .unwrap()
 }
@@ -128,6 +174,11 @@ pub async fn handle_menu_events(app_handle: &AppHandle, event: &MenuEvent) {
 
     match id {
         "file-open-file" => project::file::open_files(app_handle).await,
+        "file-export-mixdown" => {
+            project::commands::export_mixdown(app_handle.clone(), None, None, None)
+        }
+        "edit-undo" => project::commands::undo(app_handle.clone()),
+        "edit-redo" => project::commands::redo(app_handle.clone()),
         "preferences-settings" => pages::settings_page::open_settings(app_handle),
         "project-add-track" => {
             menus::commands::add_empty_track(app_handle.clone()).unwrap();
@@ -136,7 +187,16 @@ pub async fn handle_menu_events(app_handle: &AppHandle, event: &MenuEvent) {
                 .expect("Failed to get main window");
             window.emit("updated-track-list", ()).unwrap();
         }
-        "project-save-project" => project::commands::save_project(app_handle.clone()),
+        "project-add-bus" => {
+            if menus::commands::add_bus_track(app_handle.clone()).is_ok() {
+                let _ = app_handle.emit("updated-track-list", ());
+            }
+        }
+        "project-save-project" => project::commands::save_project(app_handle.clone(), None),
+        "project-save-project-binary" => project::commands::save_project(
+            app_handle.clone(),
+            Some(project::format::ProjectFormat::Binary),
+        ),
         "project-open-project" => project::commands::load_project(app_handle.clone()),
         _ if id.starts_with("preferences-output-device-") => {
             update_master_io_device_index(audio_context.output_device_index.clone(), id);
@@ -151,6 +211,47 @@ pub async fn handle_menu_events(app_handle: &AppHandle, event: &MenuEvent) {
     }
 }
 
+// Selects the devices a project was saved with, devices that aren't connected
+// anymore leave the current selection alone.
+pub fn select_devices(app_handle: &AppHandle, input: Option<&str>, output: Option<&str>) {
+    let audio_context = app_handle.state::<project::states::StateAudioContext>();
+
+    if let Some(name) = input {
+        match audio_context
+            .input_device_registry
+            .list()
+            .iter()
+            .position(|device| device == name)
+        {
+            Some(index) => {
+                let id = format!("preferences-input-device-{}", index);
+                update_master_io_device_index(audio_context.input_device_index.clone(), &id);
+                update_radio_group_menu(app_handle, &id);
+            }
+            None => eprintln!("Input device {} not found", name),
+        }
+    }
+
+    if let Some(name) = output {
+        match audio_context
+            .output_device_registry
+            .list()
+            .iter()
+            .position(|device| device == name)
+        {
+            Some(index) => {
+                if index != audio_context.output_device_index.load(Ordering::SeqCst) {
+                    let id = format!("preferences-output-device-{}", index);
+                    update_master_io_device_index(audio_context.output_device_index.clone(), &id);
+                    update_radio_group_menu(app_handle, &id);
+                    update_master_output_device_track(app_handle);
+                }
+            }
+            None => eprintln!("Output device {} not found", name),
+        }
+    }
+}
+
 fn update_master_io_device_index(device_index: Arc<AtomicUsize>, id: &str) {
     let parts = id.split("-").collect::<Vec<_>>();
     let index = parts
@@ -163,19 +264,34 @@ fn update_master_io_device_index(device_index: Arc<AtomicUsize>, id: &str) {
 
 fn update_master_output_device_track(app: &AppHandle) {
     let state_mixer_guard = app.state::<project::states::StateMixerGuard>();
-    let state_mixer = state_mixer_guard.0.lock().unwrap();
-    let out = state_mixer.master_out.clone();
-    let mut master_output = out.lock().unwrap();
+    let mut state_mixer = state_mixer_guard.0.lock().unwrap();
     let audio_context = app.state::<project::states::StateAudioContext>();
     let new_master_output_device = audio_context
         .output_device()
         .expect("Failed to get new master output device");
+    // The timeline is in frames, it's saved at the old rate before the new
+    // device sets its own.
+    let sample_rate = state_mixer.transport.sample_rate();
+    let raw = project::states::StateMixerRaw::new(&state_mixer, &audio_context);
+    let (engine, audio_engine) = track::engine::channel();
     let new_output_source = track::sources::sink::StreamSink::new(
         new_master_output_device,
-        state_mixer.track_list.clone(),
-        out.clone(),
+        audio_engine,
+        state_mixer.transport.clone(),
     );
-    master_output.sink = Box::new(new_output_source);
+    if let Ok(mut master_output) = state_mixer.master_out.lock() {
+        master_output.sink.stop_stream();
+        master_output.sink = Box::new(new_output_source);
+        master_output.sink.start_stream();
+    }
+    state_mixer.engine = engine;
+    if state_mixer.transport.sample_rate() == sample_rate {
+        state_mixer.prepare_processors();
+        state_mixer.sync_engine();
+    } else {
+        state_mixer.follow_sample_rate(raw, app);
+        let _ = app.emit("updated-track-list", ());
+    }
 }
 
 fn update_radio_group_menu(app: &AppHandle, id: &str) {
@@ -1,16 +1,12 @@
-use std::{
-    fs::File,
-    io::{Read, Write},
-};
+use std::{fs::create_dir_all, mem, thread};
 
-use bincode::config::Configuration;
 use tauri::{AppHandle, Emitter, Manager};
 use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 
-use crate::project;
+use crate::{menus, project, track};
 
 #[tauri::command]
-pub fn save_project(app_handle: AppHandle) {
+pub fn save_project(app_handle: AppHandle, format: Option<project::format::ProjectFormat>) {
     app_handle
         .dialog()
         .file()
@@ -18,39 +14,22 @@ pub fn save_project(app_handle: AppHandle) {
         .pick_folder(move |folder_path| {
             if let Some(folder_path) = folder_path {
                 if let Some(path) = folder_path.as_path() {
-                    let config = bincode::config::standard();
-                    let mixer_state_path = path.join("mixer_state.mix");
                     let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+                    let audio_context = app_handle.state::<project::states::StateAudioContext>();
 
-                    if let Ok(mixer_guard) = state_mixer_guard.0.lock() {
-                        let raw_state_mixer = project::states::StateMixerRaw::from(&*mixer_guard);
-                        if let Ok(encoded_mixer) = bincode::encode_to_vec(&raw_state_mixer, config)
-                        {
-                            if let Ok(mut file) = File::create(&mixer_state_path) {
-                                if let Ok(_) = file.write_all(&encoded_mixer) {
-                                } else {
-                                    app_handle
-                                        .dialog()
-                                        .message("Failed to write to file")
-                                        .title("File Error")
-                                        .kind(MessageDialogKind::Warning)
-                                        .buttons(MessageDialogButtons::Ok)
-                                        .blocking_show();
-                                }
-                            } else {
-                                app_handle
-                                    .dialog()
-                                    .message("Failed to create file")
-                                    .title("File Error")
-                                    .kind(MessageDialogKind::Warning)
-                                    .buttons(MessageDialogButtons::Ok)
-                                    .blocking_show();
-                            }
-                        } else {
+                    if let Ok(mut mixer_guard) = state_mixer_guard.0.lock() {
+                        mixer_guard.project_path = Some(path.to_path_buf());
+                        let raw_state_mixer =
+                            project::states::StateMixerRaw::new(&mixer_guard, &audio_context);
+                        if let Err(message) = project::format::write_project(
+                            path,
+                            raw_state_mixer,
+                            format.unwrap_or(project::format::ProjectFormat::Text),
+                        ) {
                             app_handle
                                 .dialog()
-                                .message("Failed to encode state mixer")
-                                .title("Encode Error")
+                                .message(message)
+                                .title("File Error")
                                 .kind(MessageDialogKind::Warning)
                                 .buttons(MessageDialogButtons::Ok)
                                 .blocking_show();
@@ -94,57 +73,37 @@ pub fn load_project(app_handle: AppHandle) {
         .pick_folder(move |folder_path| {
             if let Some(folder_path) = folder_path {
                 if let Some(path) = folder_path.as_path() {
-                    let config = bincode::config::standard();
-                    let mixer_state_path = path.join("mixer_state.mix");
-
-                    if let Ok(mut mixer_state_file) = File::open(&mixer_state_path) {
-                        let mut mixer_state_buffer = Vec::new();
-                        if let Ok(_) = mixer_state_file.read_to_end(&mut mixer_state_buffer) {
-                            if let Ok((decoded_mixer, _)) =
-                                bincode::decode_from_slice::<
-                                    project::states::StateMixerRaw,
-                                    Configuration,
-                                >(&mixer_state_buffer, config)
-                            {
-                                let new_state_mixer =
-                                    project::states::StateMixer::from(decoded_mixer);
-                                let state_mixer =
-                                    app_handle.state::<project::states::StateMixerGuard>();
-
-                                if let Ok(mut guard) = state_mixer.0.lock() {
-                                    *guard = new_state_mixer;
-
-                                    let window = app_handle
-                                        .get_webview_window("main")
-                                        .expect("Failed to get main window");
-                                    window.emit("updated-track-list", ()).unwrap();
-                                };
-                            } else {
-                                app_handle
-                                    .dialog()
-                                    .message("Failed to decode state mixer")
-                                    .title("Decode Error")
-                                    .kind(MessageDialogKind::Warning)
-                                    .buttons(MessageDialogButtons::Ok)
-                                    .blocking_show();
-                            }
-                        } else {
+                    match project::format::read_project(path) {
+                        Ok(decoded_mixer) => {
+                            // Devices first, switching the output needs the state
+                            // mixer lock.
+                            menus::menu_builders::select_devices(
+                                &app_handle,
+                                decoded_mixer.input_device.as_deref(),
+                                decoded_mixer.output_device.as_deref(),
+                            );
+                            let state_mixer =
+                                app_handle.state::<project::states::StateMixerGuard>();
+
+                            if let Ok(mut guard) = state_mixer.0.lock() {
+                                guard.restore(decoded_mixer, &app_handle);
+                                guard.project_path = Some(path.to_path_buf());
+
+                                let window = app_handle
+                                    .get_webview_window("main")
+                                    .expect("Failed to get main window");
+                                window.emit("updated-track-list", ()).unwrap();
+                            };
+                        }
+                        Err(message) => {
                             app_handle
                                 .dialog()
-                                .message("Failed to read file")
-                                .title("File Error")
+                                .message(message)
+                                .title("Decode Error")
                                 .kind(MessageDialogKind::Warning)
                                 .buttons(MessageDialogButtons::Ok)
                                 .blocking_show();
                         }
-                    } else {
-                        app_handle
-                            .dialog()
-                            .message("Failed to open file")
-                            .title("File Error")
-                            .kind(MessageDialogKind::Warning)
-                            .buttons(MessageDialogButtons::Ok)
-                            .blocking_show();
                     }
                 } else {
                     app_handle
@@ -167,24 +126,457 @@ pub fn load_project(app_handle: AppHandle) {
         });
 }
 
+// Renders on a worker thread once the mix is copied, `mixdown-progress`
+// reports how far it got and when it's done.
 #[tauri::command]
-pub fn start_stream(app_handle: AppHandle) {
+pub fn export_mixdown(
+    app_handle: AppHandle,
+    start: Option<u64>,
+    end: Option<u64>,
+    tail: Option<u64>,
+) {
     app_handle
         .dialog()
-        .message("Feature not implemented yet")
-        .title("Error")
-        .kind(MessageDialogKind::Warning)
-        .buttons(MessageDialogButtons::Ok)
-        .blocking_show();
+        .file()
+        .set_title("Export mixdown")
+        .add_filter("WAV files", &["wav"])
+        .set_file_name("mixdown.wav")
+        .save_file(move |file_path| {
+            if let Some(path) = file_path.and_then(|p| p.into_path().ok()) {
+                let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+                let mixdown = if let Ok(state_mixer) = state_mixer_guard.0.lock() {
+                    project::mixdown::Mixdown::new(&state_mixer, start.unwrap_or(0), end, tail)
+                } else {
+                    Err("Failed to lock state mixer".to_string())
+                };
+
+                match mixdown {
+                    Ok(mixdown) => {
+                        thread::spawn(move || {
+                            let emit = |progress, finished, error| {
+                                let _ = app_handle.emit(
+                                    "mixdown-progress",
+                                    project::mixdown::MixdownProgress {
+                                        path: path.to_string_lossy().to_string(),
+                                        progress,
+                                        finished,
+                                        error,
+                                    },
+                                );
+                            };
+                            let result =
+                                mixdown.render(&path, |progress| emit(progress, false, None));
+                            emit(1.0, true, result.clone().err());
+                            if let Err(message) = result {
+                                app_handle
+                                    .dialog()
+                                    .message(message)
+                                    .title("Export Error")
+                                    .kind(MessageDialogKind::Warning)
+                                    .buttons(MessageDialogButtons::Ok)
+                                    .blocking_show();
+                            }
+                        });
+                    }
+                    Err(message) => {
+                        app_handle
+                            .dialog()
+                            .message(message)
+                            .title("Export Error")
+                            .kind(MessageDialogKind::Warning)
+                            .buttons(MessageDialogButtons::Ok)
+                            .blocking_show();
+                    }
+                }
+            } else {
+                app_handle
+                    .dialog()
+                    .message("No destination selected")
+                    .title("Destination")
+                    .kind(MessageDialogKind::Info)
+                    .buttons(MessageDialogButtons::Ok)
+                    .blocking_show();
+            }
+        });
+}
+
+#[tauri::command]
+pub fn start_stream(app_handle: AppHandle) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let recorded = state_mixer.finish_recording();
+        state_mixer.play();
+        if recorded {
+            let _ = app_handle.emit("updated-track-list", ());
+        }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    };
 }
 
 #[tauri::command]
 pub fn stop_stream(app_handle: AppHandle) {
-    app_handle
-        .dialog()
-        .message("Feature not implemented yet")
-        .title("Error")
-        .kind(MessageDialogKind::Warning)
-        .buttons(MessageDialogButtons::Ok)
-        .blocking_show();
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let recorded = state_mixer.finish_recording();
+        let automated = state_mixer.finish_automation();
+        state_mixer.stop();
+        if recorded || automated {
+            let _ = app_handle.emit("updated-track-list", ());
+        }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    };
+}
+
+#[tauri::command]
+pub fn record_stream(app_handle: AppHandle) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let folder = match &state_mixer.project_path {
+            Some(path) => Ok(path.join("recordings")),
+            None => app_handle
+                .path()
+                .app_data_dir()
+                .map(|path| path.join("recordings")),
+        };
+
+        let result = match folder {
+            Ok(folder) => create_dir_all(&folder)
+                .map_err(|e| format!("Failed to create {}: {}", folder.display(), e))
+                .and_then(|_| state_mixer.record(&folder, &app_handle)),
+            Err(e) => Err(format!("Failed to find a recordings folder: {}", e)),
+        };
+
+        if let Err(message) = result {
+            state_mixer.finish_recording();
+            app_handle
+                .dialog()
+                .message(message)
+                .title("Record Error")
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::Ok)
+                .blocking_show();
+        }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    };
+}
+
+#[tauri::command]
+pub fn pause_stream(app_handle: AppHandle) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let recorded = state_mixer.finish_recording();
+        let automated = state_mixer.finish_automation();
+        state_mixer.pause();
+        if recorded || automated {
+            let _ = app_handle.emit("updated-track-list", ());
+        }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    };
+}
+
+#[tauri::command]
+pub fn return_to_zero(app_handle: AppHandle) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        if state_mixer.return_to_zero() {
+            let _ = app_handle.emit("updated-track-list", ());
+        }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    };
+}
+
+#[tauri::command]
+pub fn locate_transport(app_handle: AppHandle, position: u64) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        if state_mixer.locate(position) {
+            let _ = app_handle.emit("updated-track-list", ());
+        }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    };
+}
+
+#[tauri::command]
+pub fn get_transport_info(app_handle: AppHandle) -> Result<project::transport::TransportInfo, ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(state_mixer) = state_mixer_guard.0.lock() {
+        Ok(state_mixer.transport.info())
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+        Err(())
+    };
+    result
+}
+
+#[tauri::command]
+pub fn set_loop_range(
+    app_handle: AppHandle,
+    range: project::transport::TimeRange,
+    enabled: bool,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.transport.set_loop_range(range, enabled)
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+
+    result.map_err(|message| {
+        app_handle
+            .dialog()
+            .message(message)
+            .title("Transport Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    })
+}
+
+#[tauri::command]
+pub fn set_punch_range(
+    app_handle: AppHandle,
+    range: project::transport::TimeRange,
+    enabled: bool,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.transport.set_punch_range(range, enabled)
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+
+    result.map_err(|message| {
+        app_handle
+            .dialog()
+            .message(message)
+            .title("Transport Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    })
+}
+
+#[tauri::command]
+pub fn undo(app_handle: AppHandle) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        if let Some(change) = state_mixer.undo() {
+            let _ = app_handle.emit("history-changed", change);
+            let _ = app_handle.emit("updated-track-list", ());
+        }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    };
+}
+
+#[tauri::command]
+pub fn redo(app_handle: AppHandle) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        if let Some(change) = state_mixer.redo() {
+            let _ = app_handle.emit("history-changed", change);
+            let _ = app_handle.emit("updated-track-list", ());
+        }
+    } else {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    };
+}
+
+#[tauri::command]
+pub fn set_tempo_map(app_handle: AppHandle, tempo_map: project::tempo::TempoMap) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        tempo_map.validate().and_then(|after| {
+            let before = state_mixer
+                .track_list
+                .lock()
+                .map(|mut list| mem::replace(&mut list.tempo_map, after.clone()))
+                .map_err(|_| "Failed to lock track list".to_string())?;
+            state_mixer.record_edit(project::history::Edit::TempoMap { before, after });
+            Ok(())
+        })
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+
+    match result {
+        Ok(()) => {
+            let _ = app_handle.emit("updated-track-list", ());
+            Ok(())
+        }
+        Err(message) => {
+            app_handle
+                .dialog()
+                .message(message)
+                .title("Tempo Error")
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::Ok)
+                .blocking_show();
+            Err(())
+        }
+    }
+}
+
+// Metronome settings aren't part of the undo history.
+#[tauri::command]
+pub fn set_metronome(
+    app_handle: AppHandle,
+    settings: track::metronome::MetronomeSettings,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer
+            .track_list
+            .lock()
+            .map_err(|_| "Failed to lock track list".to_string())
+            .and_then(|mut list| list.metronome.set_settings(settings))
+            .map(|()| state_mixer.sync_engine())
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+
+    match result {
+        Ok(()) => {
+            let _ = app_handle.emit("updated-track-list", ());
+            Ok(())
+        }
+        Err(message) => {
+            app_handle
+                .dialog()
+                .message(message)
+                .title("Metronome Error")
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::Ok)
+                .blocking_show();
+            Err(())
+        }
+    }
+}
+
+#[tauri::command]
+pub fn samples_to_bar_beat_tick(
+    app_handle: AppHandle,
+    position: u64,
+) -> Result<project::tempo::BarBeatTick, ()> {
+    with_tempo_map(&app_handle, |tempo_map, sample_rate| {
+        tempo_map.bar_beat_tick(tempo_map.tick_at_sample(position, sample_rate))
+    })
+}
+
+#[tauri::command]
+pub fn bar_beat_tick_to_samples(
+    app_handle: AppHandle,
+    position: project::tempo::BarBeatTick,
+) -> Result<u64, ()> {
+    with_tempo_map(&app_handle, |tempo_map, sample_rate| {
+        tempo_map.sample_at_tick(
+            tempo_map.tick_at_bar_beat_tick(position) as f64,
+            sample_rate,
+        )
+    })
+}
+
+#[tauri::command]
+pub fn get_bar_lines(
+    app_handle: AppHandle,
+    start: u64,
+    end: u64,
+) -> Result<Vec<project::tempo::BarLine>, ()> {
+    with_tempo_map(&app_handle, |tempo_map, sample_rate| {
+        tempo_map.bar_lines(start, end, sample_rate)
+    })
+}
+
+#[tauri::command]
+pub fn snap_to_grid(
+    app_handle: AppHandle,
+    position: u64,
+    division: project::tempo::GridDivision,
+) -> Result<u64, ()> {
+    with_tempo_map(&app_handle, |tempo_map, sample_rate| {
+        tempo_map.snap(position, sample_rate, division)
+    })
+}
+
+// Runs `f` with the tempo map and the engine sample rate, positions are in
+// frames at that rate like the transport's.
+fn with_tempo_map<T>(
+    app_handle: &AppHandle,
+    f: impl FnOnce(&project::tempo::TempoMap, u32) -> T,
+) -> Result<T, ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = state_mixer_guard.0.lock().ok().and_then(|state_mixer| {
+        let sample_rate = state_mixer.transport.sample_rate();
+        let track_list = state_mixer.track_list.lock().ok()?;
+        Some(f(&track_list.tempo_map, sample_rate))
+    });
+    result.ok_or_else(|| {
+        app_handle
+            .dialog()
+            .message("Failed to lock state mixer")
+            .title("State Error")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::Ok)
+            .blocking_show();
+    })
 }
@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use tauri::{AppHandle, Emitter, Manager};
 use tauri_plugin_dialog::DialogExt;
@@ -48,12 +51,27 @@ fn add_file_track(
         .file_name()
         .map(|f| f.to_string_lossy().to_string())
         .expect("Failed to get file name");
-    let state_mixer = state_mixer_guard.0.lock().unwrap();
-    let track_list = state_mixer.track_list.clone();
-    let mut list = track_list.lock().expect("Failed to lock track list");
-    let track_source = track::sources::source::FileSource::new(path);
-    let track = track::tracks::InputTrack::new(name, Box::new(track_source));
-    list.add_track(name, track);
+    let mut state_mixer = state_mixer_guard.0.lock().unwrap();
+    let sample_rate = state_mixer.transport.sample_rate();
+    let track_source = match track::sources::source::FileSource::new(path, sample_rate) {
+        Ok(source) => Arc::new(source),
+        Err(e) => {
+            eprintln!("Failed to open file source: {}", e);
+            return;
+        }
+    };
+    let mut track = track::tracks::InputTrack::new(name, None);
+    track.add_clip(track::clips::Clip::new(name, track_source, 0));
+    let track = Arc::new(Mutex::new(track));
+    state_mixer
+        .track_list
+        .lock()
+        .expect("Failed to lock track list")
+        .add_arc_mut_track(name, track.clone());
+    state_mixer.record_edit(project::history::Edit::AddTrack {
+        name: name.clone(),
+        track,
+    });
 
     let window = app_handle
         .get_webview_window("main")
@@ -0,0 +1,413 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+use bincode::config::Configuration;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{project, track};
+
+pub const PROJECT_VERSION: u32 = 1;
+pub const TEXT_FILE_NAME: &str = "project.draw";
+pub const BINARY_FILE_NAME: &str = "mixer_state.mix";
+// Binary projects start with this, then the version and the mixer as a
+// `BinaryValue`.
+const BINARY_MAGIC: &[u8; 4] = b"DRWB";
+
+// Each entry turns a project of version `index + 1` into the next version.
+const MIGRATIONS: [fn(Value) -> Result<Value, String>; PROJECT_VERSION as usize - 1] = [];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProjectFormat {
+    Text,
+    Binary,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProjectFile {
+    version: u32,
+    mixer: project::states::StateMixerRaw,
+}
+
+// A JSON value in a form bincode can store, so binary projects go through the
+// same migrations as text ones. Bincode can't decode self-describing data.
+#[derive(Debug, PartialEq, bincode::Encode, bincode::Decode)]
+enum BinaryValue {
+    Null,
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<BinaryValue>),
+    Object(Vec<(String, BinaryValue)>),
+}
+
+impl From<Value> for BinaryValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => BinaryValue::Null,
+            Value::Bool(value) => BinaryValue::Bool(value),
+            Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+                (Some(value), _) => BinaryValue::Unsigned(value),
+                (None, Some(value)) => BinaryValue::Signed(value),
+                _ => BinaryValue::Float(number.as_f64().unwrap_or(0.0)),
+            },
+            Value::String(value) => BinaryValue::String(value),
+            Value::Array(values) => {
+                BinaryValue::Array(values.into_iter().map(BinaryValue::from).collect())
+            }
+            Value::Object(map) => BinaryValue::Object(
+                map.into_iter()
+                    .map(|(key, value)| (key, BinaryValue::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<BinaryValue> for Value {
+    fn from(value: BinaryValue) -> Self {
+        match value {
+            BinaryValue::Null => Value::Null,
+            BinaryValue::Bool(value) => json!(value),
+            BinaryValue::Unsigned(value) => json!(value),
+            BinaryValue::Signed(value) => json!(value),
+            BinaryValue::Float(value) => json!(value),
+            BinaryValue::String(value) => Value::String(value),
+            BinaryValue::Array(values) => {
+                Value::Array(values.into_iter().map(Value::from).collect())
+            }
+            BinaryValue::Object(entries) => Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// The unversioned bincode layout projects were saved in before the format had a
+// version, it is converted straight to the current one.
+#[derive(bincode::Decode)]
+struct LegacyStateMixerRaw {
+    track_list: BTreeMap<String, LegacyInputTrackRaw>,
+}
+
+#[derive(bincode::Decode)]
+struct LegacyInputTrackRaw {
+    name: String,
+    gain: f32,
+    pan: f32,
+    source_type: track::sources::source::AudioSourceRaw,
+}
+
+pub fn write_project(
+    folder: &Path,
+    mixer: project::states::StateMixerRaw,
+    format: ProjectFormat,
+) -> Result<(), String> {
+    let project_file = ProjectFile {
+        version: PROJECT_VERSION,
+        mixer,
+    };
+
+    let (path, contents) = match format {
+        ProjectFormat::Text => (
+            folder.join(TEXT_FILE_NAME),
+            serde_json::to_vec_pretty(&project_file)
+                .map_err(|e| format!("Failed to encode project: {}", e))?,
+        ),
+        ProjectFormat::Binary => (folder.join(BINARY_FILE_NAME), encode_binary(&project_file)?),
+    };
+    fs::write(&path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// Prefers the text project when a folder has both.
+pub fn read_project(folder: &Path) -> Result<project::states::StateMixerRaw, String> {
+    let text_path = folder.join(TEXT_FILE_NAME);
+    let binary_path = folder.join(BINARY_FILE_NAME);
+
+    if text_path.exists() {
+        let contents = read_file(&text_path)?;
+        let value = serde_json::from_slice::<Value>(&contents)
+            .map_err(|e| format!("Failed to parse project: {}", e))?;
+        from_value(value)
+    } else if binary_path.exists() {
+        let contents = read_file(&binary_path)?;
+        read_binary(&contents)
+    } else {
+        Err(format!("No project found in {}", folder.display()))
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    let mut contents = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut contents))
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(contents)
+}
+
+fn encode_binary(project_file: &ProjectFile) -> Result<Vec<u8>, String> {
+    let mixer = serde_json::to_value(&project_file.mixer)
+        .map_err(|e| format!("Failed to encode project: {}", e))?;
+    let payload = bincode::encode_to_vec(
+        (project_file.version, BinaryValue::from(mixer)),
+        bincode::config::standard(),
+    )
+    .map_err(|e| format!("Failed to encode project: {}", e))?;
+    Ok([BINARY_MAGIC.as_slice(), &payload].concat())
+}
+
+// The version is read ahead of the mixer, which is then migrated like a text
+// project. Files without the header are the unversioned legacy layout.
+fn read_binary(contents: &[u8]) -> Result<project::states::StateMixerRaw, String> {
+    let config = bincode::config::standard();
+    let Some(payload) = contents.strip_prefix(BINARY_MAGIC) else {
+        return read_legacy_binary(contents);
+    };
+
+    let (version, read) = bincode::decode_from_slice::<u32, Configuration>(payload, config)
+        .map_err(|e| format!("Failed to decode project version: {}", e))?;
+    if version == 0 || version > PROJECT_VERSION {
+        return Err(format!("Unsupported binary project version {}", version));
+    }
+    let (mixer, _) =
+        bincode::decode_from_slice::<BinaryValue, Configuration>(&payload[read..], config)
+            .map_err(|e| format!("Failed to decode project: {}", e))?;
+    from_value(json!({ "version": version, "mixer": Value::from(mixer) }))
+}
+
+fn read_legacy_binary(contents: &[u8]) -> Result<project::states::StateMixerRaw, String> {
+    let config = bincode::config::standard();
+    let (legacy, read) =
+        bincode::decode_from_slice::<LegacyStateMixerRaw, Configuration>(contents, config)
+            .map_err(|e| format!("Failed to decode project: {}", e))?;
+    if read != contents.len() {
+        return Err("Failed to decode project: unexpected data after the mixer".to_string());
+    }
+    from_value(json!({ "version": PROJECT_VERSION, "mixer": from_legacy(legacy)? }))
+}
+
+fn from_value(mut value: Value) -> Result<project::states::StateMixerRaw, String> {
+    let mut version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or("Project has no version")? as u32;
+    if version == 0 || version > PROJECT_VERSION {
+        return Err(format!(
+            "Project version {} is not supported, this build reads up to version {}",
+            version, PROJECT_VERSION
+        ));
+    }
+
+    while version < PROJECT_VERSION {
+        value = MIGRATIONS[version as usize - 1](value)?;
+        version += 1;
+        value["version"] = json!(version);
+    }
+
+    serde_json::from_value::<ProjectFile>(value)
+        .map(|project_file| project_file.mixer)
+        .map_err(|e| format!("Failed to read project: {}", e))
+}
+
+// Legacy tracks streamed one source each from the start of the timeline. File
+// sources become a clip at zero, the clip is cut to the file on load.
+// Everything added since starts out empty or at its default.
+fn from_legacy(legacy: LegacyStateMixerRaw) -> Result<Value, String> {
+    let track_list = legacy
+        .track_list
+        .into_values()
+        .map(|track| {
+            let (source_type, clips) = match track.source_type {
+                track::sources::source::AudioSourceRaw::File(path) => (
+                    None,
+                    vec![track::clips::ClipRaw {
+                        name: track.name.clone(),
+                        source: track::sources::source::AudioSourceRaw::File(path),
+                        start: 0,
+                        offset: 0,
+                        length: u64::MAX,
+                        gain: 1.0,
+                        reversed: false,
+                        fade_in: track::clips::Fade::none(),
+                        fade_out: track::clips::Fade::none(),
+                        crossfade: None,
+                    }],
+                ),
+                source => (Some(source), Vec::new()),
+            };
+            track::raw::InputTrackRaw {
+                name: track.name,
+                kind: track::tracks::TrackKind::Audio,
+                gain: track.gain,
+                pan: track.pan,
+                mute: false,
+                record: false,
+                monitor: false,
+                solo: false,
+                solo_safe: false,
+                source_type,
+                clips,
+                take_folders: Vec::new(),
+                inserts: Vec::new(),
+                sends: Vec::new(),
+                output: None,
+                automation_mode: track::automation::AutomationMode::Read,
+                automation: Vec::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let master_out = track::raw::OutputTrackRaw {
+        gain: 1.0,
+        pan: 0.0,
+        inserts: Vec::new(),
+        limiter: track::processors::chain::ProcessorRaw {
+            kind: track::processors::processor::ProcessorKind::Limiter,
+            bypass: false,
+            parameters: Vec::new(),
+            file: None,
+            key: None,
+        },
+    };
+
+    // Legacy positions are all 0 or open ended, they read the same at any rate.
+    Ok(json!({
+        "sample_rate": 48_000,
+        "track_list": serde_json::to_value(track_list)
+            .map_err(|e| format!("Failed to convert project: {}", e))?,
+        "master_out": serde_json::to_value(master_out)
+            .map_err(|e| format!("Failed to convert project: {}", e))?,
+        "pan_law": track::mixer::PanLaw::ConstantPower,
+        "solo_mode": track::mixer::SoloMode::Additive,
+        "groups": [],
+        "tempo_map": project::tempo::TempoMap::new(),
+        "metronome": track::metronome::MetronomeSettings::new(),
+        "input_device": null,
+        "output_device": null,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The legacy layout kept tracks by name, each playing one source from the
+    // start. Sources are encoded as their variant index and path.
+    fn legacy_project() -> Vec<u8> {
+        let track_list = BTreeMap::from([
+            (
+                "Kick".to_string(),
+                (
+                    "Kick".to_string(),
+                    0.5f32,
+                    -0.25f32,
+                    (0u32, "kick.wav".to_string()),
+                ),
+            ),
+            (
+                "Mic".to_string(),
+                (
+                    "Mic".to_string(),
+                    1.0f32,
+                    0.0f32,
+                    (1u32, "USB Microphone".to_string()),
+                ),
+            ),
+        ]);
+        bincode::encode_to_vec(track_list, bincode::config::standard()).unwrap()
+    }
+
+    fn mixer_value(mixer: &project::states::StateMixerRaw) -> Value {
+        serde_json::to_value(mixer).unwrap()
+    }
+
+    fn binary(version: u32, mixer: Value) -> Vec<u8> {
+        let payload = bincode::encode_to_vec(
+            (version, BinaryValue::from(mixer)),
+            bincode::config::standard(),
+        )
+        .unwrap();
+        [BINARY_MAGIC.as_slice(), &payload].concat()
+    }
+
+    #[test]
+    fn legacy_project_converts_to_current() {
+        let mixer = mixer_value(&read_binary(&legacy_project()).unwrap());
+        let tracks = mixer["track_list"].as_array().unwrap();
+        assert_eq!(tracks.len(), 2);
+
+        let kick = tracks.iter().find(|track| track["name"] == "Kick").unwrap();
+        assert_eq!(kick["gain"], 0.5);
+        assert_eq!(kick["pan"], -0.25);
+        assert_eq!(kick["source_type"], Value::Null);
+        assert_eq!(kick["clips"][0]["source"], json!({ "File": "kick.wav" }));
+        assert_eq!(kick["clips"][0]["start"], 0);
+        assert_eq!(kick["clips"][0]["length"], u64::MAX);
+        assert_eq!(kick["inserts"], json!([]));
+        assert_eq!(kick["automation"], json!([]));
+        assert_eq!(kick["take_folders"], json!([]));
+
+        let mic = tracks.iter().find(|track| track["name"] == "Mic").unwrap();
+        assert_eq!(mic["source_type"], json!({ "Stream": "USB Microphone" }));
+        assert_eq!(mic["clips"], json!([]));
+
+        assert_eq!(mixer["groups"], json!([]));
+        assert_eq!(mixer["master_out"]["limiter"]["kind"], "Limiter");
+        assert_eq!(mixer["tempo_map"], json!(project::tempo::TempoMap::new()));
+    }
+
+    #[test]
+    fn truncated_legacy_project_is_an_error() {
+        let contents = legacy_project();
+        let error = read_binary(&contents[..contents.len() - 3]).err().unwrap();
+        assert!(error.starts_with("Failed to decode project"));
+
+        let error = read_binary(&[contents.as_slice(), &[0]].concat())
+            .err()
+            .unwrap();
+        assert!(error.starts_with("Failed to decode project"));
+    }
+
+    #[test]
+    fn text_project_from_a_newer_build_is_rejected() {
+        let mixer = mixer_value(&read_binary(&legacy_project()).unwrap());
+        assert!(from_value(json!({ "version": PROJECT_VERSION, "mixer": mixer })).is_ok());
+        assert!(from_value(json!({ "version": PROJECT_VERSION + 1, "mixer": mixer })).is_err());
+    }
+
+    #[test]
+    fn binary_project_round_trips() {
+        let mixer = read_binary(&legacy_project()).unwrap();
+        let expected = mixer_value(&mixer);
+        let contents = encode_binary(&ProjectFile {
+            version: PROJECT_VERSION,
+            mixer,
+        })
+        .unwrap();
+
+        assert!(contents.starts_with(BINARY_MAGIC));
+        assert_eq!(mixer_value(&read_binary(&contents).unwrap()), expected);
+    }
+
+    #[test]
+    fn unsupported_binary_version_is_reported() {
+        let contents = binary(PROJECT_VERSION + 1, json!({}));
+        let error = read_binary(&contents).err().unwrap();
+        assert_eq!(
+            error,
+            format!("Unsupported binary project version {}", PROJECT_VERSION + 1)
+        );
+
+        let error = read_binary(&binary(0, json!({}))).err().unwrap();
+        assert_eq!(error, "Unsupported binary project version 0");
+    }
+}
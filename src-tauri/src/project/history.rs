@@ -0,0 +1,678 @@
+use std::{
+    mem,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{project, track};
+
+const HISTORY_LIMIT: usize = 500;
+// Fader moves closer together than this are undone as one drag.
+const COALESCE_WINDOW: Duration = Duration::from_millis(500);
+
+// Every undoable change to the mixer. Tracks are referred to by name, which is
+// always valid because edits are undone and redone in order.
+pub enum Edit {
+    UpdateTrack {
+        name: String,
+        update: track::track_list::TrackUpdate,
+        undo: Vec<(String, track::track_list::TrackUpdate)>,
+    },
+    AddTrack {
+        name: String,
+        track: Arc<Mutex<track::tracks::InputTrack>>,
+    },
+    AddClip {
+        track: String,
+        clip: track::clips::Clip,
+    },
+    RemoveClip {
+        track: String,
+        clip: track::clips::Clip,
+    },
+    MoveClip {
+        track: String,
+        clip_id: u64,
+        from: u64,
+        to: u64,
+    },
+    // Clip edits that change more than where one clip sits.
+    Clips {
+        track: String,
+        before: Vec<track::clips::Clip>,
+        after: Vec<track::clips::Clip>,
+    },
+    TakeFolders {
+        track: String,
+        before: Vec<track::takes::TakeFolder>,
+        after: Vec<track::takes::TakeFolder>,
+    },
+    Inserts {
+        track: String,
+        before: track::processors::chain::InsertChain,
+        after: track::processors::chain::InsertChain,
+    },
+    InsertParameter {
+        track: String,
+        insert_id: u64,
+        parameter: String,
+        before: f32,
+        after: f32,
+    },
+    Sends {
+        track: String,
+        before: Vec<track::sends::AuxSend>,
+        after: Vec<track::sends::AuxSend>,
+    },
+    Groups {
+        before: Vec<track::track_list::TrackGroup>,
+        after: Vec<track::track_list::TrackGroup>,
+    },
+    Automation {
+        track: String,
+        before: Vec<track::automation::AutomationLane>,
+        after: Vec<track::automation::AutomationLane>,
+    },
+    PanLaw {
+        before: track::mixer::PanLaw,
+        after: track::mixer::PanLaw,
+    },
+    SoloMode {
+        before: track::mixer::SoloMode,
+        after: track::mixer::SoloMode,
+    },
+    TempoMap {
+        before: project::tempo::TempoMap,
+        after: project::tempo::TempoMap,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub enum HistoryScope {
+    Track(String),
+    Clips(String),
+    Inserts(String),
+    TrackList,
+    Mixer,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryChange {
+    pub scope: HistoryScope,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+impl Edit {
+    pub fn scope(&self) -> HistoryScope {
+        match self {
+            Edit::UpdateTrack { name, update, .. } => match update {
+                track::track_list::TrackUpdate::Name(_)
+                | track::track_list::TrackUpdate::Solo(_) => HistoryScope::TrackList,
+                _ => HistoryScope::Track(name.clone()),
+            },
+            Edit::AddTrack { .. } => HistoryScope::TrackList,
+            Edit::AddClip { track, .. }
+            | Edit::RemoveClip { track, .. }
+            | Edit::MoveClip { track, .. }
+            | Edit::Clips { track, .. }
+            | Edit::TakeFolders { track, .. } => HistoryScope::Clips(track.clone()),
+            Edit::Inserts { track, .. } | Edit::InsertParameter { track, .. } => {
+                HistoryScope::Inserts(track.clone())
+            }
+            Edit::Sends { track, .. } | Edit::Automation { track, .. } => {
+                HistoryScope::Track(track.clone())
+            }
+            Edit::Groups { .. } => HistoryScope::TrackList,
+            Edit::PanLaw { .. } | Edit::SoloMode { .. } | Edit::TempoMap { .. } => {
+                HistoryScope::Mixer
+            }
+        }
+    }
+
+    pub fn undo(
+        &self,
+        track_list: &mut track::track_list::TrackList,
+        master_out: &mut track::tracks::OutputTrack,
+    ) {
+        match self {
+            Edit::UpdateTrack { undo, .. } => {
+                for (name, update) in undo.iter().rev() {
+                    apply_update(track_list, master_out, name, update.clone(), false);
+                }
+            }
+            Edit::AddTrack { name, .. } => {
+                if let Some(track) = track_list.remove_track(name) {
+                    if let Some(source) = track.lock().ok().as_ref().and_then(|t| t.source.as_ref())
+                    {
+                        source.stop_stream();
+                    }
+                }
+            }
+            Edit::AddClip { track, clip } => {
+                with_track(track_list, track, |t| {
+                    t.remove_clip(clip.id);
+                });
+            }
+            Edit::RemoveClip { track, clip } => {
+                with_track(track_list, track, |t| t.add_clip(clip.clone()));
+            }
+            Edit::MoveClip {
+                track,
+                clip_id,
+                from,
+                ..
+            } => {
+                with_track(track_list, track, |t| {
+                    t.move_clip(*clip_id, *from);
+                });
+            }
+            Edit::Clips { track, before, .. } => {
+                with_track(track_list, track, |t| t.clips = before.clone());
+            }
+            Edit::TakeFolders { track, before, .. } => {
+                with_track(track_list, track, |t| t.take_folders = before.clone());
+            }
+            Edit::Inserts { track, before, .. } => {
+                with_chain(track_list, master_out, track, |chain| {
+                    *chain = before.clone()
+                });
+            }
+            Edit::InsertParameter {
+                track,
+                insert_id,
+                parameter,
+                before,
+                ..
+            } => {
+                set_parameter(
+                    track_list, master_out, track, *insert_id, parameter, *before,
+                );
+            }
+            Edit::Sends { track, before, .. } => {
+                with_track(track_list, track, |t| t.sends = before.clone());
+            }
+            Edit::Groups { before, .. } => track_list.groups = before.clone(),
+            Edit::Automation { track, before, .. } => {
+                with_track(track_list, track, |t| t.automation.lanes = before.clone());
+            }
+            Edit::PanLaw { before, .. } => track_list.pan_law = *before,
+            Edit::SoloMode { before, .. } => track_list.solo_mode = *before,
+            Edit::TempoMap { before, .. } => track_list.tempo_map = before.clone(),
+        }
+    }
+
+    pub fn redo(
+        &self,
+        track_list: &mut track::track_list::TrackList,
+        master_out: &mut track::tracks::OutputTrack,
+    ) {
+        match self {
+            Edit::UpdateTrack { name, update, .. } => {
+                apply_update(track_list, master_out, name, update.clone(), true);
+            }
+            Edit::AddTrack { name, track } => {
+                if let Ok(t) = track.lock() {
+                    if let Some(source) = &t.source {
+                        if t.monitor || t.record {
+                            source.start_stream();
+                        }
+                    }
+                }
+                track_list.add_arc_mut_track(name, track.clone());
+            }
+            Edit::AddClip { track, clip } => {
+                with_track(track_list, track, |t| t.add_clip(clip.clone()));
+            }
+            Edit::RemoveClip { track, clip } => {
+                with_track(track_list, track, |t| {
+                    t.remove_clip(clip.id);
+                });
+            }
+            Edit::MoveClip {
+                track, clip_id, to, ..
+            } => {
+                with_track(track_list, track, |t| {
+                    t.move_clip(*clip_id, *to);
+                });
+            }
+            Edit::Clips { track, after, .. } => {
+                with_track(track_list, track, |t| t.clips = after.clone());
+            }
+            Edit::TakeFolders { track, after, .. } => {
+                with_track(track_list, track, |t| t.take_folders = after.clone());
+            }
+            Edit::Inserts { track, after, .. } => {
+                with_chain(track_list, master_out, track, |chain| {
+                    *chain = after.clone()
+                });
+            }
+            Edit::InsertParameter {
+                track,
+                insert_id,
+                parameter,
+                after,
+                ..
+            } => {
+                set_parameter(track_list, master_out, track, *insert_id, parameter, *after);
+            }
+            Edit::Sends { track, after, .. } => {
+                with_track(track_list, track, |t| t.sends = after.clone());
+            }
+            Edit::Groups { after, .. } => track_list.groups = after.clone(),
+            Edit::Automation { track, after, .. } => {
+                with_track(track_list, track, |t| t.automation.lanes = after.clone());
+            }
+            Edit::PanLaw { after, .. } => track_list.pan_law = *after,
+            Edit::SoloMode { after, .. } => track_list.solo_mode = *after,
+            Edit::TempoMap { after, .. } => track_list.tempo_map = after.clone(),
+        }
+    }
+
+    // Continuous gain, pan, send level and parameter changes on one track are
+    // merged into the edit before them, keeping the value from before the drag
+    // started.
+    fn coalesce(&mut self, next: &mut Edit) -> bool {
+        if let (
+            Edit::UpdateTrack { name, update, .. },
+            Edit::UpdateTrack {
+                name: next_name,
+                update: next_update,
+                ..
+            },
+        ) = (&mut *self, &mut *next)
+        {
+            let continuous = matches!(
+                (&*update, &*next_update),
+                (
+                    track::track_list::TrackUpdate::Gain(_),
+                    track::track_list::TrackUpdate::Gain(_)
+                ) | (
+                    track::track_list::TrackUpdate::Pan(_),
+                    track::track_list::TrackUpdate::Pan(_)
+                )
+            );
+            if continuous && name == next_name {
+                mem::swap(update, next_update);
+                return true;
+            }
+        }
+        // Send level drags, the same sends going to the same buses.
+        if let (
+            Edit::Sends { track, after, .. },
+            Edit::Sends {
+                track: next_track,
+                before: next_before,
+                after: next_after,
+            },
+        ) = (&mut *self, &mut *next)
+        {
+            let same_routing = |a: &[track::sends::AuxSend], b: &[track::sends::AuxSend]| {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b)
+                        .all(|(a, b)| a.bus == b.bus && a.pre_fader == b.pre_fader)
+            };
+            if track == next_track
+                && same_routing(after, next_before)
+                && same_routing(next_before, next_after)
+            {
+                *after = mem::take(next_after);
+                return true;
+            }
+        }
+        if let (
+            Edit::InsertParameter {
+                track,
+                insert_id,
+                parameter,
+                after,
+                ..
+            },
+            Edit::InsertParameter {
+                track: next_track,
+                insert_id: next_insert_id,
+                parameter: next_parameter,
+                after: next_after,
+                ..
+            },
+        ) = (self, next)
+        {
+            if track == next_track && insert_id == next_insert_id && parameter == next_parameter {
+                *after = *next_after;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn with_track(
+    track_list: &track::track_list::TrackList,
+    name: &str,
+    f: impl FnOnce(&mut track::tracks::InputTrack),
+) {
+    match track_list
+        .get_track(name)
+        .as_ref()
+        .map(|track| track.lock())
+    {
+        Some(Ok(mut track)) => f(&mut track),
+        _ => eprintln!("Track {} not found", name),
+    }
+}
+
+// Runs `f` on the insert chain of a track or the master.
+pub fn with_chain<R>(
+    track_list: &track::track_list::TrackList,
+    master_out: &mut track::tracks::OutputTrack,
+    name: &str,
+    f: impl FnOnce(&mut track::processors::chain::InsertChain) -> R,
+) -> Option<R> {
+    if name == "master-out" {
+        return Some(f(&mut master_out.inserts));
+    }
+    match track_list
+        .get_track(name)
+        .as_ref()
+        .map(|track| track.lock())
+    {
+        Some(Ok(mut track)) => Some(f(&mut track.inserts)),
+        _ => {
+            eprintln!("Track {} not found", name);
+            None
+        }
+    }
+}
+
+// Sets a parameter of an insert on a track or the master, the master limiter
+// included. Returns the previous value.
+pub fn set_parameter(
+    track_list: &track::track_list::TrackList,
+    master_out: &mut track::tracks::OutputTrack,
+    name: &str,
+    insert_id: u64,
+    parameter: &str,
+    value: f32,
+) -> Option<f32> {
+    if name == "master-out" && master_out.limiter.id == insert_id {
+        return master_out.limiter.set_parameter(parameter, value);
+    }
+    with_chain(track_list, master_out, name, |chain| {
+        chain.get_mut(insert_id)?.set_parameter(parameter, value)
+    })
+    .flatten()
+}
+
+// Applies an update to a track or the master and returns the updates that
+// undo it. `link` is false when undoing so linked changes aren't repeated.
+pub fn apply_update(
+    track_list: &mut track::track_list::TrackList,
+    master_out: &mut track::tracks::OutputTrack,
+    name: &str,
+    update: track::track_list::TrackUpdate,
+    link: bool,
+) -> Vec<(String, track::track_list::TrackUpdate)> {
+    if name == "master-out" {
+        let undo = match update {
+            track::track_list::TrackUpdate::Pan(pan) => {
+                track::track_list::TrackUpdate::Pan(mem::replace(&mut master_out.pan, pan))
+            }
+            track::track_list::TrackUpdate::Gain(gain) => {
+                track::track_list::TrackUpdate::Gain(mem::replace(&mut master_out.gain, gain))
+            }
+            _ => return Vec::new(),
+        };
+        vec![(name.to_string(), undo)]
+    } else {
+        if let track::track_list::TrackUpdate::Name(new_name) = &update {
+            if track_list.get_track(name).is_some() && track_list.get_track(new_name).is_none() {
+                track::track_list::rename_keys(&mut master_out.inserts, name, new_name);
+            }
+        }
+        track_list.update_track(name, update, link)
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    // The time is cleared once an edit must not be merged into anymore.
+    undo: Vec<(Edit, Option<Instant>)>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    pub fn push(&mut self, edit: Edit) {
+        self.push_at(edit, Instant::now());
+    }
+
+    fn push_at(&mut self, mut edit: Edit, now: Instant) {
+        self.redo.clear();
+        if let Some((last, Some(time))) = self.undo.last_mut() {
+            if now.duration_since(*time) < COALESCE_WINDOW && last.coalesce(&mut edit) {
+                *time = now;
+                return;
+            }
+        }
+
+        self.undo.push((edit, Some(now)));
+        if self.undo.len() > HISTORY_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<&Edit> {
+        let (edit, _) = self.undo.pop()?;
+        if let Some((_, time)) = self.undo.last_mut() {
+            *time = None;
+        }
+        self.redo.push(edit);
+        self.redo.last()
+    }
+
+    pub fn redo(&mut self) -> Option<&Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push((edit, None));
+        self.undo.last().map(|(edit, _)| edit)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> (track::track_list::TrackList, track::tracks::OutputTrack) {
+        let mut track_list = track::track_list::TrackList::new();
+        track_list.add_track("Kick", track::tracks::InputTrack::new("Kick", None));
+        (track_list, track::tracks::OutputTrack::new(48_000))
+    }
+
+    // Applies the update like the mixer does and returns the edit to record.
+    fn update(
+        track_list: &mut track::track_list::TrackList,
+        master_out: &mut track::tracks::OutputTrack,
+        update: track::track_list::TrackUpdate,
+    ) -> Edit {
+        let undo = apply_update(track_list, master_out, "Kick", update.clone(), true);
+        Edit::UpdateTrack {
+            name: "Kick".to_string(),
+            update,
+            undo,
+        }
+    }
+
+    fn gain(track_list: &track::track_list::TrackList) -> f32 {
+        track_list.get_track("Kick").unwrap().lock().unwrap().gain
+    }
+
+    fn pan_law(law: track::mixer::PanLaw) -> Edit {
+        Edit::PanLaw {
+            before: track::mixer::PanLaw::ConstantPower,
+            after: law,
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let (mut track_list, mut master_out) = session();
+        let mut history = History::new();
+        let edit = update(
+            &mut track_list,
+            &mut master_out,
+            track::track_list::TrackUpdate::Gain(0.5),
+        );
+        history.push(edit);
+
+        assert!(history.can_undo() && !history.can_redo());
+        history
+            .undo()
+            .unwrap()
+            .undo(&mut track_list, &mut master_out);
+        assert_eq!(gain(&track_list), 1.0);
+        assert!(!history.can_undo() && history.can_redo());
+
+        history
+            .redo()
+            .unwrap()
+            .redo(&mut track_list, &mut master_out);
+        assert_eq!(gain(&track_list), 0.5);
+        assert!(history.can_undo() && !history.can_redo());
+    }
+
+    #[test]
+    fn drags_inside_the_window_are_one_edit() {
+        let (mut track_list, mut master_out) = session();
+        let mut history = History::new();
+        let start = Instant::now();
+        for (step, value) in [0.9, 0.8, 0.7].into_iter().enumerate() {
+            let edit = update(
+                &mut track_list,
+                &mut master_out,
+                track::track_list::TrackUpdate::Gain(value),
+            );
+            history.push_at(edit, start + Duration::from_millis(400 * step as u64));
+        }
+
+        // Each move was within the window of the one before.
+        history
+            .undo()
+            .unwrap()
+            .undo(&mut track_list, &mut master_out);
+        assert_eq!(gain(&track_list), 1.0);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn moves_after_the_window_are_separate_edits() {
+        let (mut track_list, mut master_out) = session();
+        let mut history = History::new();
+        let start = Instant::now();
+        let first = update(
+            &mut track_list,
+            &mut master_out,
+            track::track_list::TrackUpdate::Gain(0.9),
+        );
+        history.push_at(first, start);
+        let second = update(
+            &mut track_list,
+            &mut master_out,
+            track::track_list::TrackUpdate::Gain(0.8),
+        );
+        history.push_at(second, start + COALESCE_WINDOW);
+
+        history
+            .undo()
+            .unwrap()
+            .undo(&mut track_list, &mut master_out);
+        assert_eq!(gain(&track_list), 0.9);
+        assert!(history.can_undo());
+    }
+
+    #[test]
+    fn undo_ends_the_drag() {
+        let (mut track_list, mut master_out) = session();
+        let mut history = History::new();
+        let start = Instant::now();
+        for (step, value) in [0.9, 0.8].into_iter().enumerate() {
+            let edit = update(
+                &mut track_list,
+                &mut master_out,
+                track::track_list::TrackUpdate::Pan(value - 1.0),
+            );
+            history.push_at(edit, start + COALESCE_WINDOW * step as u32 * 2);
+        }
+        history
+            .undo()
+            .unwrap()
+            .undo(&mut track_list, &mut master_out);
+
+        // Straight after the undo, but the edit below is closed.
+        let edit = update(
+            &mut track_list,
+            &mut master_out,
+            track::track_list::TrackUpdate::Pan(0.5),
+        );
+        history.push_at(edit, start + COALESCE_WINDOW * 2);
+        history
+            .undo()
+            .unwrap()
+            .undo(&mut track_list, &mut master_out);
+        assert!(history.can_undo());
+    }
+
+    #[test]
+    fn a_new_edit_clears_redo() {
+        let mut history = History::new();
+        history.push(pan_law(track::mixer::PanLaw::Linear));
+        history.undo();
+        assert!(history.can_redo());
+
+        history.push(pan_law(track::mixer::PanLaw::Balance));
+        assert!(!history.can_redo());
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn oldest_edits_are_dropped_past_the_limit() {
+        let mut history = History::new();
+        for _ in 0..HISTORY_LIMIT + 20 {
+            history.push(pan_law(track::mixer::PanLaw::Linear));
+        }
+
+        let mut undone = 0;
+        while history.undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, HISTORY_LIMIT);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn clear_forgets_everything() {
+        let mut history = History::new();
+        history.push(pan_law(track::mixer::PanLaw::Linear));
+        history.push(pan_law(track::mixer::PanLaw::Balance));
+        history.undo();
+        history.clear();
+        assert!(!history.can_undo() && !history.can_redo());
+    }
+}
@@ -0,0 +1,104 @@
+use std::{collections::HashSet, path::Path};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use serde::Serialize;
+
+use crate::{project, track};
+
+// Rendered past the last clip when the end isn't given, so reverb and delay
+// tails ring out.
+pub const DEFAULT_TAIL_SECONDS: u64 = 5;
+
+// Emitted about this often while rendering, in rendered frames.
+const PROGRESS_INTERVAL: u64 = 1 << 16;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MixdownProgress {
+    pub path: String,
+    // From 0.0 to 1.0.
+    pub progress: f32,
+    // Set once the render is over, `error` tells whether it failed.
+    pub finished: bool,
+    pub error: Option<String>,
+}
+
+// Everything a render needs, taken from the mixer up front so the render can
+// run without holding its lock. The graph has its own processors, so the live
+// engine is left alone.
+pub struct Mixdown {
+    graph: track::engine::Graph,
+    sample_rate: u32,
+    start: u64,
+    end: u64,
+}
+
+impl Mixdown {
+    // `end` defaults to the end of the last clip plus `tail` frames, which
+    // default to `DEFAULT_TAIL_SECONDS`.
+    pub fn new(
+        state_mixer: &project::states::StateMixer,
+        start: u64,
+        end: Option<u64>,
+        tail: Option<u64>,
+    ) -> Result<Self, String> {
+        let sample_rate = state_mixer.transport.sample_rate();
+        let master_out = state_mixer
+            .master_out
+            .lock()
+            .map_err(|_| "Failed to lock master out".to_string())?;
+        let track_list = state_mixer
+            .track_list
+            .lock()
+            .map_err(|_| "Failed to lock track list".to_string())?;
+        let end = end.unwrap_or_else(|| {
+            track_list.length(sample_rate)
+                + tail.unwrap_or(DEFAULT_TAIL_SECONDS * sample_rate as u64)
+        });
+        Ok(Mixdown {
+            graph: track::engine::Graph::build(
+                &track_list,
+                &master_out,
+                &HashSet::new(),
+                sample_rate,
+            ),
+            sample_rate,
+            start,
+            end,
+        })
+    }
+
+    // Bounces `start..end` to a stereo WAV as fast as the mix allows, with
+    // the share rendered so far every now and then.
+    pub fn render(self, path: &Path, mut progress: impl FnMut(f32)) -> Result<(), String> {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(path, spec).map_err(|e| e.to_string())?;
+        let mut engine = track::engine::Engine::offline(self.graph);
+        let mut left = vec![0.0; track::engine::MAX_BLOCK_SIZE];
+        let mut right = vec![0.0; track::engine::MAX_BLOCK_SIZE];
+
+        let length = self.end.saturating_sub(self.start).max(1);
+        let mut position = self.start;
+        let mut reported = position;
+        while position < self.end {
+            let frames = ((self.end - position) as usize).min(track::engine::MAX_BLOCK_SIZE);
+            engine.process(position, true, 0, &mut left[..frames], &mut right[..frames]);
+
+            for i in 0..frames {
+                writer.write_sample(left[i]).map_err(|e| e.to_string())?;
+                writer.write_sample(right[i]).map_err(|e| e.to_string())?;
+            }
+            position += frames as u64;
+            if position - reported >= PROGRESS_INTERVAL {
+                reported = position;
+                progress((position - self.start) as f32 / length as f32);
+            }
+        }
+
+        writer.finalize().map_err(|e| e.to_string())
+    }
+}
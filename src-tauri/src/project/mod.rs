@@ -1,3 +1,8 @@
 pub mod commands;
 pub mod file;
+pub mod format;
+pub mod history;
+pub mod mixdown;
 pub mod states;
+pub mod tempo;
+pub mod transport;
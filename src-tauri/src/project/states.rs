@@ -1,5 +1,6 @@
 use std::{
-    collections::HashMap,
+    mem,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc, Mutex,
@@ -8,19 +9,55 @@ use std::{
 
 use cpal::Device;
 use discord_rich_presence::{activity::Activity, DiscordIpc, DiscordIpcClient};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
 
-use crate::{track, types};
+use crate::{project, track, types};
 
-#[derive(bincode::Encode, bincode::Decode)]
+// A take that stopped early because its file couldn't be written.
+#[derive(Clone, Debug, Serialize)]
+pub struct RecordingError {
+    pub track: String,
+    pub error: String,
+}
+
+// Timeline positions are in frames at `sample_rate`, they are moved to the
+// engine's rate on load.
+#[derive(Serialize, Deserialize)]
 pub struct StateMixerRaw {
-    track_list: HashMap<String, track::raw::InputTrackRaw>,
+    sample_rate: u32,
+    track_list: Vec<track::raw::InputTrackRaw>,
+    master_out: track::raw::OutputTrackRaw,
+    pan_law: track::mixer::PanLaw,
+    solo_mode: track::mixer::SoloMode,
+    groups: Vec<track::track_list::TrackGroup>,
+    tempo_map: project::tempo::TempoMap,
+    metronome: track::metronome::MetronomeSettings,
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
 }
 
-impl From<&StateMixer> for StateMixerRaw {
-    fn from(value: &StateMixer) -> Self {
-        let track_list = value.track_list.lock().expect("Failed to lock track list");
+impl StateMixerRaw {
+    pub fn new(state_mixer: &StateMixer, audio_context: &StateAudioContext) -> Self {
+        let track_list = state_mixer
+            .track_list
+            .lock()
+            .expect("Failed to lock track list");
+        let master_out = state_mixer
+            .master_out
+            .lock()
+            .expect("Failed to lock master out");
         StateMixerRaw {
+            sample_rate: state_mixer.transport.sample_rate(),
             track_list: track_list.to_raw(),
+            master_out: track::raw::OutputTrackRaw::from(&*master_out),
+            pan_law: track_list.pan_law,
+            solo_mode: track_list.solo_mode,
+            groups: track_list.groups.clone(),
+            tempo_map: track_list.tempo_map.clone(),
+            metronome: track_list.metronome.settings.clone(),
+            input_device: audio_context.input_device_name(),
+            output_device: audio_context.output_device_name(),
         }
     }
 }
@@ -30,19 +67,29 @@ pub struct StateMixerGuard(pub Arc<Mutex<StateMixer>>);
 pub struct StateMixer {
     pub track_list: Arc<Mutex<track::track_list::TrackList>>,
     pub master_out: Arc<Mutex<track::tracks::OutputTrack>>,
+    pub transport: Arc<project::transport::Transport>,
+    pub engine: track::engine::EngineHandle,
+    pub history: project::history::History,
+    pub project_path: Option<PathBuf>,
     pub discord_client: Mutex<DiscordIpcClient>,
 }
 
 impl StateMixer {
     pub fn new(device: Arc<Device>) -> Self {
         let track_list = Arc::new(Mutex::new(track::track_list::TrackList::new()));
-        let master_out = Arc::new(Mutex::new(track::tracks::OutputTrack::new()));
-        let sink =
-            track::sources::sink::StreamSink::new(device, track_list.clone(), master_out.clone());
+        let transport = Arc::new(project::transport::Transport::new());
+        let master_out = Arc::new(Mutex::new(track::tracks::OutputTrack::new(
+            transport.sample_rate(),
+        )));
+        let (engine, audio_engine) = track::engine::channel();
+        let sink = track::sources::sink::StreamSink::new(device, audio_engine, transport.clone());
         if let Ok(mut out) = master_out.lock() {
             out.initialize(Box::new(sink));
             out.sink.start_stream();
         }
+        if let Ok(mut track_list) = track_list.lock() {
+            track_list.metronome.prepare(transport.sample_rate());
+        }
         let discord_client = Mutex::new(DiscordIpcClient::new("1435880809767637164"));
         if let Ok(mut client) = discord_client.lock() {
             if let Err(e) = client.connect() {
@@ -53,10 +100,726 @@ impl StateMixer {
         StateMixer {
             track_list,
             master_out,
+            transport,
+            engine,
+            history: project::history::History::new(),
+            project_path: None,
             discord_client,
         }
     }
 
+    pub fn sync_engine(&self) {
+        if let (Ok(track_list), Ok(master_out)) = (self.track_list.lock(), self.master_out.lock()) {
+            self.engine
+                .publish(&track_list, &master_out, self.transport.sample_rate());
+        }
+    }
+
+    // Applies nothing itself, the change must already be made.
+    pub fn record_edit(&mut self, edit: project::history::Edit) {
+        self.history.push(edit);
+        self.sync_engine();
+    }
+
+    // Returns whether other tracks followed the change, through a group or
+    // exclusive solo.
+    pub fn update_track(
+        &mut self,
+        track_name: &str,
+        update: track::track_list::TrackUpdate,
+    ) -> Result<bool, String> {
+        let undo = {
+            let mut track_list = self
+                .track_list
+                .lock()
+                .map_err(|_| "Failed to lock track list".to_string())?;
+            let mut master_out = self
+                .master_out
+                .lock()
+                .map_err(|_| "Failed to lock master out".to_string())?;
+            project::history::apply_update(
+                &mut track_list,
+                &mut master_out,
+                track_name,
+                update.clone(),
+                true,
+            )
+        };
+
+        let linked = undo.iter().any(|(name, _)| name != track_name);
+        self.write_automation(
+            undo.iter()
+                .filter_map(|(name, update)| {
+                    let target = match update {
+                        track::track_list::TrackUpdate::Gain(_) => {
+                            track::automation::AutomationTarget::Gain
+                        }
+                        track::track_list::TrackUpdate::Pan(_) => {
+                            track::automation::AutomationTarget::Pan
+                        }
+                        track::track_list::TrackUpdate::Mute(_) => {
+                            track::automation::AutomationTarget::Mute
+                        }
+                        _ => return None,
+                    };
+                    Some((name.clone(), target))
+                })
+                .collect(),
+        );
+        if !undo.is_empty() {
+            self.record_edit(project::history::Edit::UpdateTrack {
+                name: track_name.to_string(),
+                update,
+                undo,
+            });
+        }
+        Ok(linked)
+    }
+
+    // Runs `f` on the insert chain of a track or the master and records the
+    // change when `f` returns true.
+    pub fn edit_inserts(
+        &mut self,
+        track_name: &str,
+        f: impl FnOnce(&mut track::processors::chain::InsertChain) -> bool,
+    ) -> Result<(), String> {
+        let (before, after) = {
+            let track_list = self
+                .track_list
+                .lock()
+                .map_err(|_| "Failed to lock track list".to_string())?;
+            let mut master_out = self
+                .master_out
+                .lock()
+                .map_err(|_| "Failed to lock master out".to_string())?;
+            project::history::with_chain(&track_list, &mut master_out, track_name, |chain| {
+                let before = chain.clone();
+                f(chain).then(|| (before, chain.clone()))
+            })
+            .ok_or(format!("Track {} not found", track_name))?
+            .ok_or("Insert not found".to_string())?
+        };
+
+        self.record_edit(project::history::Edit::Inserts {
+            track: track_name.to_string(),
+            before,
+            after,
+        });
+        Ok(())
+    }
+
+    // Runs `f` on the sends of a track and records the change when it succeeds.
+    pub fn edit_sends(
+        &mut self,
+        track_name: &str,
+        f: impl FnOnce(&mut Vec<track::sends::AuxSend>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let track = self
+            .track_list
+            .lock()
+            .map_err(|_| "Failed to lock track list".to_string())?
+            .get_track(track_name)
+            .ok_or(format!("Track {} not found", track_name))?;
+        let (before, after) = {
+            let mut track = track
+                .lock()
+                .map_err(|_| "Failed to lock track".to_string())?;
+            let before = track.sends.clone();
+            f(&mut track.sends)?;
+            (before, track.sends.clone())
+        };
+
+        if before != after {
+            self.record_edit(project::history::Edit::Sends {
+                track: track_name.to_string(),
+                before,
+                after,
+            });
+        }
+        Ok(())
+    }
+
+    // Runs `f` on the track groups and records the change when it succeeds.
+    pub fn edit_groups(
+        &mut self,
+        f: impl FnOnce(
+            &track::track_list::TrackList,
+            &mut Vec<track::track_list::TrackGroup>,
+        ) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let (before, after) = {
+            let mut track_list = self
+                .track_list
+                .lock()
+                .map_err(|_| "Failed to lock track list".to_string())?;
+            let mut groups = track_list.groups.clone();
+            f(&track_list, &mut groups)?;
+            let before = mem::replace(&mut track_list.groups, groups);
+            (before, track_list.groups.clone())
+        };
+
+        if before != after {
+            self.record_edit(project::history::Edit::Groups { before, after });
+        }
+        Ok(())
+    }
+
+    pub fn set_insert_parameter(
+        &mut self,
+        track_name: &str,
+        insert_id: u64,
+        parameter: &str,
+        value: f32,
+    ) -> Result<(), String> {
+        let before = {
+            let track_list = self
+                .track_list
+                .lock()
+                .map_err(|_| "Failed to lock track list".to_string())?;
+            let mut master_out = self
+                .master_out
+                .lock()
+                .map_err(|_| "Failed to lock master out".to_string())?;
+            project::history::set_parameter(
+                &track_list,
+                &mut master_out,
+                track_name,
+                insert_id,
+                parameter,
+                value,
+            )
+            .ok_or(format!("Parameter {} not found", parameter))?
+        };
+
+        self.write_automation(vec![(
+            track_name.to_string(),
+            track::automation::AutomationTarget::Parameter {
+                insert_id,
+                parameter: parameter.to_string(),
+            },
+        )]);
+        self.record_edit(project::history::Edit::InsertParameter {
+            track: track_name.to_string(),
+            insert_id,
+            parameter: parameter.to_string(),
+            before,
+            after: value,
+        });
+        Ok(())
+    }
+
+    // Writes the current values of the controls into their lanes, for tracks
+    // whose automation mode writes while the transport plays.
+    fn write_automation(&self, targets: Vec<(String, track::automation::AutomationTarget)>) {
+        if !self.transport.is_playing() {
+            return;
+        }
+        let position = self.transport.position();
+        let sample_rate = self.transport.sample_rate();
+        if let Ok(track_list) = self.track_list.lock() {
+            for (name, target) in targets {
+                if let Some(Ok(mut track)) = track_list.get_track(&name).as_ref().map(|t| t.lock())
+                {
+                    if let Some(value) = track.automation_value(&target) {
+                        track.automation.write(target, value, position, sample_rate);
+                    }
+                }
+            }
+        }
+    }
+
+    // Starts the passes of tracks in write mode when the transport starts.
+    pub fn begin_automation(&self) {
+        let position = self.transport.position();
+        let sample_rate = self.transport.sample_rate();
+        if let Ok(mut track_list) = self.track_list.lock() {
+            track_list.metronome.prepare(sample_rate);
+            for track in track_list.get_tracks() {
+                if let Ok(mut track) = track.lock() {
+                    let values = [
+                        track::automation::AutomationTarget::Gain,
+                        track::automation::AutomationTarget::Pan,
+                        track::automation::AutomationTarget::Mute,
+                    ]
+                    .into_iter()
+                    .chain(
+                        track
+                            .automation
+                            .lanes
+                            .iter()
+                            .map(|lane| lane.target.clone()),
+                    )
+                    .filter_map(|target| Some((target.clone(), track.automation_value(&target)?)))
+                    .collect();
+                    track.automation.begin(values, position, sample_rate);
+                }
+            }
+        }
+        self.sync_engine();
+    }
+
+    // Ends every automation pass at the playhead, each track's pass undoes as
+    // one edit. Returns whether any lane changed.
+    pub fn finish_automation(&mut self) -> bool {
+        let position = self.transport.position();
+        let mut edits = Vec::new();
+        if let Ok(track_list) = self.track_list.lock() {
+            for track in track_list.get_tracks() {
+                if let Ok(mut track) = track.lock() {
+                    if let Some((before, after)) = track.automation.finish(position) {
+                        edits.push(project::history::Edit::Automation {
+                            track: track.name.clone(),
+                            before,
+                            after,
+                        });
+                    }
+                }
+            }
+        }
+
+        let changed = !edits.is_empty();
+        for edit in edits {
+            self.history.push(edit);
+        }
+        self.sync_engine();
+        changed
+    }
+
+    // Lets go of a control in touch mode, its lane plays back again.
+    pub fn release_automation(
+        &mut self,
+        track_name: &str,
+        target: &track::automation::AutomationTarget,
+    ) -> Result<(), String> {
+        let track = self
+            .track_list
+            .lock()
+            .map_err(|_| "Failed to lock track list".to_string())?
+            .get_track(track_name)
+            .ok_or(format!("Track {} not found", track_name))?;
+        track
+            .lock()
+            .map_err(|_| "Failed to lock track".to_string())?
+            .automation
+            .release(target, self.transport.position());
+        self.sync_engine();
+        Ok(())
+    }
+
+    // Runs `f` on the automation of a track and records the change to its
+    // lanes.
+    pub fn edit_automation(
+        &mut self,
+        track_name: &str,
+        f: impl FnOnce(&mut track::automation::Automation),
+    ) -> Result<(), String> {
+        let track = self
+            .track_list
+            .lock()
+            .map_err(|_| "Failed to lock track list".to_string())?
+            .get_track(track_name)
+            .ok_or(format!("Track {} not found", track_name))?;
+        let (before, after) = {
+            let mut track = track
+                .lock()
+                .map_err(|_| "Failed to lock track".to_string())?;
+            let before = track.automation.lanes.clone();
+            f(&mut track.automation);
+            (before, track.automation.lanes.clone())
+        };
+
+        if before != after {
+            self.record_edit(project::history::Edit::Automation {
+                track: track_name.to_string(),
+                before,
+                after,
+            });
+        }
+        Ok(())
+    }
+
+    // Runs `f` on the clips of a track and records the change when it
+    // succeeds, the clips are kept in order of their start.
+    pub fn edit_clips<T>(
+        &mut self,
+        track_name: &str,
+        f: impl FnOnce(&mut Vec<track::clips::Clip>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let track = self
+            .track_list
+            .lock()
+            .map_err(|_| "Failed to lock track list".to_string())?
+            .get_track(track_name)
+            .ok_or(format!("Track {} not found", track_name))?;
+        let (result, before, after) = {
+            let mut track = track
+                .lock()
+                .map_err(|_| "Failed to lock track".to_string())?;
+            let before = track.clips.clone();
+            let result = f(&mut track.clips);
+            if result.is_err() {
+                track.clips = before;
+                return result;
+            }
+            track.clips.sort_by_key(|clip| clip.start);
+            (result, before, track.clips.clone())
+        };
+
+        if before != after {
+            self.record_edit(project::history::Edit::Clips {
+                track: track_name.to_string(),
+                before,
+                after,
+            });
+        }
+        result
+    }
+
+    // Runs `f` on a track and records the edit it returns, if any, while the
+    // mixer is still held so no other edit lands in between.
+    pub fn edit_track<T>(
+        &mut self,
+        track_name: &str,
+        f: impl FnOnce(
+            &mut track::tracks::InputTrack,
+        ) -> Result<(T, Option<project::history::Edit>), String>,
+    ) -> Result<T, String> {
+        let track = self
+            .track_list
+            .lock()
+            .map_err(|_| "Failed to lock track list".to_string())?
+            .get_track(track_name)
+            .ok_or(format!("Track {} not found", track_name))?;
+        let (result, edit) = {
+            let mut track = track
+                .lock()
+                .map_err(|_| "Failed to lock track".to_string())?;
+            f(&mut track)?
+        };
+        if let Some(edit) = edit {
+            self.record_edit(edit);
+        }
+        Ok(result)
+    }
+
+    // Runs `f` on a take folder of a track and records the change.
+    pub fn edit_take_folder(
+        &mut self,
+        track_name: &str,
+        folder_id: u64,
+        f: impl FnOnce(&mut track::takes::TakeFolder) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let track = self
+            .track_list
+            .lock()
+            .map_err(|_| "Failed to lock track list".to_string())?
+            .get_track(track_name)
+            .ok_or(format!("Track {} not found", track_name))?;
+        let (before, after) = {
+            let mut track = track
+                .lock()
+                .map_err(|_| "Failed to lock track".to_string())?;
+            let before = track.take_folders.clone();
+            let folder = track
+                .take_folders
+                .iter_mut()
+                .find(|folder| folder.id == folder_id)
+                .ok_or(format!("Take folder {} not found", folder_id))?;
+            if let Err(e) = f(folder) {
+                track.take_folders = before;
+                return Err(e);
+            }
+            (before, track.take_folders.clone())
+        };
+
+        self.record_edit(project::history::Edit::TakeFolders {
+            track: track_name.to_string(),
+            before,
+            after,
+        });
+        Ok(())
+    }
+
+    // Called after the engine sample rate changes. Inserts get new processors
+    // for the new rate with the next graph.
+    pub fn prepare_processors(&self) {
+        if let Ok(mut track_list) = self.track_list.lock() {
+            track_list.metronome.prepare(self.transport.sample_rate());
+        }
+    }
+
+    pub fn gain_reductions(&self) -> Vec<track::processors::dynamics::GainReduction> {
+        let mut reductions = Vec::new();
+        let mut collect = |name: &str, chain: &track::processors::chain::InsertChain| {
+            for insert in &chain.inserts {
+                if let Some(meter) = &insert.meter {
+                    reductions.push(track::processors::dynamics::GainReduction {
+                        track: name.to_string(),
+                        insert_id: insert.id,
+                        gain_reduction: if insert.bypass { 0.0 } else { meter.get() },
+                    });
+                }
+            }
+        };
+        if let Ok(track_list) = self.track_list.lock() {
+            for track in track_list.get_tracks() {
+                if let Ok(track) = track.lock() {
+                    collect(&track.name, &track.inserts);
+                }
+            }
+        }
+        if let Ok(master_out) = self.master_out.lock() {
+            collect("master-out", &master_out.inserts);
+            if let Some(meter) = &master_out.limiter.meter {
+                reductions.push(track::processors::dynamics::GainReduction {
+                    track: "master-out".to_string(),
+                    insert_id: master_out.limiter.id,
+                    gain_reduction: meter.get(),
+                });
+            }
+        }
+        reductions
+    }
+
+    pub fn undo(&mut self) -> Option<project::history::HistoryChange> {
+        let scope = {
+            let edit = self.history.undo()?;
+            let mut track_list = self.track_list.lock().ok()?;
+            let mut master_out = self.master_out.lock().ok()?;
+            edit.undo(&mut track_list, &mut master_out);
+            edit.scope()
+        };
+        Some(self.history_changed(scope))
+    }
+
+    pub fn redo(&mut self) -> Option<project::history::HistoryChange> {
+        let scope = {
+            let edit = self.history.redo()?;
+            let mut track_list = self.track_list.lock().ok()?;
+            let mut master_out = self.master_out.lock().ok()?;
+            edit.redo(&mut track_list, &mut master_out);
+            edit.scope()
+        };
+        Some(self.history_changed(scope))
+    }
+
+    fn history_changed(
+        &self,
+        scope: project::history::HistoryScope,
+    ) -> project::history::HistoryChange {
+        self.sync_engine();
+        project::history::HistoryChange {
+            scope,
+            can_undo: self.history.can_undo(),
+            can_redo: self.history.can_redo(),
+        }
+    }
+
+    pub fn play(&self) {
+        self.transport.play();
+        self.begin_automation();
+    }
+
+    // Write errors during the take are sent to the UI as `recording-error`.
+    pub fn record(&self, folder: &Path, app_handle: &AppHandle) -> Result<(), String> {
+        if self.transport.is_recording() {
+            return Ok(());
+        }
+
+        let position = self.transport.position();
+        let (tracks, count_in) = {
+            let track_list = self
+                .track_list
+                .lock()
+                .map_err(|_| "Failed to lock track list".to_string())?;
+            // Only a record from a standstill counts in.
+            let count_in = if self.transport.is_playing() {
+                0
+            } else {
+                track_list
+                    .metronome
+                    .count_in_frames(&track_list.tempo_map, position)
+            };
+            (track_list.get_tracks(), count_in)
+        };
+
+        // Takes cover the punch range when it's on, the input runs on through
+        // the count-in and up to punch in. Recording over the loop keeps every
+        // pass, their punch ranges are cut out when they're split into lanes.
+        let punch = self.transport.punch_range();
+        let cycle = self
+            .transport
+            .loop_range()
+            .filter(|range| position < range.end);
+        let mut window = track::tracks::TakeWindow {
+            start: position,
+            skip: count_in,
+            limit: None,
+            cycle,
+            punch,
+        };
+        if let (None, Some(punch)) = (cycle, punch) {
+            window.start = position.max(punch.start);
+            window.skip += window.start - position;
+            window.limit = Some(punch.end.saturating_sub(window.start));
+        }
+
+        if window.limit != Some(0) {
+            let sample_rate = self.transport.sample_rate();
+            let mut started: Vec<Arc<Mutex<track::tracks::InputTrack>>> = Vec::new();
+            for track in tracks {
+                let Ok(mut guard) = track.lock() else {
+                    continue;
+                };
+                if !guard.record {
+                    continue;
+                }
+                let app_handle = app_handle.clone();
+                let name = guard.name.clone();
+                let on_error = Box::new(move |error: String| {
+                    let error = RecordingError {
+                        track: name.clone(),
+                        error,
+                    };
+                    if let Err(e) = app_handle.emit("recording-error", error) {
+                        eprintln!("Failed to emit recording error: {}", e);
+                    }
+                });
+                if let Err(e) = guard.start_take(folder, window, sample_rate, on_error) {
+                    let error = format!("Failed to create take for {}: {}", guard.name, e);
+                    drop(guard);
+                    // Nothing has been written yet, so closing the takes
+                    // already opened removes their files again.
+                    for track in started {
+                        if let Ok(mut track) = track.lock() {
+                            track.finish_take(sample_rate);
+                        }
+                    }
+                    return Err(error);
+                }
+                drop(guard);
+                started.push(track);
+            }
+        }
+        self.transport.record(count_in);
+        self.begin_automation();
+        Ok(())
+    }
+
+    // Closes every open take and adds it to its track as a clip or take lanes,
+    // returns whether anything was added.
+    pub fn finish_recording(&mut self) -> bool {
+        let mut edits = Vec::new();
+        if let Ok(track_list) = self.track_list.lock() {
+            for track in track_list.get_tracks() {
+                if let Ok(mut track) = track.lock() {
+                    match track.finish_take(self.transport.sample_rate()) {
+                        Some(track::tracks::RecordedTake::Clip(clip)) => {
+                            edits.push(project::history::Edit::AddClip {
+                                track: track.name.clone(),
+                                clip,
+                            });
+                        }
+                        Some(track::tracks::RecordedTake::Folders(before)) => {
+                            edits.push(project::history::Edit::TakeFolders {
+                                track: track.name.clone(),
+                                before,
+                                after: track.take_folders.clone(),
+                            });
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        let added = !edits.is_empty();
+        for edit in edits {
+            self.record_edit(edit);
+        }
+        added
+    }
+
+    pub fn pause(&self) {
+        self.transport.pause();
+    }
+
+    pub fn stop(&self) {
+        self.transport.stop();
+    }
+
+    pub fn return_to_zero(&mut self) -> bool {
+        self.locate(0)
+    }
+
+    // Automation passes end at the old position and start again at the new
+    // one. Returns whether any lane changed.
+    pub fn locate(&mut self, position: u64) -> bool {
+        let playing = self.transport.is_playing();
+        let changed = playing && self.finish_automation();
+        self.transport.locate(position);
+        if playing {
+            self.begin_automation();
+        }
+        changed
+    }
+
+    // Replaces the session with a saved one. The transport, engine and output
+    // stream are kept, so only the project contents change.
+    pub fn restore(&mut self, raw: StateMixerRaw, app_handle: &AppHandle) {
+        self.finish_recording();
+        self.history.clear();
+        self.transport.stop();
+        self.transport.locate(0);
+
+        let mut tracks = raw.track_list;
+        for track in &mut tracks {
+            track.rescale(raw.sample_rate, self.transport.sample_rate());
+        }
+        let mut track_list = track::track_list::TrackList::from_raw(
+            tracks,
+            self.transport.sample_rate(),
+            app_handle,
+        );
+        track_list.pan_law = raw.pan_law;
+        track_list.solo_mode = raw.solo_mode;
+        track_list.groups = raw.groups;
+        track_list.tempo_map = raw.tempo_map.validate().unwrap_or_else(|e| {
+            eprintln!("{}, using the default tempo map", e);
+            project::tempo::TempoMap::new()
+        });
+        track_list.metronome.settings = raw.metronome;
+        track_list.metronome.prepare(self.transport.sample_rate());
+        if let Ok(mut list) = self.track_list.lock() {
+            *list = track_list;
+        }
+        if let Ok(mut master_out) = self.master_out.lock() {
+            master_out.gain = raw.master_out.gain;
+            master_out.pan = raw.master_out.pan;
+            master_out.inserts = track::processors::chain::InsertChain::from_raw(
+                raw.master_out.inserts,
+                self.transport.sample_rate(),
+            );
+            master_out.limiter = track::processors::chain::Insert::from_raw(
+                raw.master_out.limiter,
+                self.transport.sample_rate(),
+            );
+        }
+        self.sync_engine();
+    }
+
+    // Rebuilds the session at the transport's new sample rate from `raw`, saved
+    // at the old one. The history goes with it, its edits hold old positions.
+    pub fn follow_sample_rate(&mut self, raw: StateMixerRaw, app_handle: &AppHandle) {
+        let from = raw.sample_rate;
+        let to = self.transport.sample_rate();
+        let position = self.transport.position();
+        self.restore(raw, app_handle);
+        self.transport
+            .locate(project::transport::rescale_frames(position, from, to));
+        self.transport.rescale_ranges(from, to);
+    }
+
     pub fn connect_to_discord(&self) {
         if let Ok(mut client) = self.discord_client.lock() {
             if let Err(e) = client.connect() {
@@ -84,20 +847,6 @@ impl StateMixer {
     }
 }
 
-impl From<StateMixerRaw> for StateMixer {
-    fn from(value: StateMixerRaw) -> Self {
-        let track_list = value.track_list;
-        let discord_client = Mutex::new(DiscordIpcClient::new("1435880809767637164"));
-        StateMixer {
-            track_list: Arc::new(Mutex::new(track::track_list::TrackList::from_raw(
-                track_list,
-            ))),
-            discord_client,
-            master_out: { todo!() },
-        }
-    }
-}
-
 #[derive(Clone)]
 pub struct StateAudioContext {
     pub input_device_registry: Arc<types::InputDeviceRegistry>,
@@ -129,4 +878,18 @@ impl StateAudioContext {
         self.output_device_registry
             .get(self.output_device_index.load(Ordering::SeqCst))
     }
+
+    pub fn input_device_name(&self) -> Option<String> {
+        self.input_device_registry
+            .list()
+            .get(self.input_device_index.load(Ordering::SeqCst))
+            .cloned()
+    }
+
+    pub fn output_device_name(&self) -> Option<String> {
+        self.output_device_registry
+            .list()
+            .get(self.output_device_index.load(Ordering::SeqCst))
+            .cloned()
+    }
 }
@@ -0,0 +1,397 @@
+use serde::{Deserialize, Serialize};
+
+// Resolution of musical time, per quarter note.
+pub const TICKS_PER_QUARTER: u32 = 960;
+const MIN_BPM: f64 = 10.0;
+const MAX_BPM: f64 = 999.0;
+const MAX_NUMERATOR: u32 = 64;
+const MAX_DENOMINATOR: u32 = 64;
+// Keeps a ruler request over a huge range from building an endless list.
+const MAX_BAR_LINES: usize = 10_000;
+
+// Tempo from `tick` on, in quarter notes per minute.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TempoChange {
+    pub tick: u64,
+    pub bpm: f64,
+}
+
+// Time signature from the start of `bar` on, bars counted from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeterChange {
+    pub bar: u32,
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl MeterChange {
+    fn ticks_per_beat(&self) -> u64 {
+        (TICKS_PER_QUARTER * 4 / self.denominator) as u64
+    }
+
+    fn ticks_per_bar(&self) -> u64 {
+        self.ticks_per_beat() * self.numerator as u64
+    }
+}
+
+// A position as shown on the ruler, bar and beat count from 1 and beats are
+// in the unit of the time signature's denominator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BarBeatTick {
+    pub bar: u32,
+    pub beat: u32,
+    pub tick: u32,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BarLine {
+    pub bar: u32,
+    pub position: u64,
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+// Grid clips snap to, `Note(8)` is every eighth note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GridDivision {
+    Bar,
+    Beat,
+    Note(u32),
+}
+
+// Both lists are sorted and start at 0, which `validate` checks for maps
+// coming from the UI or a project file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TempoMap {
+    pub tempos: Vec<TempoChange>,
+    pub meters: Vec<MeterChange>,
+}
+
+impl TempoMap {
+    pub fn new() -> Self {
+        TempoMap {
+            tempos: vec![TempoChange {
+                tick: 0,
+                bpm: 120.0,
+            }],
+            meters: vec![MeterChange {
+                bar: 0,
+                numerator: 4,
+                denominator: 4,
+            }],
+        }
+    }
+
+    // Sorts the changes and checks their values.
+    pub fn validate(mut self) -> Result<Self, String> {
+        self.tempos.sort_by_key(|tempo| tempo.tick);
+        self.tempos.dedup_by_key(|tempo| tempo.tick);
+        self.meters.sort_by_key(|meter| meter.bar);
+        self.meters.dedup_by_key(|meter| meter.bar);
+
+        if self.tempos.first().map(|tempo| tempo.tick) != Some(0) {
+            return Err("The tempo map needs a tempo at the start".to_string());
+        }
+        if self.meters.first().map(|meter| meter.bar) != Some(0) {
+            return Err("The tempo map needs a time signature at the start".to_string());
+        }
+        if let Some(tempo) = self
+            .tempos
+            .iter()
+            .find(|tempo| !(MIN_BPM..=MAX_BPM).contains(&tempo.bpm))
+        {
+            return Err(format!(
+                "Tempo {} is outside {} to {} BPM",
+                tempo.bpm, MIN_BPM, MAX_BPM
+            ));
+        }
+        if let Some(meter) = self.meters.iter().find(|meter| {
+            !(1..=MAX_NUMERATOR).contains(&meter.numerator)
+                || !meter.denominator.is_power_of_two()
+                || meter.denominator > MAX_DENOMINATOR
+        }) {
+            return Err(format!(
+                "{}/{} is not a supported time signature",
+                meter.numerator, meter.denominator
+            ));
+        }
+        Ok(self)
+    }
+
+    pub fn seconds_at_tick(&self, tick: f64) -> f64 {
+        let mut seconds = 0.0;
+        for (i, tempo) in self.tempos.iter().enumerate() {
+            let start = tempo.tick as f64;
+            if tick <= start {
+                break;
+            }
+            let end = self
+                .tempos
+                .get(i + 1)
+                .map_or(tick, |next| (next.tick as f64).min(tick));
+            seconds += (end - start) / TICKS_PER_QUARTER as f64 * 60.0 / tempo.bpm;
+        }
+        seconds
+    }
+
+    pub fn tick_at_seconds(&self, seconds: f64) -> f64 {
+        let mut elapsed = 0.0;
+        for (i, tempo) in self.tempos.iter().enumerate() {
+            let seconds_per_tick = 60.0 / tempo.bpm / TICKS_PER_QUARTER as f64;
+            match self.tempos.get(i + 1) {
+                Some(next) => {
+                    let length = (next.tick - tempo.tick) as f64 * seconds_per_tick;
+                    if seconds < elapsed + length {
+                        return tempo.tick as f64 + (seconds - elapsed) / seconds_per_tick;
+                    }
+                    elapsed += length;
+                }
+                None => return tempo.tick as f64 + (seconds - elapsed) / seconds_per_tick,
+            }
+        }
+        0.0
+    }
+
+    pub fn tick_at_sample(&self, position: u64, sample_rate: u32) -> f64 {
+        self.tick_at_seconds(position as f64 / sample_rate.max(1) as f64)
+    }
+
+    pub fn sample_at_tick(&self, tick: f64, sample_rate: u32) -> u64 {
+        (self.seconds_at_tick(tick) * sample_rate as f64)
+            .round()
+            .max(0.0) as u64
+    }
+
+    pub fn tempo_at(&self, tick: f64) -> f64 {
+        self.tempos
+            .iter()
+            .rev()
+            .find(|tempo| tempo.tick as f64 <= tick)
+            .map_or(self.tempos[0].bpm, |tempo| tempo.bpm)
+    }
+
+    pub fn meter_at(&self, tick: f64) -> MeterChange {
+        self.meter_at_tick(tick).0
+    }
+
+    // The first beat at or after `tick` and whether it starts a bar. Time
+    // signatures change on bar lines, so they never cut a beat short.
+    pub fn next_beat(&self, tick: f64) -> (f64, bool) {
+        let (meter, start) = self.meter_at_tick(tick);
+        let beat = meter.ticks_per_beat() as f64;
+        let index = ((tick - start as f64) / beat).ceil().max(0.0) as u64;
+        (
+            start as f64 + index as f64 * beat,
+            index.is_multiple_of(meter.numerator as u64),
+        )
+    }
+
+    // The meter in effect at `tick` and the tick its first bar starts at.
+    fn meter_at_tick(&self, tick: f64) -> (MeterChange, u64) {
+        let mut current = self.meters[0];
+        let mut start = 0;
+        for meter in &self.meters[1..] {
+            let next_start = start + (meter.bar - current.bar) as u64 * current.ticks_per_bar();
+            if next_start as f64 > tick {
+                break;
+            }
+            current = *meter;
+            start = next_start;
+        }
+        (current, start)
+    }
+
+    // Same as `meter_at_tick`, by the 0 based bar number.
+    fn meter_at_bar(&self, bar: u32) -> (MeterChange, u64) {
+        let mut current = self.meters[0];
+        let mut start = 0;
+        for meter in self.meters[1..].iter().take_while(|meter| meter.bar <= bar) {
+            start += (meter.bar - current.bar) as u64 * current.ticks_per_bar();
+            current = *meter;
+        }
+        (current, start)
+    }
+
+    pub fn tick_at_bar(&self, bar: u32) -> u64 {
+        let (meter, start) = self.meter_at_bar(bar);
+        start + (bar - meter.bar) as u64 * meter.ticks_per_bar()
+    }
+
+    pub fn bar_beat_tick(&self, tick: f64) -> BarBeatTick {
+        let tick = tick.max(0.0);
+        let (meter, start) = self.meter_at_tick(tick);
+        let offset = (tick - start as f64).floor() as u64;
+        let bar = meter.bar as u64 + offset / meter.ticks_per_bar();
+        let in_bar = offset % meter.ticks_per_bar();
+        BarBeatTick {
+            bar: bar as u32 + 1,
+            beat: (in_bar / meter.ticks_per_beat()) as u32 + 1,
+            tick: (in_bar % meter.ticks_per_beat()) as u32,
+        }
+    }
+
+    pub fn tick_at_bar_beat_tick(&self, position: BarBeatTick) -> u64 {
+        let bar = position.bar.max(1) - 1;
+        let (meter, _) = self.meter_at_bar(bar);
+        self.tick_at_bar(bar)
+            + (position.beat.max(1) - 1) as u64 * meter.ticks_per_beat()
+            + position.tick as u64
+    }
+
+    // Start of every bar that begins between `start` and `end`, in frames.
+    pub fn bar_lines(&self, start: u64, end: u64, sample_rate: u32) -> Vec<BarLine> {
+        let first = self
+            .bar_beat_tick(self.tick_at_sample(start, sample_rate))
+            .bar
+            - 1;
+        (first..)
+            .map(|bar| {
+                let (meter, _) = self.meter_at_bar(bar);
+                BarLine {
+                    bar: bar + 1,
+                    position: self.sample_at_tick(self.tick_at_bar(bar) as f64, sample_rate),
+                    numerator: meter.numerator,
+                    denominator: meter.denominator,
+                }
+            })
+            .skip_while(|line| line.position < start)
+            .take_while(|line| line.position <= end)
+            .take(MAX_BAR_LINES)
+            .collect()
+    }
+
+    // Moves `position` to the nearest line of the grid, counted from the start
+    // of the time signature it falls in.
+    pub fn snap(&self, position: u64, sample_rate: u32, division: GridDivision) -> u64 {
+        let tick = self.tick_at_sample(position, sample_rate);
+        let (meter, start) = self.meter_at_tick(tick);
+        let step = match division {
+            GridDivision::Bar => meter.ticks_per_bar(),
+            GridDivision::Beat => meter.ticks_per_beat(),
+            GridDivision::Note(note) => (TICKS_PER_QUARTER as u64 * 4 / note.max(1) as u64).max(1),
+        } as f64;
+        let snapped = start as f64 + ((tick - start as f64) / step).round() * step;
+        self.sample_at_tick(snapped, sample_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 120 BPM in 4/4, 90 BPM from bar 3 and 3/4 from bar 5.
+    fn changing_map() -> TempoMap {
+        TempoMap {
+            tempos: vec![
+                TempoChange {
+                    tick: 0,
+                    bpm: 120.0,
+                },
+                TempoChange {
+                    tick: 2 * 4 * TICKS_PER_QUARTER as u64,
+                    bpm: 90.0,
+                },
+            ],
+            meters: vec![
+                MeterChange {
+                    bar: 0,
+                    numerator: 4,
+                    denominator: 4,
+                },
+                MeterChange {
+                    bar: 4,
+                    numerator: 3,
+                    denominator: 4,
+                },
+            ],
+        }
+        .validate()
+        .unwrap()
+    }
+
+    #[test]
+    fn seconds_and_ticks_round_trip() {
+        let map = changing_map();
+        // Two bars at 120 BPM take four seconds, a quarter at 90 BPM after
+        // that takes two thirds of one.
+        assert!((map.seconds_at_tick(7_680.0) - 4.0).abs() < 1e-9);
+        assert!((map.seconds_at_tick(7_680.0 + 960.0) - 4.0 - 2.0 / 3.0).abs() < 1e-9);
+
+        for tick in [0.0, 480.0, 7_679.0, 7_680.0, 7_681.0, 50_000.5] {
+            let seconds = map.seconds_at_tick(tick);
+            assert!((map.tick_at_seconds(seconds) - tick).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn samples_and_ticks_round_trip() {
+        let map = changing_map();
+        for sample_rate in [44_100, 48_000, 96_000] {
+            for position in [0, 1, 12_345, 192_000, 10_000_000] {
+                let tick = map.tick_at_sample(position, sample_rate);
+                assert_eq!(map.sample_at_tick(tick, sample_rate), position);
+            }
+        }
+    }
+
+    #[test]
+    fn bar_beat_tick_round_trips_across_meter_changes() {
+        let map = changing_map();
+        // Four bars of 4/4, then bars of three quarters.
+        assert_eq!(map.tick_at_bar(4), 4 * 3_840);
+        assert_eq!(map.tick_at_bar(5), 4 * 3_840 + 2_880);
+        assert_eq!(
+            map.bar_beat_tick((4 * 3_840 + 2_880 + 960 + 10) as f64),
+            BarBeatTick {
+                bar: 6,
+                beat: 2,
+                tick: 10,
+            }
+        );
+
+        for tick in [0, 959, 960, 3_840, 15_359, 15_360, 18_240, 100_000] {
+            let position = map.bar_beat_tick(tick as f64);
+            assert_eq!(map.tick_at_bar_beat_tick(position), tick);
+        }
+    }
+
+    #[test]
+    fn next_beat_marks_bar_starts() {
+        let map = changing_map();
+        assert_eq!(map.next_beat(0.0), (0.0, true));
+        assert_eq!(map.next_beat(1.0), (960.0, false));
+        assert_eq!(map.next_beat(15_361.0), (16_320.0, false));
+        assert_eq!(map.next_beat(17_281.0), (18_240.0, true));
+    }
+
+    #[test]
+    fn snap_moves_to_the_nearest_line() {
+        let map = TempoMap::new();
+        // A beat at 120 BPM is 24000 frames at 48 kHz.
+        assert_eq!(map.snap(11_000, 48_000, GridDivision::Beat), 0);
+        assert_eq!(map.snap(13_000, 48_000, GridDivision::Beat), 24_000);
+        assert_eq!(map.snap(40_000, 48_000, GridDivision::Bar), 0);
+        assert_eq!(map.snap(50_000, 48_000, GridDivision::Bar), 96_000);
+        assert_eq!(map.snap(50_000, 48_000, GridDivision::Note(8)), 48_000);
+    }
+
+    #[test]
+    fn validate_sorts_and_checks_changes() {
+        let mut map = changing_map();
+        map.tempos.reverse();
+        map.meters.reverse();
+        assert_eq!(map.validate().unwrap(), changing_map());
+
+        let mut map = TempoMap::new();
+        map.tempos[0].tick = 10;
+        assert!(map.validate().is_err());
+
+        let mut map = TempoMap::new();
+        map.tempos[0].bpm = 5.0;
+        assert!(map.validate().is_err());
+
+        let mut map = TempoMap::new();
+        map.meters[0].denominator = 3;
+        assert!(map.validate().is_err());
+    }
+}
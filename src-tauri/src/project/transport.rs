@@ -0,0 +1,384 @@
+use std::{
+    hint,
+    sync::{
+        atomic::{self, AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+const POSITION_EVENT_INTERVAL: Duration = Duration::from_millis(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TransportState {
+    Stopped,
+    Playing,
+    Paused,
+    Recording,
+}
+
+impl TransportState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => TransportState::Playing,
+            2 => TransportState::Paused,
+            3 => TransportState::Recording,
+            _ => TransportState::Stopped,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            TransportState::Stopped => 0,
+            TransportState::Playing => 1,
+            TransportState::Paused => 2,
+            TransportState::Recording => 3,
+        }
+    }
+}
+
+// A span of the timeline in frames, `end` not included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl TimeRange {
+    pub fn rescale(self, from: u32, to: u32) -> Self {
+        TimeRange {
+            start: rescale_frames(self.start, from, to),
+            end: rescale_frames(self.end, from, to),
+        }
+    }
+}
+
+// Moves a frame position from one sample rate to another, open ended lengths
+// stay open ended.
+pub fn rescale_frames(frames: u64, from: u32, to: u32) -> u64 {
+    if from == to || from == 0 || frames == u64::MAX {
+        return frames;
+    }
+    (frames as u128 * to as u128 / from as u128).min(u64::MAX as u128) as u64
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TransportInfo {
+    pub state: TransportState,
+    pub position: u64,
+    pub seconds: f64,
+    pub sample_rate: u32,
+    // Frames of count-in left before the position moves.
+    pub count_in: u64,
+    pub loop_range: TimeRange,
+    pub loop_enabled: bool,
+    pub punch_range: TimeRange,
+    pub punch_enabled: bool,
+}
+
+// A range the audio thread reads, kept disabled while it isn't valid. It's a
+// seqlock, `sequence` is odd while a writer stores and a read only counts when
+// all three fields were read under the same even sequence.
+struct AtomicRange {
+    sequence: AtomicU64,
+    enabled: AtomicBool,
+    start: AtomicU64,
+    end: AtomicU64,
+}
+
+impl AtomicRange {
+    fn new() -> Self {
+        AtomicRange {
+            sequence: AtomicU64::new(0),
+            enabled: AtomicBool::new(false),
+            start: AtomicU64::new(0),
+            end: AtomicU64::new(0),
+        }
+    }
+
+    // Reads once, `None` when a writer got in the way.
+    fn try_load(&self) -> Option<(TimeRange, bool)> {
+        let sequence = self.sequence.load(Ordering::Acquire);
+        if sequence % 2 == 1 {
+            return None;
+        }
+        let range = TimeRange {
+            start: self.start.load(Ordering::Relaxed),
+            end: self.end.load(Ordering::Relaxed),
+        };
+        let enabled = self.enabled.load(Ordering::Relaxed);
+        atomic::fence(Ordering::Acquire);
+        (self.sequence.load(Ordering::Relaxed) == sequence).then_some((range, enabled))
+    }
+
+    // Retries until a read goes through, not for the audio thread.
+    fn load(&self) -> (TimeRange, bool) {
+        loop {
+            match self.try_load() {
+                Some(value) => return value,
+                None => hint::spin_loop(),
+            }
+        }
+    }
+
+    fn get(&self) -> Option<TimeRange> {
+        let (range, enabled) = self.load();
+        enabled.then_some(range)
+    }
+
+    // Writers take turns by making the sequence odd, readers never see a
+    // range that is half stored.
+    fn set(&self, range: TimeRange, enabled: bool) {
+        let mut sequence = self.sequence.load(Ordering::Relaxed);
+        loop {
+            if sequence % 2 == 1 {
+                hint::spin_loop();
+                sequence = self.sequence.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.sequence.compare_exchange_weak(
+                sequence,
+                sequence + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => sequence = current,
+            }
+        }
+        atomic::fence(Ordering::Release);
+        self.start.store(range.start, Ordering::Relaxed);
+        self.end.store(range.end, Ordering::Relaxed);
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.sequence.store(sequence + 2, Ordering::Release);
+    }
+}
+
+// The playhead is counted in frames at the output device rate and is only
+// advanced by the master sink callback, so it stays in step with what is heard.
+pub struct Transport {
+    state: AtomicU8,
+    position: AtomicU64,
+    start_position: AtomicU64,
+    sample_rate: AtomicU32,
+    count_in: AtomicU64,
+    loop_range: AtomicRange,
+    punch_range: AtomicRange,
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Transport {
+            state: AtomicU8::new(TransportState::Stopped.as_u8()),
+            position: AtomicU64::new(0),
+            start_position: AtomicU64::new(0),
+            sample_rate: AtomicU32::new(48000),
+            count_in: AtomicU64::new(0),
+            loop_range: AtomicRange::new(),
+            punch_range: AtomicRange::new(),
+        }
+    }
+
+    pub fn state(&self) -> TransportState {
+        TransportState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(
+            self.state(),
+            TransportState::Playing | TransportState::Recording
+        )
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state() == TransportState::Recording
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Acquire)
+    }
+
+    pub fn count_in(&self) -> u64 {
+        self.count_in.load(Ordering::Acquire)
+    }
+
+    // Playback wraps from the end back to the start once it reaches the end
+    // from before it.
+    pub fn loop_range(&self) -> Option<TimeRange> {
+        self.loop_range.get()
+    }
+
+    // The audio thread's read, it can't wait on a writer so it keeps `last`
+    // while the range is being stored.
+    pub fn poll_loop_range(&self, last: &mut Option<TimeRange>) -> Option<TimeRange> {
+        if let Some((range, enabled)) = self.loop_range.try_load() {
+            *last = enabled.then_some(range);
+        }
+        *last
+    }
+
+    // Armed tracks only record inside this range.
+    pub fn punch_range(&self) -> Option<TimeRange> {
+        self.punch_range.get()
+    }
+
+    pub fn set_loop_range(&self, range: TimeRange, enabled: bool) -> Result<(), String> {
+        if range.start >= range.end {
+            return Err("The loop has to end after it starts".to_string());
+        }
+        self.loop_range.set(range, enabled);
+        Ok(())
+    }
+
+    pub fn set_punch_range(&self, range: TimeRange, enabled: bool) -> Result<(), String> {
+        if range.start >= range.end {
+            return Err("Punch out has to come after punch in".to_string());
+        }
+        self.punch_range.set(range, enabled);
+        Ok(())
+    }
+
+    // Keeps the loop and punch ranges on the same music after the sample rate
+    // changes.
+    pub fn rescale_ranges(&self, from: u32, to: u32) {
+        for range in [&self.loop_range, &self.punch_range] {
+            let (value, enabled) = range.load();
+            range.set(value.rescale(from, to), enabled);
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_sample_rate(&self, sample_rate: u32) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    pub fn play(&self) {
+        if self.state() == TransportState::Stopped {
+            self.start_position
+                .store(self.position(), Ordering::Release);
+        }
+        self.set_state(TransportState::Playing);
+    }
+
+    // The position holds still for `count_in` frames before it starts moving.
+    pub fn record(&self, count_in: u64) {
+        if self.state() == TransportState::Stopped {
+            self.start_position
+                .store(self.position(), Ordering::Release);
+        }
+        self.count_in.store(count_in, Ordering::Release);
+        self.set_state(TransportState::Recording);
+    }
+
+    pub fn pause(&self) {
+        if self.is_playing() {
+            self.set_state(TransportState::Paused);
+            self.count_in.store(0, Ordering::Release);
+        }
+    }
+
+    pub fn stop(&self) {
+        let was_stopped = self.state() == TransportState::Stopped;
+        self.set_state(TransportState::Stopped);
+        self.count_in.store(0, Ordering::Release);
+        if was_stopped {
+            self.position.store(0, Ordering::Release);
+        } else {
+            self.position.store(
+                self.start_position.load(Ordering::Acquire),
+                Ordering::Release,
+            );
+        }
+    }
+
+    pub fn locate(&self, position: u64) {
+        self.start_position.store(position, Ordering::Release);
+        self.position.store(position, Ordering::Release);
+    }
+
+    // Called from the audio callback once per buffer with the position it
+    // started from, the one it ended on and the frames of count-in it used up.
+    // A locate made in the meantime wins over the callback.
+    pub fn advance(&self, from: u64, to: u64, counted: u64) {
+        let _ = self
+            .count_in
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count_in| {
+                Some(count_in.saturating_sub(counted))
+            });
+        let _ = self
+            .position
+            .compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    pub fn info(&self) -> TransportInfo {
+        let position = self.position();
+        let sample_rate = self.sample_rate();
+        let (loop_range, loop_enabled) = self.loop_range.load();
+        let (punch_range, punch_enabled) = self.punch_range.load();
+        TransportInfo {
+            state: self.state(),
+            position,
+            seconds: position as f64 / sample_rate.max(1) as f64,
+            sample_rate,
+            count_in: self.count_in(),
+            loop_range,
+            loop_enabled,
+            punch_range,
+            punch_enabled,
+        }
+    }
+
+    fn set_state(&self, state: TransportState) {
+        self.state.store(state.as_u8(), Ordering::Release);
+    }
+}
+
+pub fn spawn_position_emitter(app_handle: AppHandle, transport: Arc<Transport>) {
+    thread::spawn(move || {
+        let mut last_info = None;
+
+        loop {
+            let info = transport.info();
+            if last_info.as_ref() != Some(&info) {
+                last_info = Some(info.clone());
+                if let Err(e) = app_handle.emit("transport-position", info) {
+                    eprintln!("Failed to emit transport position: {}", e);
+                }
+            }
+            thread::sleep(POSITION_EVENT_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_thread_keeps_the_last_loop_while_it_is_stored() {
+        let transport = Transport::new();
+        let range = TimeRange {
+            start: 100,
+            end: 200,
+        };
+        transport.set_loop_range(range, true).unwrap();
+        let mut last = None;
+        assert_eq!(transport.poll_loop_range(&mut last), Some(range));
+
+        // A writer is halfway through storing a new range.
+        transport.loop_range.sequence.fetch_add(1, Ordering::AcqRel);
+        transport.loop_range.start.store(300, Ordering::Relaxed);
+        assert_eq!(transport.poll_loop_range(&mut last), Some(range));
+
+        transport.loop_range.enabled.store(false, Ordering::Relaxed);
+        transport.loop_range.sequence.fetch_add(1, Ordering::AcqRel);
+        assert_eq!(transport.poll_loop_range(&mut last), None);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{mem, ops::RangeInclusive};
+
+use serde::{Deserialize, Serialize};
+
+use crate::project;
+
+// A pause longer than this while writing holds the last value instead of
+// ramping to the next move.
+const HOLD_SECONDS: f64 = 0.1;
+
+// Lane values use the units of the control they drive: linear gain, pan from
+// -1.0 to 1.0, mute as 0.0 or 1.0 and processor parameters in their own range.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AutomationTarget {
+    Gain,
+    Pan,
+    Mute,
+    Parameter { insert_id: u64, parameter: String },
+}
+
+impl AutomationTarget {
+    // Curve new points get when they are written from a control.
+    fn curve(&self) -> Curve {
+        match self {
+            AutomationTarget::Mute => Curve::Step,
+            _ => Curve::Linear,
+        }
+    }
+}
+
+// Shape of the segment from a breakpoint to the next one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Curve {
+    Linear,
+    // Holds the value until the next point.
+    Step,
+    // Slow start, fast end.
+    Exponential,
+    // Fast start, slow end.
+    Logarithmic,
+    // S-shaped, slow at both ends.
+    Smooth,
+}
+
+impl Curve {
+    // Maps progress through a segment, both in 0.0..=1.0.
+    fn shape(self, t: f32) -> f32 {
+        match self {
+            Curve::Linear => t,
+            Curve::Step => 0.0,
+            Curve::Exponential => t * t,
+            Curve::Logarithmic => 1.0 - (1.0 - t) * (1.0 - t),
+            Curve::Smooth => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Breakpoint {
+    pub position: u64,
+    pub value: f32,
+    pub curve: Curve,
+}
+
+// Points are sorted by position with at most one per position.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AutomationLane {
+    pub target: AutomationTarget,
+    pub points: Vec<Breakpoint>,
+}
+
+impl AutomationLane {
+    pub fn new(target: AutomationTarget) -> Self {
+        AutomationLane {
+            target,
+            points: Vec::new(),
+        }
+    }
+
+    // The first and last values hold before and after the points, `None` when
+    // the lane is empty.
+    pub fn value_at(&self, position: u64) -> Option<f32> {
+        let next = self
+            .points
+            .partition_point(|point| point.position <= position);
+        match (
+            next.checked_sub(1).map(|i| &self.points[i]),
+            self.points.get(next),
+        ) {
+            (None, None) => None,
+            (Some(point), None) | (None, Some(point)) => Some(point.value),
+            (Some(from), Some(to)) => {
+                let t = (position - from.position) as f32 / (to.position - from.position) as f32;
+                Some(from.value + (to.value - from.value) * from.curve.shape(t))
+            }
+        }
+    }
+
+    // Points that land on the same frame at the new rate keep the later one.
+    pub fn rescale(&mut self, from: u32, to: u32) {
+        let mut points = mem::take(&mut self.points);
+        for point in &mut points {
+            point.position = project::transport::rescale_frames(point.position, from, to);
+        }
+        self.set_points(points);
+    }
+
+    // Later points win when two share a position.
+    pub fn set_points(&mut self, mut points: Vec<Breakpoint>) {
+        points.reverse();
+        points.sort_by_key(|point| point.position);
+        points.dedup_by_key(|point| point.position);
+        self.points = points;
+    }
+
+    // Replaces the points inside `range` with `point`.
+    fn replace(&mut self, range: RangeInclusive<u64>, point: Breakpoint) {
+        self.points
+            .retain(|existing| !range.contains(&existing.position));
+        let index = self
+            .points
+            .partition_point(|existing| existing.position < point.position);
+        self.points.insert(index, point);
+    }
+}
+
+// Read plays the lanes back, the other modes also write control moves made
+// while the transport plays. Touch writes until the control is released, latch
+// until the transport stops and write from start to stop, even without moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutomationMode {
+    Off,
+    Read,
+    Touch,
+    Latch,
+    Write,
+}
+
+// A control being written into its lane.
+struct Pass {
+    target: AutomationTarget,
+    last: u64,
+    value: f32,
+}
+
+// The lanes of one track and the passes being written into them. Passes only
+// last while the transport plays and aren't saved.
+pub struct Automation {
+    pub mode: AutomationMode,
+    pub lanes: Vec<AutomationLane>,
+    passes: Vec<Pass>,
+    // Lanes as they were before the first pass, so a whole pass undoes at once.
+    before: Option<Vec<AutomationLane>>,
+}
+
+impl Automation {
+    pub fn new() -> Self {
+        Automation::from_raw(AutomationMode::Read, Vec::new())
+    }
+
+    pub fn from_raw(mode: AutomationMode, lanes: Vec<AutomationLane>) -> Self {
+        Automation {
+            mode,
+            lanes,
+            passes: Vec::new(),
+            before: None,
+        }
+    }
+
+    // Whether the engine should follow the lane, a control being written
+    // follows the control instead.
+    pub fn reads(&self, target: &AutomationTarget) -> bool {
+        self.mode != AutomationMode::Off && !self.passes.iter().any(|pass| pass.target == *target)
+    }
+
+    pub fn lane(&self, target: &AutomationTarget) -> Option<&AutomationLane> {
+        self.lanes.iter().find(|lane| lane.target == *target)
+    }
+
+    // An empty list of points removes the lane.
+    pub fn set_lane(&mut self, target: AutomationTarget, points: Vec<Breakpoint>) {
+        self.lanes.retain(|lane| lane.target != target);
+        if !points.is_empty() {
+            let mut lane = AutomationLane::new(target);
+            lane.set_points(points);
+            self.lanes.push(lane);
+        }
+    }
+
+    // Records a control move at `position`, returns false when the mode doesn't
+    // write.
+    pub fn write(
+        &mut self,
+        target: AutomationTarget,
+        value: f32,
+        position: u64,
+        sample_rate: u32,
+    ) -> bool {
+        if !matches!(
+            self.mode,
+            AutomationMode::Touch | AutomationMode::Latch | AutomationMode::Write
+        ) {
+            return false;
+        }
+        let hold = (sample_rate as f64 * HOLD_SECONDS) as u64;
+        if self.before.is_none() {
+            self.before = Some(self.lanes.clone());
+        }
+        let curve = target.curve();
+        let index = match self.lanes.iter().position(|lane| lane.target == target) {
+            Some(index) => index,
+            None => {
+                self.lanes.push(AutomationLane::new(target.clone()));
+                self.lanes.len() - 1
+            }
+        };
+        let lane = &mut self.lanes[index];
+        let point = Breakpoint {
+            position,
+            value,
+            curve,
+        };
+
+        match self.passes.iter_mut().find(|pass| pass.target == target) {
+            Some(pass) if position > pass.last => {
+                if position - pass.last > hold {
+                    lane.replace(
+                        pass.last + 1..=position - 1,
+                        Breakpoint {
+                            position: position - 1,
+                            value: pass.value,
+                            curve,
+                        },
+                    );
+                    lane.replace(position..=position, point);
+                } else {
+                    lane.replace(pass.last + 1..=position, point);
+                }
+                pass.last = position;
+                pass.value = value;
+            }
+            Some(pass) => {
+                lane.replace(position..=position, point);
+                pass.value = value;
+            }
+            None => {
+                lane.replace(position..=position, point);
+                self.passes.push(Pass {
+                    target,
+                    last: position,
+                    value,
+                });
+            }
+        }
+        true
+    }
+
+    // Touch stops writing a control when it's let go, the other modes keep
+    // the value until the transport stops.
+    pub fn release(&mut self, target: &AutomationTarget, position: u64) {
+        if self.mode != AutomationMode::Touch {
+            return;
+        }
+        if let Some(index) = self.passes.iter().position(|pass| pass.target == *target) {
+            let pass = self.passes.remove(index);
+            self.hold(pass, position);
+        }
+    }
+
+    // Starts writing every control in write mode, with their current values.
+    pub fn begin(&mut self, values: Vec<(AutomationTarget, f32)>, position: u64, sample_rate: u32) {
+        if self.mode != AutomationMode::Write {
+            return;
+        }
+        for (target, value) in values {
+            if !self.passes.iter().any(|pass| pass.target == target) {
+                self.write(target, value, position, sample_rate);
+            }
+        }
+    }
+
+    // Ends every pass at `position` and returns the lanes from before and
+    // after them when they changed anything.
+    pub fn finish(&mut self, position: u64) -> Option<(Vec<AutomationLane>, Vec<AutomationLane>)> {
+        for pass in mem::take(&mut self.passes) {
+            self.hold(pass, position);
+        }
+        let before = self.before.take()?;
+        (before != self.lanes).then(|| (before, self.lanes.clone()))
+    }
+
+    fn hold(&mut self, pass: Pass, position: u64) {
+        if position <= pass.last {
+            return;
+        }
+        if let Some(lane) = self
+            .lanes
+            .iter_mut()
+            .find(|lane| lane.target == pass.target)
+        {
+            let curve = pass.target.curve();
+            lane.replace(
+                pass.last + 1..=position,
+                Breakpoint {
+                    position,
+                    value: pass.value,
+                    curve,
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,805 @@
+use std::{
+    f32::consts::FRAC_PI_2,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{project, track};
+
+static NEXT_CLIP_ID: AtomicU64 = AtomicU64::new(1);
+// +12 dB.
+const MAX_CLIP_GAIN: f32 = 4.0;
+// Length of automatic crossfades at joins with no overlap to fade over.
+const DEFAULT_CROSSFADE_MS: u64 = 10;
+
+fn next_clip_id() -> u64 {
+    NEXT_CLIP_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Shape of a fade in, fade outs play it backwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FadeCurve {
+    Linear,
+    // Keeps the summed power steady when crossed with the opposite fade.
+    EqualPower,
+    // Slow start, fast end.
+    Exponential,
+    // Fast start, slow end.
+    Logarithmic,
+    // S-shaped, slow at both ends.
+    Smooth,
+}
+
+impl FadeCurve {
+    // Gain at `t` through the fade, both in 0.0..=1.0.
+    pub fn gain(self, t: f32) -> f32 {
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::EqualPower => (t * FRAC_PI_2).sin(),
+            FadeCurve::Exponential => t * t,
+            FadeCurve::Logarithmic => 1.0 - (1.0 - t) * (1.0 - t),
+            FadeCurve::Smooth => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fade {
+    pub length: u64,
+    pub curve: FadeCurve,
+}
+
+impl Fade {
+    pub fn none() -> Self {
+        Fade {
+            length: 0,
+            curve: FadeCurve::Linear,
+        }
+    }
+
+    pub fn rescale(self, from: u32, to: u32) -> Self {
+        Fade {
+            length: project::transport::rescale_frames(self.length, from, to),
+            ..self
+        }
+    }
+}
+
+// Positions are in frames at the engine sample rate: `start` is where the clip
+// sits on the timeline, `offset` is where it starts reading in its source.
+// A reversed clip plays the same part of its source backwards. Edits never
+// touch the source file.
+#[derive(Clone)]
+pub struct Clip {
+    pub id: u64,
+    pub name: String,
+    pub source: Arc<track::sources::source::FileSource>,
+    pub start: u64,
+    pub offset: u64,
+    pub length: u64,
+    pub gain: f32,
+    pub reversed: bool,
+    pub fade_in: Fade,
+    pub fade_out: Fade,
+    // The crossfade from the clip before into this one, `None` for the
+    // automatic one.
+    pub crossfade: Option<Fade>,
+    // Only set on the copies the engine plays: the crossfades at their edges,
+    // and how far they reach past the clip's own edges into them, where the
+    // clip's fades stay.
+    crossfade_in: Fade,
+    crossfade_out: Fade,
+    reach_before: u64,
+    reach_after: u64,
+}
+
+// Clips are equal when they play the same, the fields only the engine's
+// copies use are left out.
+impl PartialEq for Clip {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.name == other.name
+            && Arc::ptr_eq(&self.source, &other.source)
+            && self.start == other.start
+            && self.offset == other.offset
+            && self.length == other.length
+            && self.gain == other.gain
+            && self.reversed == other.reversed
+            && self.fade_in == other.fade_in
+            && self.fade_out == other.fade_out
+            && self.crossfade == other.crossfade
+    }
+}
+
+impl Clip {
+    pub fn new(name: &str, source: Arc<track::sources::source::FileSource>, start: u64) -> Self {
+        let length = source.len();
+        Clip {
+            id: next_clip_id(),
+            name: name.to_string(),
+            source,
+            start,
+            offset: 0,
+            length,
+            gain: 1.0,
+            reversed: false,
+            fade_in: Fade::none(),
+            fade_out: Fade::none(),
+            crossfade: None,
+            crossfade_in: Fade::none(),
+            crossfade_out: Fade::none(),
+            reach_before: 0,
+            reach_after: 0,
+        }
+    }
+
+    // A copy with its own id, it gets the automatic crossfade.
+    pub fn duplicate(&self, start: u64) -> Self {
+        Clip {
+            id: next_clip_id(),
+            start,
+            crossfade: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.start + self.length
+    }
+
+    pub fn overlaps(&self, from: u64, to: u64) -> bool {
+        self.start < to && self.end() > from
+    }
+
+    // The part of the clip between `from` and `to` on the timeline, with the
+    // same id.
+    pub fn trimmed(&self, from: u64, to: u64) -> Option<Self> {
+        let mut clip = self.clone();
+        clip.set_bounds(self.start.max(from), self.end().min(to))
+            .ok()
+            .map(|()| clip)
+    }
+
+    // Moves the edges of the clip to `start` and `end` on the timeline while
+    // the audio stays where it is, as far as the source reaches.
+    pub fn set_bounds(&mut self, start: u64, end: u64) -> Result<(), String> {
+        if start >= end {
+            return Err("A clip can't end before it starts".to_string());
+        }
+        // Frames cut from each end, negative when the clip grows.
+        let head = start as i128 - self.start as i128;
+        let tail = self.end() as i128 - end as i128;
+        let cut = if self.reversed { tail } else { head };
+        let offset = self.offset as i128 + cut;
+        let length = (end - start) as i128;
+        if offset < 0 || offset + length > self.source.len() as i128 {
+            return Err(format!("{} doesn't reach that far", self.name));
+        }
+        self.start = start;
+        self.offset = offset as u64;
+        self.length = length as u64;
+        self.fit_fades();
+        Ok(())
+    }
+
+    // Cuts the clip at `position` on the timeline and returns the part after
+    // it, which gets a new id and the fade out.
+    pub fn split(&mut self, position: u64) -> Result<Self, String> {
+        if position <= self.start || position >= self.end() {
+            return Err("The split has to be inside the clip".to_string());
+        }
+        let mut after = self.duplicate(self.start);
+        after.set_bounds(position, self.end())?;
+        after.fade_in = Fade::none();
+        self.set_bounds(self.start, position)?;
+        self.fade_out = Fade::none();
+        Ok(after)
+    }
+
+    // Changes which part of the source plays without moving the clip.
+    pub fn slip(&mut self, offset: u64) -> Result<(), String> {
+        if offset + self.length > self.source.len() {
+            return Err(format!("{} doesn't reach that far", self.name));
+        }
+        self.offset = offset;
+        Ok(())
+    }
+
+    pub fn set_gain(&mut self, gain: f32) -> Result<(), String> {
+        if !(0.0..=MAX_CLIP_GAIN).contains(&gain) {
+            return Err(format!("Clip gain must be between 0 and {}", MAX_CLIP_GAIN));
+        }
+        self.gain = gain;
+        Ok(())
+    }
+
+    pub fn set_fades(&mut self, fade_in: Fade, fade_out: Fade) -> Result<(), String> {
+        if fade_in.length + fade_out.length > self.length {
+            return Err("The fades are longer than the clip".to_string());
+        }
+        self.fade_in = fade_in;
+        self.fade_out = fade_out;
+        Ok(())
+    }
+
+    pub fn set_crossfade(&mut self, crossfade: Option<Fade>) -> Result<(), String> {
+        if crossfade.is_some_and(|fade| fade.length == 0) {
+            return Err("A crossfade can't be empty".to_string());
+        }
+        self.crossfade = crossfade;
+        Ok(())
+    }
+
+    // Frames of source the clip could grow by at its start and at its end.
+    fn room_before(&self) -> u64 {
+        if self.reversed {
+            self.source.len().saturating_sub(self.offset + self.length)
+        } else {
+            self.offset
+        }
+    }
+
+    fn room_after(&self) -> u64 {
+        if self.reversed {
+            self.offset
+        } else {
+            self.source.len().saturating_sub(self.offset + self.length)
+        }
+    }
+
+    // Whether the clip picks up exactly where `before` ends, like the two
+    // halves of a split, so there's nothing to crossfade.
+    fn continues(&self, before: &Clip) -> bool {
+        Arc::ptr_eq(&self.source, &before.source)
+            && self.reversed == before.reversed
+            && self.gain == before.gain
+            && self.start == before.end()
+            && if self.reversed {
+                self.offset + self.length == before.offset
+            } else {
+                self.offset == before.offset + before.length
+            }
+    }
+
+    // Shortens the fades to fit after the clip got shorter.
+    fn fit_fades(&mut self) {
+        self.fade_in.length = self.fade_in.length.min(self.length);
+        self.fade_out.length = self.fade_out.length.min(self.length - self.fade_in.length);
+    }
+
+    // Clip gain, crossfades and fades at `frame` frames into the clip. Where
+    // it reaches past its own edges its fades are still to come, or over.
+    fn gain_at(&self, frame: u64) -> f32 {
+        let gain = self.gain * fade_gain(frame, self.length, self.crossfade_in, self.crossfade_out);
+        let inner = self.length - self.reach_before - self.reach_after;
+        match frame.checked_sub(self.reach_before) {
+            Some(frame) if frame < inner => {
+                gain * fade_gain(frame, inner, self.fade_in, self.fade_out)
+            }
+            Some(_) if self.fade_out.length > 0 => 0.0,
+            None if self.fade_in.length > 0 => 0.0,
+            _ => gain,
+        }
+    }
+
+    // Adds the part of the clip that falls inside the block starting at
+    // `position` into `left` and `right`.
+    pub fn mix_into(&self, position: u64, left: &mut [f32], right: &mut [f32]) {
+        let frames = left.len() as u64;
+        if !self.overlaps(position, position + frames) {
+            return;
+        }
+
+        let first = self.start.saturating_sub(position);
+        let last = (self.end() - position).min(frames);
+        for frame in first..last {
+            let in_clip = position + frame - self.start;
+            let source_index = if self.reversed {
+                self.offset + self.length - 1 - in_clip
+            } else {
+                self.offset + in_clip
+            };
+            let (l, r) = self.source.frame(source_index);
+            let gain = self.gain_at(in_clip);
+            left[frame as usize] += l * gain;
+            right[frame as usize] += r * gain;
+        }
+    }
+
+    pub fn as_info(&self) -> ClipInfo {
+        ClipInfo {
+            id: self.id,
+            name: self.name.clone(),
+            start: self.start,
+            offset: self.offset,
+            length: self.length,
+            gain: self.gain,
+            reversed: self.reversed,
+            fade_in: self.fade_in,
+            fade_out: self.fade_out,
+            crossfade: self.crossfade,
+        }
+    }
+
+    pub fn from_raw(value: ClipRaw, sample_rate: u32) -> Option<Self> {
+        match value.source {
+            track::sources::source::AudioSourceRaw::File(path) => {
+                match track::sources::source::FileSource::new(PathBuf::from(&path), sample_rate) {
+                    // The file may have changed since the project was saved.
+                    Ok(source) => {
+                        let mut clip = Clip {
+                            id: next_clip_id(),
+                            name: value.name,
+                            length: value.length.min(source.len().saturating_sub(value.offset)),
+                            source: Arc::new(source),
+                            start: value.start,
+                            offset: value.offset,
+                            gain: value.gain,
+                            reversed: value.reversed,
+                            fade_in: value.fade_in,
+                            fade_out: value.fade_out,
+                            crossfade: value.crossfade,
+                            crossfade_in: Fade::none(),
+                            crossfade_out: Fade::none(),
+                            reach_before: 0,
+                            reach_after: 0,
+                        };
+                        clip.fit_fades();
+                        Some(clip)
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to load clip source {}: {}", path, e);
+                        None
+                    }
+                }
+            }
+            track::sources::source::AudioSourceRaw::Stream(device) => {
+                eprintln!("Clip can't reference live input {}", device);
+                None
+            }
+        }
+    }
+}
+
+// Gain at `frame` of `length` frames with a fade at each end.
+fn fade_gain(frame: u64, length: u64, fade_in: Fade, fade_out: Fade) -> f32 {
+    let mut gain = 1.0;
+    if frame < fade_in.length {
+        gain *= fade_in.curve.gain(frame as f32 / fade_in.length as f32);
+    }
+    let left = length - frame;
+    if left <= fade_out.length {
+        gain *= fade_out
+            .curve
+            .gain((left - 1) as f32 / fade_out.length as f32);
+    }
+    gain
+}
+
+// A crossfade the engine plays from the clip before into clip `clip_id`, in
+// frames on the timeline.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Crossfade {
+    pub clip_id: u64,
+    pub start: u64,
+    pub end: u64,
+    pub curve: FadeCurve,
+}
+
+// The copies of `clips` the engine plays and the crossfades between them,
+// `clips` sorted by start. Later clips play on top of earlier ones, so a clip
+// another one covers is cut there and picks up again after it. Every join
+// between the pieces gets a crossfade: over the overlap where a clip ends
+// inside the next, centred on the join otherwise. Clips reach into their
+// sources past the join as far as they go, and their own fades and gain stay
+// on top of the crossfade.
+pub fn apply_crossfades(clips: &[Clip], sample_rate: u32) -> (Vec<Clip>, Vec<Crossfade>) {
+    // Pieces of the clips that stay audible, with the index of their clip.
+    let mut pieces: Vec<(Clip, usize)> = Vec::new();
+    for (index, clip) in clips.iter().enumerate() {
+        let mut below = Vec::new();
+        for (piece, original) in pieces.drain(..) {
+            if piece.overlaps(clip.start, clip.end()) {
+                below.extend(
+                    piece
+                        .trimmed(piece.start, clip.start)
+                        .map(|p| (p, original)),
+                );
+                below.extend(
+                    piece
+                        .trimmed(clip.end(), piece.end())
+                        .map(|p| (p, original)),
+                );
+            } else {
+                below.push((piece, original));
+            }
+        }
+        below.push((clip.clone(), index));
+        pieces = below;
+    }
+    pieces.sort_by_key(|(piece, _)| piece.start);
+
+    let mut crossfades = Vec::new();
+    let default_length = DEFAULT_CROSSFADE_MS * sample_rate as u64 / 1000;
+    for i in 1..pieces.len() {
+        let (before, after) = pieces.split_at_mut(i);
+        let ((previous, before_index), (next, next_index)) = (&mut before[i - 1], &mut after[0]);
+        let (before_clip, next_clip) = (&clips[*before_index], &clips[*next_index]);
+        if next.start != previous.end() || (next.crossfade.is_none() && next.continues(previous)) {
+            continue;
+        }
+
+        let (join, length) = if next.start == next_clip.start
+            && before_clip.end() > next.start
+            && before_clip.end() <= next_clip.end()
+        {
+            (
+                (next.start + before_clip.end()) / 2,
+                before_clip.end() - next.start,
+            )
+        } else {
+            (next.start, default_length)
+        };
+        let fade = next.crossfade.unwrap_or(Fade {
+            length,
+            curve: FadeCurve::EqualPower,
+        });
+        let start = join
+            .saturating_sub(fade.length / 2)
+            .max(next.start.saturating_sub(next.room_before()))
+            .max(previous.start + previous.crossfade_in.length);
+        let end = (join + fade.length - fade.length / 2)
+            .min(previous.end() + previous.room_after())
+            .min(next.end());
+        if start >= end {
+            continue;
+        }
+
+        let (mut out, mut into) = (previous.clone(), next.clone());
+        if out.set_bounds(out.start, end).is_err() || into.set_bounds(start, into.end()).is_err() {
+            continue;
+        }
+        let fade = Fade {
+            length: end - start,
+            curve: fade.curve,
+        };
+        out.crossfade_out = fade;
+        into.crossfade_in = fade;
+        *previous = out;
+        *next = into;
+        crossfades.push(Crossfade {
+            clip_id: next.id,
+            start,
+            end,
+            curve: fade.curve,
+        });
+    }
+
+    // The clip's fades belong to the pieces that hold its edges.
+    let pieces = pieces
+        .into_iter()
+        .map(|(mut piece, index)| {
+            let clip = &clips[index];
+            (piece.fade_in, piece.reach_before) = if piece.start <= clip.start {
+                (clip.fade_in, clip.start - piece.start)
+            } else {
+                (Fade::none(), 0)
+            };
+            (piece.fade_out, piece.reach_after) = if piece.end() >= clip.end() {
+                (clip.fade_out, piece.end() - clip.end())
+            } else {
+                (Fade::none(), 0)
+            };
+            piece
+        })
+        .collect();
+    (pieces, crossfades)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClipRaw {
+    pub name: String,
+    pub source: track::sources::source::AudioSourceRaw,
+    pub start: u64,
+    pub offset: u64,
+    pub length: u64,
+    pub gain: f32,
+    pub reversed: bool,
+    pub fade_in: Fade,
+    pub fade_out: Fade,
+    pub crossfade: Option<Fade>,
+}
+
+impl ClipRaw {
+    pub fn rescale(&mut self, from: u32, to: u32) {
+        self.start = project::transport::rescale_frames(self.start, from, to);
+        self.offset = project::transport::rescale_frames(self.offset, from, to);
+        self.length = project::transport::rescale_frames(self.length, from, to);
+        self.fade_in = self.fade_in.rescale(from, to);
+        self.fade_out = self.fade_out.rescale(from, to);
+        self.crossfade = self.crossfade.map(|fade| fade.rescale(from, to));
+    }
+}
+
+impl From<&Clip> for ClipRaw {
+    fn from(value: &Clip) -> Self {
+        ClipRaw {
+            name: value.name.clone(),
+            source: value.source.kind(),
+            start: value.start,
+            offset: value.offset,
+            length: value.length,
+            gain: value.gain,
+            reversed: value.reversed,
+            fade_in: value.fade_in,
+            fade_out: value.fade_out,
+            crossfade: value.crossfade,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClipInfo {
+    pub id: u64,
+    pub name: String,
+    pub start: u64,
+    pub offset: u64,
+    pub length: u64,
+    pub gain: f32,
+    pub reversed: bool,
+    pub fade_in: Fade,
+    pub fade_out: Fade,
+    pub crossfade: Option<Fade>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::sources::source::FileSource;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    // Every frame of the source is its own index, so reads can be traced.
+    fn ramp(length: usize) -> Arc<FileSource> {
+        Arc::new(FileSource::from_channels(
+            "ramp.wav",
+            vec![(0..length).map(|i| i as f32).collect()],
+        ))
+    }
+
+    fn constant(length: usize) -> Arc<FileSource> {
+        Arc::new(FileSource::from_channels(
+            "constant.wav",
+            vec![vec![0.5; length]],
+        ))
+    }
+
+    // A clip of `source` from `start` to `end` on the timeline, with the
+    // source's first frame at `anchor`.
+    fn clip(source: Arc<FileSource>, anchor: u64, start: u64, end: u64) -> Clip {
+        let mut clip = Clip::new("clip", source, anchor);
+        clip.set_bounds(start, end).unwrap();
+        clip
+    }
+
+    fn render(clips: &[Clip], frames: usize) -> (Vec<f32>, Vec<Crossfade>) {
+        let (pieces, crossfades) = apply_crossfades(clips, SAMPLE_RATE);
+        let mut left = vec![0.0; frames];
+        let mut right = vec![0.0; frames];
+        for piece in &pieces {
+            piece.mix_into(0, &mut left, &mut right);
+        }
+        (left, crossfades)
+    }
+
+    // Outside the crossfades exactly one clip plays at a time.
+    fn assert_single_layer(mix: &[f32], crossfades: &[Crossfade], end: usize) {
+        for (frame, sample) in mix.iter().enumerate().take(end) {
+            let fading = crossfades
+                .iter()
+                .any(|fade| (fade.start..fade.end).contains(&(frame as u64)));
+            if !fading {
+                assert!((sample - 0.5).abs() < 1e-6, "frame {} is {}", frame, sample);
+            }
+        }
+    }
+
+    #[test]
+    fn split_keeps_the_audio_in_place() {
+        let mut before = Clip::new("ramp", ramp(100), 1000);
+        before
+            .set_fades(
+                Fade {
+                    length: 10,
+                    curve: FadeCurve::Linear,
+                },
+                Fade {
+                    length: 10,
+                    curve: FadeCurve::Linear,
+                },
+            )
+            .unwrap();
+        let after = before.split(1040).unwrap();
+
+        assert_eq!((before.start, before.offset, before.length), (1000, 0, 40));
+        assert_eq!((after.start, after.offset, after.length), (1040, 40, 60));
+        assert_ne!(before.id, after.id);
+        assert_eq!(before.fade_in.length, 10);
+        assert_eq!(before.fade_out, Fade::none());
+        assert_eq!(after.fade_in, Fade::none());
+        assert_eq!(after.fade_out.length, 10);
+        assert!(before.split(1000).is_err());
+        assert!(before.split(1040).is_err());
+    }
+
+    #[test]
+    fn split_reversed_clip_keeps_the_audio_in_place() {
+        let mut before = Clip::new("ramp", ramp(100), 0);
+        before.reversed = true;
+        let after = before.split(30).unwrap();
+
+        // The first 30 frames play the end of the source backwards.
+        assert_eq!((before.offset, before.length), (70, 30));
+        assert_eq!((after.offset, after.length), (0, 70));
+    }
+
+    #[test]
+    fn trimmed_cuts_to_the_range_and_keeps_the_id() {
+        let clip = Clip::new("ramp", ramp(100), 1000);
+        let trimmed = clip.trimmed(1020, 1030).unwrap();
+
+        assert_eq!(trimmed.id, clip.id);
+        assert_eq!(
+            (trimmed.start, trimmed.offset, trimmed.length),
+            (1020, 20, 10)
+        );
+        assert_eq!(clip.trimmed(0, 2000).unwrap().length, 100);
+        assert!(clip.trimmed(2000, 3000).is_none());
+    }
+
+    #[test]
+    fn set_bounds_stops_at_the_source() {
+        let mut clip = Clip::new("ramp", ramp(100), 1000);
+        assert!(clip.set_bounds(999, 1100).is_err());
+        assert!(clip.set_bounds(1000, 1101).is_err());
+        assert!(clip.set_bounds(1010, 1010).is_err());
+        assert_eq!((clip.start, clip.length), (1000, 100));
+    }
+
+    #[test]
+    fn butt_joined_clips_crossfade_around_the_join() {
+        let first = clip(constant(20_000), 0, 0, 10_000);
+        let second = clip(constant(20_000), 5_000, 10_000, 20_000);
+        let (mix, crossfades) = render(&[first, second.clone()], 20_000);
+
+        assert_eq!(crossfades.len(), 1);
+        assert_eq!(crossfades[0].clip_id, second.id);
+        assert_eq!((crossfades[0].start, crossfades[0].end), (9_760, 10_240));
+        assert_single_layer(&mix, &crossfades, 20_000);
+        // Equal power, both clips are 3 dB down in the middle.
+        let centre = 2.0 * 0.5 * FadeCurve::EqualPower.gain(0.5);
+        assert!((mix[10_000] - centre).abs() < 1e-2);
+    }
+
+    #[test]
+    fn default_crossfade_follows_the_sample_rate() {
+        let clips = [
+            clip(constant(20_000), 0, 0, 10_000),
+            clip(constant(20_000), 5_000, 10_000, 20_000),
+        ];
+        let length = |sample_rate| {
+            let (_, crossfades) = apply_crossfades(&clips, sample_rate);
+            crossfades[0].end - crossfades[0].start
+        };
+
+        assert_eq!(length(48_000), 480);
+        assert_eq!(length(96_000), 960);
+    }
+
+    #[test]
+    fn split_halves_play_through_without_a_crossfade() {
+        let mut first = Clip::new("constant", constant(20_000), 0);
+        let second = first.split(10_000).unwrap();
+        let (mix, crossfades) = render(&[first, second], 20_000);
+
+        assert!(crossfades.is_empty());
+        assert_single_layer(&mix, &crossfades, 20_000);
+    }
+
+    #[test]
+    fn overlap_fades_across_the_overlap() {
+        let first = clip(constant(20_000), 0, 0, 10_000);
+        let second = clip(constant(20_000), 8_000, 8_000, 20_000);
+        let (mix, crossfades) = render(&[first, second], 20_000);
+
+        assert_eq!(crossfades.len(), 1);
+        assert_eq!((crossfades[0].start, crossfades[0].end), (8_000, 10_000));
+        assert_single_layer(&mix, &crossfades, 20_000);
+    }
+
+    #[test]
+    fn contained_clip_is_not_doubled() {
+        let outer = Clip::new("outer", constant(48_000), 0);
+        let inner = Clip::new("inner", constant(10_000), 20_000);
+        let (mix, crossfades) = render(&[outer.clone(), inner], 48_000);
+
+        // Out of the outer clip into the inner one and back.
+        assert_eq!(crossfades.len(), 2);
+        assert_eq!(crossfades[1].clip_id, outer.id);
+        assert_single_layer(&mix, &crossfades, 48_000);
+    }
+
+    #[test]
+    fn later_clip_with_the_same_start_plays_on_top() {
+        let under = Clip::new("under", constant(20_000), 0);
+        let over = Clip::new("over", constant(10_000), 0);
+        let (mix, crossfades) = render(&[under.clone(), over], 20_000);
+
+        assert_eq!(crossfades.len(), 1);
+        assert_eq!(crossfades[0].clip_id, under.id);
+        assert_single_layer(&mix, &crossfades, 20_000);
+    }
+
+    #[test]
+    fn clip_fades_stay_on_top_of_the_crossfade() {
+        let mut first = clip(constant(20_000), 0, 0, 10_000);
+        first
+            .set_fades(
+                Fade::none(),
+                Fade {
+                    length: 1_000,
+                    curve: FadeCurve::Linear,
+                },
+            )
+            .unwrap();
+        let second = clip(constant(20_000), 5_000, 10_000, 20_000);
+        let (alone, _) = render(std::slice::from_ref(&first), 20_000);
+        let (mix, crossfades) = render(&[first, second], 20_000);
+
+        // Before the crossfade only the fade out plays.
+        assert_eq!(mix[9_500], alone[9_500]);
+        assert!(alone[9_999].abs() < 1e-6);
+        // Past its edge the faded out clip stays silent, only the second
+        // clip fades in.
+        let frame = 10_100;
+        let into =
+            (frame - crossfades[0].start) as f32 / (crossfades[0].end - crossfades[0].start) as f32;
+        let expected = 0.5 * FadeCurve::EqualPower.gain(into);
+        assert!((mix[frame as usize] - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn raw_clip_moves_to_a_new_sample_rate() {
+        let mut raw = ClipRaw::from(&clip(ramp(96_000), 48_000, 72_000, 96_000));
+        raw.fade_in.length = 4_800;
+        raw.rescale(48_000, 96_000);
+
+        assert_eq!(
+            (raw.start, raw.offset, raw.length),
+            (144_000, 48_000, 48_000)
+        );
+        assert_eq!(raw.fade_in.length, 9_600);
+
+        raw.length = u64::MAX;
+        raw.rescale(96_000, 44_100);
+        assert_eq!((raw.start, raw.length), (66_150, u64::MAX));
+    }
+
+    #[test]
+    fn clips_compare_by_what_they_play() {
+        let original = clip(ramp(100), 0, 10, 90);
+        let mut edited = original.clone();
+        assert!(edited == original);
+
+        edited.set_bounds(10, 90).unwrap();
+        assert!(edited == original);
+        edited.set_bounds(20, 90).unwrap();
+        assert!(edited != original);
+        assert!(original.duplicate(original.start) != original);
+    }
+}
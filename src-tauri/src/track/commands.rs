@@ -1,4 +1,7 @@
-use tauri::{AppHandle, Manager};
+use std::{mem, path::PathBuf, sync::Arc};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
 use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 
 use crate::{project, track};
@@ -10,7 +13,7 @@ pub fn get_track_list(app_handle: AppHandle) -> Result<track::track_list::TrackL
     let result = if let Ok(state_mixer) = state_mixer_guard.0.lock() {
         if let Ok(master_out) = state_mixer.master_out.lock() {
             if let Ok(list) = state_mixer.track_list.lock() {
-                let mut response = list.as_response();
+                let mut response = list.as_response(state_mixer.transport.sample_rate());
                 response.tracks.insert(0, master_out.as_response());
                 Ok(response)
             } else {
@@ -53,31 +56,17 @@ pub fn update_track(
     update: track::track_list::TrackUpdate,
 ) {
     let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
-    if let Ok(state_mixer) = state_mixer_guard.0.lock() {
-        if track_name == "master-out" {
-            if let Ok(mut output) = state_mixer.master_out.lock() {
-                match update {
-                    track::track_list::TrackUpdate::Pan(pan) => output.pan = pan,
-                    track::track_list::TrackUpdate::Gain(gain) => output.gain = gain,
-                    _ => (),
-                }
-            } else {
-                app_handle
-                    .dialog()
-                    .message("Failed to lock master out")
-                    .title("Master Out Error")
-                    .kind(MessageDialogKind::Warning)
-                    .buttons(MessageDialogButtons::Ok)
-                    .blocking_show();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        match state_mixer.update_track(&track_name, update) {
+            Ok(true) => {
+                let _ = app_handle.emit("updated-track-list", ());
             }
-        } else {
-            if let Ok(mut list) = state_mixer.track_list.lock() {
-                list.update_track(&track_name, update);
-            } else {
+            Ok(false) => {}
+            Err(message) => {
                 app_handle
                     .dialog()
-                    .message("Failed to lock track list")
-                    .title("Track List Error")
+                    .message(message)
+                    .title("Track Error")
                     .kind(MessageDialogKind::Warning)
                     .buttons(MessageDialogButtons::Ok)
                     .blocking_show();
@@ -85,3 +74,750 @@ pub fn update_track(
         }
     };
 }
+
+#[tauri::command]
+pub fn import_clip(
+    app_handle: AppHandle,
+    track_name: String,
+    path: String,
+    start: u64,
+) -> Result<track::clips::ClipInfo, ()> {
+    let sample_rate = {
+        let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+        let state_mixer = state_mixer_guard.0.lock().map_err(|_| ())?;
+        state_mixer.transport.sample_rate()
+    };
+    let path = PathBuf::from(path);
+    let name = path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let source = match track::sources::source::FileSource::new(path, sample_rate) {
+        Ok(source) => Arc::new(source),
+        Err(e) => {
+            app_handle
+                .dialog()
+                .message(format!("Failed to open {}: {}", name, e))
+                .title("File Error")
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::Ok)
+                .blocking_show();
+            return Err(());
+        }
+    };
+
+    let clip = track::clips::Clip::new(&name, source, start);
+    let info = clip.as_info();
+    edit_track(&app_handle, &track_name, |track| {
+        track.add_clip(clip.clone());
+        Ok((
+            info,
+            Some(project::history::Edit::AddClip {
+                track: track_name.clone(),
+                clip,
+            }),
+        ))
+    })
+}
+
+#[tauri::command]
+pub fn move_clip(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    start: u64,
+) -> Result<(), ()> {
+    edit_track(&app_handle, &track_name, |track| {
+        let from = track
+            .move_clip(clip_id, start)
+            .ok_or(format!("Clip {} not found on {}", clip_id, track_name))?;
+        let edit = (from != start).then(|| project::history::Edit::MoveClip {
+            track: track_name.clone(),
+            clip_id,
+            from,
+            to: start,
+        });
+        Ok(((), edit))
+    })
+}
+
+#[tauri::command]
+pub fn remove_clip(app_handle: AppHandle, track_name: String, clip_id: u64) -> Result<(), ()> {
+    edit_track(&app_handle, &track_name, |track| {
+        let clip = track
+            .remove_clip(clip_id)
+            .ok_or(format!("Clip {} not found on {}", clip_id, track_name))?;
+        Ok((
+            (),
+            Some(project::history::Edit::RemoveClip {
+                track: track_name.clone(),
+                clip,
+            }),
+        ))
+    })
+}
+
+// Runs `f` on a track as an undoable edit.
+fn edit_track<T>(
+    app_handle: &AppHandle,
+    track_name: &str,
+    f: impl FnOnce(
+        &mut track::tracks::InputTrack,
+    ) -> Result<(T, Option<project::history::Edit>), String>,
+) -> Result<T, ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_track(track_name, f)
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(app_handle, "Clip Error", result)
+}
+
+// Runs `f` on one clip of a track, with the rest of its clips around it, as an
+// undoable edit.
+fn edit_clip<T>(
+    app_handle: &AppHandle,
+    track_name: &str,
+    clip_id: u64,
+    f: impl FnOnce(&mut Vec<track::clips::Clip>, usize) -> Result<T, String>,
+) -> Result<T, ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_clips(track_name, |clips| {
+            let index = clips
+                .iter()
+                .position(|clip| clip.id == clip_id)
+                .ok_or(format!("Clip {} not found on {}", clip_id, track_name))?;
+            f(clips, index)
+        })
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(app_handle, "Clip Error", result)
+}
+
+// Returns the part after `position`, the clip keeps the part before it.
+#[tauri::command]
+pub fn split_clip(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    position: u64,
+) -> Result<track::clips::ClipInfo, ()> {
+    edit_clip(&app_handle, &track_name, clip_id, |clips, index| {
+        let after = clips[index].split(position)?;
+        let info = after.as_info();
+        clips.push(after);
+        Ok(info)
+    })
+}
+
+// Moves the edges of a clip, the audio stays where it is on the timeline.
+#[tauri::command]
+pub fn trim_clip(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    start: u64,
+    end: u64,
+) -> Result<(), ()> {
+    edit_clip(&app_handle, &track_name, clip_id, |clips, index| {
+        clips[index].set_bounds(start, end)
+    })
+}
+
+// The copy goes right after the clip unless `start` is given.
+#[tauri::command]
+pub fn duplicate_clip(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    start: Option<u64>,
+) -> Result<track::clips::ClipInfo, ()> {
+    edit_clip(&app_handle, &track_name, clip_id, |clips, index| {
+        let copy = clips[index].duplicate(start.unwrap_or(clips[index].end()));
+        let info = copy.as_info();
+        clips.push(copy);
+        Ok(info)
+    })
+}
+
+#[tauri::command]
+pub fn slip_clip(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    offset: u64,
+) -> Result<(), ()> {
+    edit_clip(&app_handle, &track_name, clip_id, |clips, index| {
+        clips[index].slip(offset)
+    })
+}
+
+#[tauri::command]
+pub fn set_clip_gain(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    gain: f32,
+) -> Result<(), ()> {
+    edit_clip(&app_handle, &track_name, clip_id, |clips, index| {
+        clips[index].set_gain(gain)
+    })
+}
+
+#[tauri::command]
+pub fn reverse_clip(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    reversed: bool,
+) -> Result<(), ()> {
+    edit_clip(&app_handle, &track_name, clip_id, |clips, index| {
+        clips[index].reversed = reversed;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn set_clip_fades(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    fade_in: track::clips::Fade,
+    fade_out: track::clips::Fade,
+) -> Result<(), ()> {
+    edit_clip(&app_handle, &track_name, clip_id, |clips, index| {
+        clips[index].set_fades(fade_in, fade_out)
+    })
+}
+
+// Sets the crossfade from the clip before into this one, `None` goes back to
+// the automatic one.
+#[tauri::command]
+pub fn set_crossfade(
+    app_handle: AppHandle,
+    track_name: String,
+    clip_id: u64,
+    crossfade: Option<track::clips::Fade>,
+) -> Result<(), ()> {
+    edit_clip(&app_handle, &track_name, clip_id, |clips, index| {
+        clips[index].set_crossfade(crossfade)
+    })
+}
+
+#[tauri::command]
+pub fn select_take(
+    app_handle: AppHandle,
+    track_name: String,
+    folder_id: u64,
+    take: usize,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_take_folder(&track_name, folder_id, |folder| folder.select(take))
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Take Error", result)
+}
+
+// Replaces the comp of a take folder with regions picked from its takes.
+#[tauri::command]
+pub fn set_comp(
+    app_handle: AppHandle,
+    track_name: String,
+    folder_id: u64,
+    regions: Vec<track::takes::CompRegion>,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_take_folder(&track_name, folder_id, |folder| folder.set_comp(regions))
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Take Error", result)
+}
+
+#[tauri::command]
+pub fn set_pan_law(app_handle: AppHandle, pan_law: track::mixer::PanLaw) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let before = state_mixer
+            .track_list
+            .lock()
+            .map(|mut list| mem::replace(&mut list.pan_law, pan_law))
+            .ok();
+        if let Some(before) = before {
+            state_mixer.record_edit(project::history::Edit::PanLaw {
+                before,
+                after: pan_law,
+            });
+        }
+    };
+}
+
+#[tauri::command]
+pub fn set_solo_mode(app_handle: AppHandle, solo_mode: track::mixer::SoloMode) {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let before = state_mixer
+            .track_list
+            .lock()
+            .map(|mut list| mem::replace(&mut list.solo_mode, solo_mode))
+            .ok();
+        if let Some(before) = before {
+            state_mixer.record_edit(project::history::Edit::SoloMode {
+                before,
+                after: solo_mode,
+            });
+        }
+    };
+}
+
+fn show_result<T>(app_handle: &AppHandle, title: &str, result: Result<T, String>) -> Result<T, ()> {
+    match result {
+        Ok(value) => {
+            let _ = app_handle.emit("updated-track-list", ());
+            Ok(value)
+        }
+        Err(message) => {
+            app_handle
+                .dialog()
+                .message(message)
+                .title(title)
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::Ok)
+                .blocking_show();
+            Err(())
+        }
+    }
+}
+
+#[tauri::command]
+pub fn add_insert(
+    app_handle: AppHandle,
+    track_name: String,
+    kind: track::processors::processor::ProcessorKind,
+    index: Option<usize>,
+) -> Result<u64, ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let insert =
+            track::processors::chain::Insert::new(kind, state_mixer.transport.sample_rate());
+        let id = insert.id;
+        state_mixer
+            .edit_inserts(&track_name, |chain| {
+                chain.add(insert, index);
+                true
+            })
+            .map(|_| id)
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Insert Error", result)
+}
+
+#[tauri::command]
+pub fn remove_insert(app_handle: AppHandle, track_name: String, insert_id: u64) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_inserts(&track_name, |chain| chain.remove(insert_id).is_some())
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Insert Error", result)
+}
+
+#[tauri::command]
+pub fn move_insert(
+    app_handle: AppHandle,
+    track_name: String,
+    insert_id: u64,
+    index: usize,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_inserts(&track_name, |chain| chain.move_to(insert_id, index))
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Insert Error", result)
+}
+
+#[tauri::command]
+pub fn set_insert_bypass(
+    app_handle: AppHandle,
+    track_name: String,
+    insert_id: u64,
+    bypass: bool,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_inserts(&track_name, |chain| match chain.get_mut(insert_id) {
+            Some(insert) => {
+                insert.bypass = bypass;
+                true
+            }
+            None => false,
+        })
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Insert Error", result)
+}
+
+// Swaps in a copy of the insert that reads `path`, so undo restores the
+// previous file.
+#[tauri::command]
+pub fn load_insert_file(
+    app_handle: AppHandle,
+    track_name: String,
+    insert_id: u64,
+    path: String,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let sample_rate = state_mixer.transport.sample_rate();
+        let insert = match (state_mixer.track_list.lock(), state_mixer.master_out.lock()) {
+            (Ok(track_list), Ok(mut master_out)) => {
+                project::history::with_chain(&track_list, &mut master_out, &track_name, |chain| {
+                    chain.get(insert_id).cloned()
+                })
+                .flatten()
+            }
+            _ => None,
+        };
+
+        match insert.map(|insert| insert.with_file(PathBuf::from(path), sample_rate)) {
+            Some(Ok(loaded)) => {
+                state_mixer.edit_inserts(&track_name, |chain| match chain.get_mut(insert_id) {
+                    Some(insert) => {
+                        *insert = loaded;
+                        true
+                    }
+                    None => false,
+                })
+            }
+            Some(Err(message)) => Err(message),
+            None => Err("Insert not found".to_string()),
+        }
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Insert Error", result)
+}
+
+// Feeds an insert the signal of another track as its sidechain key, or its
+// own input when `key` is `None`.
+#[tauri::command]
+pub fn set_insert_key(
+    app_handle: AppHandle,
+    track_name: String,
+    insert_id: u64,
+    key: Option<String>,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let valid = match (&key, state_mixer.track_list.lock()) {
+            (None, _) => Ok(()),
+            (Some(key), Ok(track_list)) => track_list.check_key(&track_name, key),
+            (Some(_), Err(_)) => Err("Failed to lock track list".to_string()),
+        };
+        valid.and_then(|_| {
+            state_mixer.edit_inserts(&track_name, |chain| match chain.get_mut(insert_id) {
+                Some(insert) => {
+                    insert.key = key;
+                    true
+                }
+                None => false,
+            })
+        })
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Routing Error", result)
+}
+
+// Doesn't announce the change, the UI already shows the value it sent.
+#[tauri::command]
+pub fn set_insert_parameter(
+    app_handle: AppHandle,
+    track_name: String,
+    insert_id: u64,
+    parameter: String,
+    value: f32,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.set_insert_parameter(&track_name, insert_id, &parameter, value)
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    // Parameter moves come in fast, so only a failure goes through
+    // `show_result` and the track list isn't refreshed for each one.
+    result.or_else(|message| show_result(&app_handle, "Insert Error", Err(message)))
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ResponsePoint {
+    pub frequency: f32,
+    pub gain: f32,
+}
+
+// Log spaced from 20 Hz to 20 kHz, for drawing an EQ curve.
+#[tauri::command]
+pub fn get_frequency_response(
+    app_handle: AppHandle,
+    track_name: String,
+    insert_id: u64,
+    points: Option<usize>,
+) -> Result<Vec<ResponsePoint>, ()> {
+    let points = points.unwrap_or(256).max(2);
+    let frequencies = (0..points)
+        .map(|i| 20.0 * 1000.0_f32.powf(i as f32 / (points - 1) as f32))
+        .collect::<Vec<_>>();
+
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let response = if let Ok(state_mixer) = state_mixer_guard.0.lock() {
+        let sample_rate = state_mixer.transport.sample_rate();
+        match (state_mixer.track_list.lock(), state_mixer.master_out.lock()) {
+            (Ok(track_list), Ok(mut master_out)) => {
+                project::history::with_chain(&track_list, &mut master_out, &track_name, |chain| {
+                    chain
+                        .get(insert_id)
+                        .and_then(|insert| insert.frequency_response(&frequencies, sample_rate))
+                })
+                .flatten()
+            }
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    match response {
+        Some(gains) => Ok(frequencies
+            .into_iter()
+            .zip(gains)
+            .map(|(frequency, gain)| ResponsePoint { frequency, gain })
+            .collect()),
+        None => {
+            eprintln!(
+                "Insert {} on {} has no frequency response",
+                insert_id, track_name
+            );
+            Err(())
+        }
+    }
+}
+
+// Adds a send to `bus` or updates the one already there.
+#[tauri::command]
+pub fn set_send(
+    app_handle: AppHandle,
+    track_name: String,
+    bus: String,
+    level: f32,
+    pre_fader: bool,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let valid = match state_mixer.track_list.lock() {
+            Ok(track_list) => {
+                let current = track_list
+                    .get_track(&track_name)
+                    .and_then(|track| {
+                        let track = track.lock().ok()?;
+                        Some((track.output.clone(), track.sends.clone()))
+                    })
+                    .ok_or(format!("Track {} not found", track_name));
+                current.and_then(|(output, mut sends)| {
+                    track::sends::set_send(
+                        &mut sends,
+                        track::sends::AuxSend::new(&bus, level, pre_fader),
+                    );
+                    track_list.check_routing(&track_name, output.as_deref(), &sends)
+                })
+            }
+            Err(_) => Err("Failed to lock track list".to_string()),
+        };
+        valid.and_then(|_| {
+            state_mixer.edit_sends(&track_name, |sends| {
+                track::sends::set_send(sends, track::sends::AuxSend::new(&bus, level, pre_fader));
+                Ok(())
+            })
+        })
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Send Error", result)
+}
+
+#[tauri::command]
+pub fn remove_send(app_handle: AppHandle, track_name: String, bus: String) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_sends(&track_name, |sends| {
+            if track::sends::remove_send(sends, &bus) {
+                Ok(())
+            } else {
+                Err(format!("{} has no send to {}", track_name, bus))
+            }
+        })
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Send Error", result)
+}
+
+// Routes a track into a bus, or the master when `output` is `None`.
+#[tauri::command]
+pub fn set_track_output(
+    app_handle: AppHandle,
+    track_name: String,
+    output: Option<String>,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        let valid = match state_mixer.track_list.lock() {
+            Ok(track_list) => track_list
+                .get_track(&track_name)
+                .and_then(|track| track.lock().ok().map(|track| track.sends.clone()))
+                .ok_or(format!("Track {} not found", track_name))
+                .and_then(|sends| track_list.check_routing(&track_name, output.as_deref(), &sends)),
+            Err(_) => Err("Failed to lock track list".to_string()),
+        };
+        valid.and_then(|_| {
+            state_mixer
+                .update_track(&track_name, track::track_list::TrackUpdate::Output(output))
+                .map(|_| ())
+        })
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Routing Error", result)
+}
+
+// Replaces the breakpoints of one lane, an empty list removes the lane.
+#[tauri::command]
+pub fn set_automation_points(
+    app_handle: AppHandle,
+    track_name: String,
+    target: track::automation::AutomationTarget,
+    points: Vec<track::automation::Breakpoint>,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_automation(&track_name, |automation| {
+            automation.set_lane(target, points)
+        })
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Automation Error", result)
+}
+
+// Called when the UI lets go of a control, ends its touch pass.
+#[tauri::command]
+pub fn release_automation(
+    app_handle: AppHandle,
+    track_name: String,
+    target: track::automation::AutomationTarget,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.release_automation(&track_name, &target)
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(&app_handle, "Automation Error", result)
+}
+
+fn edit_groups(
+    app_handle: &AppHandle,
+    f: impl FnOnce(
+        &track::track_list::TrackList,
+        &mut Vec<track::track_list::TrackGroup>,
+    ) -> Result<(), String>,
+) -> Result<(), ()> {
+    let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+    let result = if let Ok(mut state_mixer) = state_mixer_guard.0.lock() {
+        state_mixer.edit_groups(f)
+    } else {
+        Err("Failed to lock state mixer".to_string())
+    };
+    show_result(app_handle, "Group Error", result)
+}
+
+fn track_gain(track_list: &track::track_list::TrackList, name: &str) -> f32 {
+    track_list
+        .get_track(name)
+        .and_then(|track| track.lock().ok().map(|track| track.gain))
+        .unwrap_or(1.0)
+}
+
+// Creates a group, members move over from any group they were in.
+#[tauri::command]
+pub fn create_group(app_handle: AppHandle, name: String, members: Vec<String>) -> Result<(), ()> {
+    edit_groups(&app_handle, |track_list, groups| {
+        if groups.iter().any(|group| group.name == name) {
+            return Err(format!("Group {} already exists", name));
+        }
+        if let Some(missing) = members.iter().find(|m| track_list.get_track(m).is_none()) {
+            return Err(format!("Track {} not found", missing));
+        }
+        for group in groups.iter_mut() {
+            members.iter().for_each(|member| group.leave(member));
+        }
+        let mut group = track::track_list::TrackGroup::new(&name);
+        for member in &members {
+            group.join(member, track_gain(track_list, member));
+        }
+        groups.push(group);
+        Ok(())
+    })
+}
+
+// Ungroups the members, their settings stay as they are.
+#[tauri::command]
+pub fn remove_group(app_handle: AppHandle, name: String) -> Result<(), ()> {
+    edit_groups(&app_handle, |_, groups| {
+        let length = groups.len();
+        groups.retain(|group| group.name != name);
+        if groups.len() == length {
+            return Err(format!("Group {} not found", name));
+        }
+        Ok(())
+    })
+}
+
+// Moves a track into `group`, or out of any group when `None`. Empty groups
+// are kept so they can be filled again.
+#[tauri::command]
+pub fn set_track_group(
+    app_handle: AppHandle,
+    track_name: String,
+    group: Option<String>,
+) -> Result<(), ()> {
+    edit_groups(&app_handle, |track_list, groups| {
+        if track_list.get_track(&track_name).is_none() {
+            return Err(format!("Track {} not found", track_name));
+        }
+        if let Some(group) = &group {
+            if !groups.iter().any(|g| g.name == *group) {
+                return Err(format!("Group {} not found", group));
+            }
+        }
+        let gain = track_gain(track_list, &track_name);
+        for g in groups.iter_mut() {
+            g.leave(&track_name);
+            if Some(&g.name) == group.as_ref() {
+                g.join(&track_name, gain);
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,534 @@
+use std::{
+    collections::HashSet,
+    mem,
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+};
+
+use crate::{track, types};
+
+pub const MAX_BLOCK_SIZE: usize = 4096;
+const GRAPH_QUEUE_SIZE: usize = 1024;
+// Replaced graphs the audio thread holds on to while `garbage` is full.
+const PARKED_GRAPHS: usize = 16;
+
+// The newest graph when the queue was full. The audio thread takes it once
+// it has read the queue, a newer publish replaces it.
+type PendingGraph = Arc<Mutex<Option<Box<Graph>>>>;
+
+pub struct LiveInput {
+    pub ring_buffer: Arc<types::RingBuffer>,
+    pub channels: usize,
+}
+
+pub struct SendNode {
+    pub bus: usize,
+    pub level: f32,
+    pub pre_fader: bool,
+}
+
+// Parameter automation, by the index of its insert in the chain.
+pub struct ParameterLane {
+    pub insert: usize,
+    pub parameter: String,
+    pub lane: track::automation::AutomationLane,
+}
+
+pub struct TrackNode {
+    // Index into `Graph::bus_buffers` this node reads from when it's a bus.
+    pub bus: Option<usize>,
+    // Bus the track sums into, the master when `None`.
+    pub output: Option<usize>,
+    // Index into `Graph::key_buffers` this node writes to when other inserts
+    // key from it.
+    pub key_tap: Option<usize>,
+    pub input: Option<LiveInput>,
+    pub clips: Vec<track::clips::Clip>,
+    pub inserts: track::processors::chain::InsertChainNode,
+    // Key buffer of each insert, in the same order as the inserts.
+    pub insert_keys: Vec<Option<usize>>,
+    pub sends: Vec<SendNode>,
+    pub gain: f32,
+    pub pan: f32,
+    pub mute: bool,
+    // Also set when the solo is implied by a soloed track or bus it's routed
+    // with.
+    pub solo: bool,
+    pub solo_safe: bool,
+    // Lanes the track follows instead of its controls.
+    pub gain_lane: Option<track::automation::AutomationLane>,
+    pub pan_lane: Option<track::automation::AutomationLane>,
+    pub mute_lane: Option<track::automation::AutomationLane>,
+    pub parameter_lanes: Vec<ParameterLane>,
+}
+
+// A snapshot of everything the audio thread needs to render the mix, only its
+// buffers and processors change once published. The UI side builds a new one
+// after every edit and hands it over whole, so the callback never has to lock
+// the track list. Tracks are in processing order, buses after every track that feeds
+// them.
+pub struct Graph {
+    pub tracks: Vec<TrackNode>,
+    // Where sends sum into, one stereo pair of `MAX_BLOCK_SIZE` per bus.
+    pub bus_buffers: Vec<[Vec<f32>; 2]>,
+    // Post-insert, pre-fader signal of every track something keys from.
+    pub key_buffers: Vec<[Vec<f32>; 2]>,
+    pub master_inserts: track::processors::chain::InsertChainNode,
+    pub master_insert_keys: Vec<Option<usize>>,
+    // Runs after the master fader and is never bypassed, `None` only before
+    // the first graph arrives.
+    pub master_limiter: Option<track::processors::chain::InsertNode>,
+    pub master_gain: f32,
+    pub master_pan: f32,
+    pub pan_law: track::mixer::PanLaw,
+    pub any_solo: bool,
+    // `None` when rendering offline, the click never goes into a mixdown.
+    pub metronome: Option<track::metronome::MetronomeNode>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Graph {
+            tracks: Vec::new(),
+            bus_buffers: Vec::new(),
+            key_buffers: Vec::new(),
+            master_inserts: track::processors::chain::InsertChainNode::default(),
+            master_insert_keys: Vec::new(),
+            master_limiter: None,
+            master_gain: 1.0,
+            master_pan: 0.0,
+            pan_law: track::mixer::PanLaw::ConstantPower,
+            any_solo: false,
+            metronome: None,
+        }
+    }
+
+    // Inserts whose instance is in `running` come without a processor, the
+    // engine hands theirs over from the graph this one replaces.
+    pub fn build(
+        track_list: &track::track_list::TrackList,
+        master_out: &track::tracks::OutputTrack,
+        running: &HashSet<u64>,
+        sample_rate: u32,
+    ) -> Self {
+        let tracks = track_list.get_tracks();
+        let tracks = tracks
+            .iter()
+            .filter_map(|track| track.lock().ok())
+            .collect::<Vec<_>>();
+        let buses = tracks
+            .iter()
+            .filter(|track| track.kind == track::tracks::TrackKind::Bus)
+            .map(|track| track.name.clone())
+            .collect::<Vec<_>>();
+        let mut keys = tracks
+            .iter()
+            .flat_map(|track| track.inserts.inserts.iter())
+            .chain(master_out.inserts.inserts.iter())
+            .filter_map(|insert| insert.key.clone())
+            .filter(|key| tracks.iter().any(|track| track.name == *key))
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        let key_index = |name: &str| keys.iter().position(|key| key == name);
+        let insert_keys = |chain: &track::processors::chain::InsertChain| {
+            chain
+                .inserts
+                .iter()
+                .map(|insert| insert.key.as_deref().and_then(key_index))
+                .collect::<Vec<_>>()
+        };
+
+        // Edits are checked for loops, so a failed order only means a track
+        // changed while this was built. List order is the best left then.
+        let routing = tracks
+            .iter()
+            .map(|track| track::routing::RoutingNode::from_track(track))
+            .collect::<Vec<_>>();
+        let order = track::routing::processing_order(&routing).unwrap_or_else(|names| {
+            eprintln!("Routing loops through {}", names.join(", "));
+            (0..tracks.len()).collect()
+        });
+        let bus_index = |name: &str| buses.iter().position(|bus| bus == name);
+
+        let mut nodes = Vec::new();
+        for track in order.into_iter().map(|index| &tracks[index]) {
+            let input = match &track.source {
+                Some(source) if track.monitor => Some(LiveInput {
+                    ring_buffer: source.get_ring_buffer(),
+                    channels: source.channels().max(1) as usize,
+                }),
+                _ => None,
+            };
+            let read_lane = |target: track::automation::AutomationTarget| {
+                track
+                    .automation
+                    .lane(&target)
+                    .filter(|lane| !lane.points.is_empty() && track.automation.reads(&target))
+                    .cloned()
+            };
+            let parameter_lanes = track
+                .automation
+                .lanes
+                .iter()
+                .filter(|lane| !lane.points.is_empty() && track.automation.reads(&lane.target))
+                .filter_map(|lane| match &lane.target {
+                    track::automation::AutomationTarget::Parameter {
+                        insert_id,
+                        parameter,
+                    } => Some(ParameterLane {
+                        insert: track
+                            .inserts
+                            .inserts
+                            .iter()
+                            .position(|insert| insert.id == *insert_id)?,
+                        parameter: parameter.clone(),
+                        lane: lane.clone(),
+                    }),
+                    _ => None,
+                })
+                .collect();
+            nodes.push(TrackNode {
+                bus: bus_index(&track.name),
+                output: track.output.as_deref().and_then(bus_index),
+                key_tap: key_index(&track.name),
+                input,
+                clips: track.playback_clips(sample_rate),
+                inserts: track.inserts.node(running, sample_rate),
+                insert_keys: insert_keys(&track.inserts),
+                // Sends to tracks that aren't buses are ignored.
+                sends: track
+                    .sends
+                    .iter()
+                    .filter_map(|send| {
+                        Some(SendNode {
+                            bus: bus_index(&send.bus)?,
+                            level: send.level,
+                            pre_fader: send.pre_fader,
+                        })
+                    })
+                    .collect(),
+                gain: track.gain,
+                pan: track.pan,
+                mute: track.mute,
+                solo: track.solo,
+                solo_safe: track.solo_safe,
+                gain_lane: read_lane(track::automation::AutomationTarget::Gain),
+                pan_lane: read_lane(track::automation::AutomationTarget::Pan),
+                mute_lane: read_lane(track::automation::AutomationTarget::Mute),
+                parameter_lanes,
+            });
+        }
+
+        let any_solo = nodes.iter().any(|track| track.solo);
+        imply_solo(&mut nodes);
+        Graph {
+            any_solo,
+            tracks: nodes,
+            bus_buffers: buses
+                .iter()
+                .map(|_| [vec![0.0; MAX_BLOCK_SIZE], vec![0.0; MAX_BLOCK_SIZE]])
+                .collect(),
+            key_buffers: keys
+                .iter()
+                .map(|_| [vec![0.0; MAX_BLOCK_SIZE], vec![0.0; MAX_BLOCK_SIZE]])
+                .collect(),
+            master_inserts: master_out.inserts.node(running, sample_rate),
+            master_insert_keys: insert_keys(&master_out.inserts),
+            master_limiter: Some(master_out.limiter.node(running, sample_rate)),
+            master_gain: master_out.gain,
+            master_pan: master_out.pan,
+            pan_law: track_list.pan_law,
+            metronome: Some(track_list.metronome.node(&track_list.tempo_map)),
+        }
+    }
+
+    // Drops live inputs and the click. Built with nothing running, every
+    // insert already has its own processor, apart from the live engine's.
+    pub fn into_offline(mut self) -> Self {
+        for track in &mut self.tracks {
+            track.input = None;
+        }
+        self.metronome = None;
+        self
+    }
+
+    fn insert_nodes(&mut self) -> impl Iterator<Item = &mut track::processors::chain::InsertNode> {
+        self.tracks
+            .iter_mut()
+            .flat_map(|track| track.inserts.inserts.iter_mut())
+            .chain(self.master_inserts.inserts.iter_mut())
+            .chain(self.master_limiter.iter_mut())
+    }
+
+    // Moves the processors this graph didn't bring along out of `old`.
+    fn adopt(&mut self, old: &mut Graph) {
+        for node in self.insert_nodes() {
+            for old_node in old.insert_nodes() {
+                if node.adopt(old_node) {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn instances(&self) -> HashSet<u64> {
+        self.tracks
+            .iter()
+            .flat_map(|track| track.inserts.inserts.iter())
+            .chain(self.master_inserts.inserts.iter())
+            .chain(self.master_limiter.iter())
+            .map(track::processors::chain::InsertNode::instance)
+            .collect()
+    }
+}
+
+// UI side of the engine. Graphs the audio thread has replaced come back through
+// `garbage` so they are freed here instead of on the callback.
+pub struct EngineHandle {
+    sender: SyncSender<Box<Graph>>,
+    pending: PendingGraph,
+    garbage: Receiver<Box<Graph>>,
+    // Insert instances of the last graph sent and the rate their processors
+    // run at. Only the UI side locks it.
+    running: Mutex<(HashSet<u64>, u32)>,
+}
+
+impl EngineHandle {
+    pub fn publish(
+        &self,
+        track_list: &track::track_list::TrackList,
+        master_out: &track::tracks::OutputTrack,
+        sample_rate: u32,
+    ) {
+        while self.garbage.try_recv().is_ok() {}
+        let Ok(mut running) = self.running.lock() else {
+            return;
+        };
+        // Processors prepared for another rate are all replaced.
+        if running.1 != sample_rate {
+            *running = (HashSet::new(), sample_rate);
+        }
+        let mut graph = Box::new(Graph::build(
+            track_list,
+            master_out,
+            &running.0,
+            sample_rate,
+        ));
+        let instances = graph.instances();
+        let Ok(mut pending) = self.pending.lock() else {
+            return;
+        };
+        // A graph still pending holds the processors this one was built to
+        // take over.
+        if let Some(mut old) = pending.take() {
+            graph.adopt(&mut old);
+        }
+        match self.sender.try_send(graph) {
+            Ok(()) => running.0 = instances,
+            Err(TrySendError::Full(graph)) => {
+                *pending = Some(graph);
+                running.0 = instances;
+            }
+            Err(TrySendError::Disconnected(_)) => eprintln!("Engine is gone, dropping update"),
+        }
+    }
+}
+
+// Audio thread side of the engine. `process` never locks or allocates, blocks
+// must not be longer than `MAX_BLOCK_SIZE`.
+pub struct Engine {
+    graph: Box<Graph>,
+    receiver: Option<Receiver<Box<Graph>>>,
+    pending: Option<PendingGraph>,
+    garbage: Option<SyncSender<Box<Graph>>>,
+    // Allocated up front, it never grows past `PARKED_GRAPHS`. The graphs stay
+    // boxed so parking one moves a pointer.
+    #[allow(clippy::vec_box)]
+    parked: Vec<Box<Graph>>,
+    mixer: track::mixer::Mixer,
+    click: track::metronome::ClickPlayer,
+    // Channels of the output device, to tell whether a routed click fits.
+    output_channels: usize,
+}
+
+impl Engine {
+    pub fn offline(graph: Graph) -> Self {
+        Engine {
+            graph: Box::new(graph.into_offline()),
+            receiver: None,
+            pending: None,
+            garbage: None,
+            parked: Vec::new(),
+            mixer: track::mixer::Mixer::new(MAX_BLOCK_SIZE),
+            click: track::metronome::ClickPlayer::new(MAX_BLOCK_SIZE),
+            output_channels: 2,
+        }
+    }
+
+    pub fn set_output_channels(&mut self, channels: usize) {
+        self.output_channels = channels;
+    }
+
+    // `count_in` is how many frames of count-in are left, the timeline doesn't
+    // play until it's over.
+    pub fn process(
+        &mut self,
+        position: u64,
+        playing: bool,
+        count_in: u64,
+        left: &mut [f32],
+        right: &mut [f32],
+    ) {
+        if let (Some(receiver), Some(pending), Some(garbage)) =
+            (&self.receiver, &self.pending, &self.garbage)
+        {
+            while let Some(old) = self.parked.pop() {
+                if let Err(e) = garbage.try_send(old) {
+                    self.parked.push(unsent(e));
+                    break;
+                }
+            }
+            // New graphs wait while there's nowhere to put the one they
+            // replace, it must not be freed here.
+            while self.parked.len() < PARKED_GRAPHS {
+                let Some(graph) = receiver
+                    .try_recv()
+                    .ok()
+                    .or_else(|| pending.try_lock().ok()?.take())
+                else {
+                    break;
+                };
+                let mut old = mem::replace(&mut self.graph, graph);
+                self.graph.adopt(&mut old);
+                if let Err(e) = garbage.try_send(old) {
+                    self.parked.push(unsent(e));
+                }
+            }
+        }
+
+        self.mixer
+            .render(&mut self.graph, position, playing, left, right);
+
+        if let Some(metronome) = &self.graph.metronome {
+            let frames = left.len();
+            self.click
+                .render(metronome, position, playing, count_in, frames);
+            if self.routed_click().is_none() {
+                for i in 0..frames {
+                    left[i] += self.click.left[i];
+                    right[i] += self.click.right[i];
+                }
+            }
+        }
+    }
+
+    // First channel of the pair the click of the last block goes to, when it
+    // stays out of the master.
+    pub fn routed_click(&self) -> Option<(usize, &[f32], &[f32])> {
+        match self.graph.metronome.as_ref()?.output {
+            track::metronome::MetronomeOutput::Channels(first)
+                if first as usize + 1 < self.output_channels =>
+            {
+                Some((first as usize, &self.click.left, &self.click.right))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn unsent(error: TrySendError<Box<Graph>>) -> Box<Graph> {
+    match error {
+        TrySendError::Full(graph) | TrySendError::Disconnected(graph) => graph,
+    }
+}
+
+pub fn channel() -> (EngineHandle, Engine) {
+    let (sender, receiver) = sync_channel(GRAPH_QUEUE_SIZE);
+    let (garbage_sender, garbage) = sync_channel(GRAPH_QUEUE_SIZE);
+    let pending = Arc::new(Mutex::new(None));
+    (
+        EngineHandle {
+            sender,
+            pending: pending.clone(),
+            garbage,
+            running: Mutex::new((HashSet::new(), 0)),
+        },
+        Engine {
+            graph: Box::new(Graph::new()),
+            receiver: Some(receiver),
+            pending: Some(pending),
+            garbage: Some(garbage_sender),
+            parked: Vec::with_capacity(PARKED_GRAPHS),
+            mixer: track::mixer::Mixer::new(MAX_BLOCK_SIZE),
+            click: track::metronome::ClickPlayer::new(MAX_BLOCK_SIZE),
+            output_channels: 2,
+        },
+    )
+}
+
+// Solo in place. A soloed track keeps the buses it reaches through its output
+// and sends audible, a soloed bus keeps the tracks that output into it
+// audible. Nodes are in processing order, so one pass each way is enough.
+fn imply_solo(nodes: &mut [TrackNode]) {
+    let node_of_bus =
+        |nodes: &[TrackNode], bus: usize| nodes.iter().position(|node| node.bus == Some(bus));
+
+    let mut downstream: Vec<bool> = nodes.iter().map(|node| node.solo).collect();
+    for index in 0..nodes.len() {
+        if !downstream[index] {
+            continue;
+        }
+        let node = &nodes[index];
+        for bus in node
+            .output
+            .iter()
+            .chain(node.sends.iter().map(|send| &send.bus))
+        {
+            if let Some(destination) = node_of_bus(nodes, *bus) {
+                downstream[destination] = true;
+            }
+        }
+    }
+
+    let mut upstream: Vec<bool> = nodes.iter().map(|node| node.solo).collect();
+    for index in (0..nodes.len()).rev() {
+        if let Some(destination) = nodes[index].output.and_then(|bus| node_of_bus(nodes, bus)) {
+            upstream[index] |= upstream[destination];
+        }
+    }
+
+    for (index, node) in nodes.iter_mut().enumerate() {
+        node.solo = downstream[index] || upstream[index];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_past_a_full_queue_are_not_lost() {
+        let (handle, mut engine) = channel();
+        let track_list = track::track_list::TrackList::new();
+        let mut master_out = track::tracks::OutputTrack::new(48_000);
+        for i in 0..=GRAPH_QUEUE_SIZE {
+            master_out.gain = i as f32;
+            handle.publish(&track_list, &master_out, 48_000);
+        }
+        assert!(handle.pending.lock().unwrap().is_some());
+
+        let (mut left, mut right) = (vec![0.0; 64], vec![0.0; 64]);
+        engine.process(0, false, 0, &mut left, &mut right);
+        assert_eq!(engine.graph.master_gain, GRAPH_QUEUE_SIZE as f32);
+        // The garbage queue was full too, the last graph replaced is kept.
+        assert_eq!(engine.parked.len(), 1);
+
+        while handle.garbage.try_recv().is_ok() {}
+        engine.process(0, false, 0, &mut left, &mut right);
+        assert!(engine.parked.is_empty());
+        assert_eq!(handle.garbage.try_iter().count(), 1);
+    }
+}
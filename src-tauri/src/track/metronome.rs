@@ -0,0 +1,301 @@
+use std::{
+    f32::consts::PI,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{project, track};
+
+const BUILTIN_SECONDS: f32 = 0.05;
+const MAX_LEVEL: f32 = 2.0;
+const MAX_COUNT_IN_BARS: u32 = 8;
+// Longer click files are cut, so a wrong file can't drone over the beat.
+const MAX_CLICK_SECONDS: f32 = 1.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuiltinClick {
+    Beep,
+    Woodblock,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ClickSound {
+    Builtin(BuiltinClick),
+    // WAV files for the first beat of a bar and the other beats.
+    Files { accent: PathBuf, beat: PathBuf },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetronomeOutput {
+    // Mixed in after the master fader.
+    Master,
+    // Only on the device channel pair starting at this one, counted from 0.
+    // Falls back to the master when the device doesn't have them.
+    Channels(u16),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetronomeSettings {
+    // Clicks while the transport plays, the count-in clicks regardless.
+    pub enabled: bool,
+    pub count_in_bars: u32,
+    pub level: f32,
+    pub sound: ClickSound,
+    pub output: MetronomeOutput,
+}
+
+impl MetronomeSettings {
+    pub fn new() -> Self {
+        MetronomeSettings {
+            enabled: false,
+            count_in_bars: 0,
+            level: 0.5,
+            sound: ClickSound::Builtin(BuiltinClick::Beep),
+            output: MetronomeOutput::Master,
+        }
+    }
+}
+
+// The settings and their click sounds, mono at the engine rate.
+pub struct Metronome {
+    pub settings: MetronomeSettings,
+    sample_rate: u32,
+    accent: Arc<Vec<f32>>,
+    beat: Arc<Vec<f32>>,
+}
+
+impl Metronome {
+    pub fn new() -> Self {
+        Metronome {
+            settings: MetronomeSettings::new(),
+            sample_rate: 0,
+            accent: Arc::new(Vec::new()),
+            beat: Arc::new(Vec::new()),
+        }
+    }
+
+    // Keeps the current settings when the click files can't be read.
+    pub fn set_settings(&mut self, settings: MetronomeSettings) -> Result<(), String> {
+        if !(0.0..=MAX_LEVEL).contains(&settings.level) {
+            return Err(format!("Click level must be between 0 and {}", MAX_LEVEL));
+        }
+        if settings.count_in_bars > MAX_COUNT_IN_BARS {
+            return Err(format!(
+                "Count-in can be at most {} bars",
+                MAX_COUNT_IN_BARS
+            ));
+        }
+        let (accent, beat) = load_sound(&settings.sound, self.sample_rate)?;
+        self.settings = settings;
+        self.accent = Arc::new(accent);
+        self.beat = Arc::new(beat);
+        Ok(())
+    }
+
+    // Renders the clicks again for a new engine rate, missing click files fall
+    // back to the built-in beep.
+    pub fn prepare(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        if let Err(e) = self.set_settings(self.settings.clone()) {
+            eprintln!("{}, using the built-in click", e);
+            self.settings.sound = ClickSound::Builtin(BuiltinClick::Beep);
+            let (accent, beat) = builtin_click(BuiltinClick::Beep, sample_rate);
+            self.accent = Arc::new(accent);
+            self.beat = Arc::new(beat);
+        }
+    }
+
+    // Length of the count-in before recording from `position`.
+    pub fn count_in_frames(&self, tempo_map: &project::tempo::TempoMap, position: u64) -> u64 {
+        let (beat, numerator) = count_in_beat(tempo_map, position, self.sample_rate);
+        (beat * (self.settings.count_in_bars * numerator) as f64).round() as u64
+    }
+
+    pub fn node(&self, tempo_map: &project::tempo::TempoMap) -> MetronomeNode {
+        MetronomeNode {
+            tempo_map: tempo_map.clone(),
+            sample_rate: self.sample_rate,
+            accent: self.accent.clone(),
+            beat: self.beat.clone(),
+            enabled: self.settings.enabled,
+            level: self.settings.level,
+            output: self.settings.output,
+        }
+    }
+}
+
+// Frames per beat and beats per bar of a count-in ending at `position`, at the
+// tempo and time signature found there.
+fn count_in_beat(
+    tempo_map: &project::tempo::TempoMap,
+    position: u64,
+    sample_rate: u32,
+) -> (f64, u32) {
+    let tick = tempo_map.tick_at_sample(position, sample_rate);
+    let meter = tempo_map.meter_at(tick);
+    let seconds = 60.0 / tempo_map.tempo_at(tick) * 4.0 / meter.denominator as f64;
+    (seconds * sample_rate as f64, meter.numerator)
+}
+
+fn load_sound(sound: &ClickSound, sample_rate: u32) -> Result<(Vec<f32>, Vec<f32>), String> {
+    match sound {
+        ClickSound::Builtin(click) => Ok(builtin_click(*click, sample_rate)),
+        ClickSound::Files { accent, beat } => Ok((
+            load_click(accent, sample_rate)?,
+            load_click(beat, sample_rate)?,
+        )),
+    }
+}
+
+// Mixes the file down to mono.
+fn load_click(path: &Path, sample_rate: u32) -> Result<Vec<f32>, String> {
+    let (file_rate, channels) = track::sources::source::read_wav(path)
+        .map_err(|e| format!("Failed to read click {}: {}", path.display(), e))?;
+    let length = channels.first().map_or(0, Vec::len);
+    let scale = 1.0 / channels.len().max(1) as f32;
+    let mono = (0..length)
+        .map(|i| channels.iter().map(|channel| channel[i]).sum::<f32>() * scale)
+        .collect::<Vec<_>>();
+    let mut click = track::sources::source::resample(&mono, file_rate, sample_rate);
+    click.truncate((MAX_CLICK_SECONDS * sample_rate as f32) as usize);
+    Ok(click)
+}
+
+// Accent and beat, the accent a fifth higher.
+fn builtin_click(click: BuiltinClick, sample_rate: u32) -> (Vec<f32>, Vec<f32>) {
+    let render = |frequency: f32| {
+        let sample_rate = sample_rate.max(1) as f32;
+        (0..(BUILTIN_SECONDS * sample_rate) as usize)
+            .map(|i| {
+                let time = i as f32 / sample_rate;
+                match click {
+                    BuiltinClick::Beep => {
+                        (2.0 * PI * frequency * time).sin() * (-time * 80.0).exp()
+                    }
+                    // Two inharmonic partials with a fast decay.
+                    BuiltinClick::Woodblock => {
+                        ((2.0 * PI * frequency * time).sin()
+                            + 0.5 * (2.0 * PI * frequency * 2.76 * time).sin())
+                            * (-time * 160.0).exp()
+                            * 0.7
+                    }
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let frequency = match click {
+        BuiltinClick::Beep => 1000.0,
+        BuiltinClick::Woodblock => 800.0,
+    };
+    (render(frequency * 1.5), render(frequency))
+}
+
+// What the audio thread needs to place clicks, part of the engine graph.
+pub struct MetronomeNode {
+    tempo_map: project::tempo::TempoMap,
+    sample_rate: u32,
+    accent: Arc<Vec<f32>>,
+    beat: Arc<Vec<f32>>,
+    enabled: bool,
+    level: f32,
+    pub output: MetronomeOutput,
+}
+
+impl MetronomeNode {
+    // The first click at or after frame `from` of the block starting at
+    // `position`, and whether it's accented. While `count_in` frames of
+    // count-in are left the clicks count down to the end of it instead.
+    fn next_click(
+        &self,
+        position: u64,
+        playing: bool,
+        count_in: u64,
+        from: usize,
+        frames: usize,
+    ) -> Option<(usize, bool)> {
+        if count_in > 0 {
+            let (beat, numerator) = count_in_beat(&self.tempo_map, position, self.sample_rate);
+            let left = count_in.checked_sub(from as u64)?;
+            // Beats are counted back from the end of the count-in.
+            let mut index = (left as f64 / beat).floor() as u64;
+            if ((index + 1) as f64 * beat).round() as u64 <= left {
+                index += 1;
+            }
+            let frame = (count_in - (index as f64 * beat).round() as u64) as usize;
+            return (index > 0 && frame < frames)
+                .then_some((frame, index.is_multiple_of(numerator as u64)));
+        }
+        if !playing || !self.enabled {
+            return None;
+        }
+
+        let start = position + from as u64;
+        let mut tick = self
+            .tempo_map
+            .tick_at_sample(start.saturating_sub(1), self.sample_rate);
+        loop {
+            let (beat, accent) = self.tempo_map.next_beat(tick);
+            let sample = self.tempo_map.sample_at_tick(beat, self.sample_rate);
+            if sample >= start {
+                let frame = (sample - position) as usize;
+                return (frame < frames).then_some((frame, accent));
+            }
+            tick = beat + 0.5;
+        }
+    }
+}
+
+// Plays the clicks on the audio thread. The click that's sounding is kept by
+// index, so it carries on from the new graph's sounds after an update.
+pub struct ClickPlayer {
+    voice: Option<(bool, usize)>,
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+}
+
+impl ClickPlayer {
+    pub fn new(max_block: usize) -> Self {
+        ClickPlayer {
+            voice: None,
+            left: vec![0.0; max_block],
+            right: vec![0.0; max_block],
+        }
+    }
+
+    // Renders `frames` frames of clicks into `left` and `right`.
+    pub fn render(
+        &mut self,
+        node: &MetronomeNode,
+        position: u64,
+        playing: bool,
+        count_in: u64,
+        frames: usize,
+    ) {
+        let frames = frames.min(self.left.len());
+        self.left[..frames].fill(0.0);
+        let mut from = 0;
+        let mut next = node.next_click(position, playing, count_in, 0, frames);
+        loop {
+            let until = next.map_or(frames, |(frame, _)| frame);
+            if let Some((accent, index)) = self.voice {
+                let sound = if accent { &node.accent } else { &node.beat };
+                let count = (until - from).min(sound.len().saturating_sub(index));
+                for i in 0..count {
+                    self.left[from + i] = sound[index + i] * node.level;
+                }
+                self.voice = (index + count < sound.len()).then_some((accent, index + count));
+            }
+            match next {
+                Some((frame, accent)) => {
+                    self.voice = Some((accent, 0));
+                    from = frame;
+                    next = node.next_click(position, playing, count_in, frame + 1, frames);
+                }
+                None => break,
+            }
+        }
+        self.right[..frames].copy_from_slice(&self.left[..frames]);
+    }
+}
@@ -0,0 +1,249 @@
+use std::f32::consts::FRAC_PI_4;
+
+use serde::{Deserialize, Serialize};
+
+use crate::track;
+
+// Processor parameters follow their automation once per this many frames.
+const AUTOMATION_BLOCK: usize = 32;
+
+// Named after the level a centred signal gets on each side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PanLaw {
+    Balance,
+    ConstantPower,
+    Compromise,
+    Linear,
+}
+
+impl PanLaw {
+    // -1.0 is hard left and 1.0 is hard right.
+    pub fn gains(self, pan: f32) -> (f32, f32) {
+        let pan = pan.clamp(-1.0, 1.0);
+        match self {
+            PanLaw::Balance => ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0)),
+            PanLaw::ConstantPower => {
+                let angle = (pan + 1.0) * FRAC_PI_4;
+                (angle.cos(), angle.sin())
+            }
+            PanLaw::Compromise => {
+                let (power_left, power_right) = PanLaw::ConstantPower.gains(pan);
+                let (linear_left, linear_right) = PanLaw::Linear.gains(pan);
+                (
+                    (power_left * linear_left).sqrt(),
+                    (power_right * linear_right).sqrt(),
+                )
+            }
+            PanLaw::Linear => ((1.0 - pan) * 0.5, (1.0 + pan) * 0.5),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SoloMode {
+    Additive,
+    Exclusive,
+}
+
+// The stereo mix shared by the real-time engine and offline rendering, it
+// keeps its scratch buffers between blocks so they are only allocated once.
+pub struct Mixer {
+    track_left: Vec<f32>,
+    track_right: Vec<f32>,
+    // Per frame gain of the current track on each side, with pan and mute.
+    fader_left: Vec<f32>,
+    fader_right: Vec<f32>,
+    // 1.0 where the current track plays and 0.0 where it's muted.
+    unmuted: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn new(max_block: usize) -> Self {
+        Mixer {
+            track_left: vec![0.0; max_block],
+            track_right: vec![0.0; max_block],
+            fader_left: vec![0.0; max_block],
+            fader_right: vec![0.0; max_block],
+            unmuted: vec![0.0; max_block],
+        }
+    }
+
+    // Renders `left.len()` frames starting at `position`, clips are only read
+    // while `playing`.
+    pub fn render(
+        &mut self,
+        graph: &mut track::engine::Graph,
+        position: u64,
+        playing: bool,
+        left: &mut [f32],
+        right: &mut [f32],
+    ) {
+        let frames = left.len().min(self.track_left.len());
+        left.fill(0.0);
+        right.fill(0.0);
+        for [bus_left, bus_right] in &mut graph.bus_buffers {
+            bus_left[..frames].fill(0.0);
+            bus_right[..frames].fill(0.0);
+        }
+
+        for track in &mut graph.tracks {
+            let track_left = &mut self.track_left[..frames];
+            let track_right = &mut self.track_right[..frames];
+
+            match track.bus {
+                Some(bus) => {
+                    let [bus_left, bus_right] = &graph.bus_buffers[bus];
+                    track_left.copy_from_slice(&bus_left[..frames]);
+                    track_right.copy_from_slice(&bus_right[..frames]);
+                }
+                None => {
+                    track_left.fill(0.0);
+                    track_right.fill(0.0);
+                    if let Some(input) = &track.input {
+                        read_live_input(input, track_left, track_right);
+                    }
+                    if playing {
+                        for clip in &track.clips {
+                            clip.mix_into(position, track_left, track_right);
+                        }
+                    }
+                }
+            }
+
+            let key_buffers = &graph.key_buffers;
+            let block = if track.parameter_lanes.is_empty() {
+                frames.max(1)
+            } else {
+                AUTOMATION_BLOCK
+            };
+            for start in (0..frames).step_by(block) {
+                let end = (start + block).min(frames);
+                for automation in &track.parameter_lanes {
+                    if let Some(value) = automation.lane.value_at(position + start as u64) {
+                        track.inserts.inserts[automation.insert]
+                            .automate(&automation.parameter, value);
+                    }
+                }
+                track.inserts.process(
+                    &mut track_left[start..end],
+                    &mut track_right[start..end],
+                    |index| {
+                        let [key_left, key_right] = &key_buffers[track.insert_keys[index]?];
+                        Some((&key_left[start..end], &key_right[start..end]))
+                    },
+                );
+            }
+            if let Some(tap) = track.key_tap {
+                let [key_left, key_right] = &mut graph.key_buffers[tap];
+                key_left[..frames].copy_from_slice(track_left);
+                key_right[..frames].copy_from_slice(track_right);
+            }
+
+            let muted = track.mute_lane.is_none() && track.mute;
+            if muted || (graph.any_solo && !track.solo && !track.solo_safe) {
+                continue;
+            }
+            let fader_left = &mut self.fader_left[..frames];
+            let fader_right = &mut self.fader_right[..frames];
+            let unmuted = &mut self.unmuted[..frames];
+            fill_lane(track.gain_lane.as_ref(), track.gain, position, fader_left);
+            fill_lane(track.pan_lane.as_ref(), track.pan, position, fader_right);
+            fill_lane(track.mute_lane.as_ref(), 0.0, position, unmuted);
+            let static_pan = graph.pan_law.gains(track.pan);
+            for i in 0..frames {
+                let (pan_left, pan_right) = match track.pan_lane {
+                    Some(_) => graph.pan_law.gains(fader_right[i]),
+                    None => static_pan,
+                };
+                unmuted[i] = if unmuted[i] >= 0.5 { 0.0 } else { 1.0 };
+                let gain = fader_left[i] * unmuted[i];
+                fader_left[i] = gain * pan_left;
+                fader_right[i] = gain * pan_right;
+            }
+
+            for send in &track.sends {
+                let [bus_left, bus_right] = &mut graph.bus_buffers[send.bus];
+                for i in 0..frames {
+                    let (send_left, send_right) = if send.pre_fader {
+                        (unmuted[i], unmuted[i])
+                    } else {
+                        (fader_left[i], fader_right[i])
+                    };
+                    bus_left[i] += track_left[i] * send.level * send_left;
+                    bus_right[i] += track_right[i] * send.level * send_right;
+                }
+            }
+            let (out_left, out_right) = match track.output {
+                Some(bus) => {
+                    let [bus_left, bus_right] = &mut graph.bus_buffers[bus];
+                    (&mut bus_left[..frames], &mut bus_right[..frames])
+                }
+                None => (&mut left[..frames], &mut right[..frames]),
+            };
+            for i in 0..frames {
+                out_left[i] += track_left[i] * fader_left[i];
+                out_right[i] += track_right[i] * fader_right[i];
+            }
+        }
+
+        let key_buffers = &graph.key_buffers;
+        let master_insert_keys = &graph.master_insert_keys;
+        graph
+            .master_inserts
+            .process(&mut left[..frames], &mut right[..frames], |index| {
+                let [key_left, key_right] = &key_buffers[master_insert_keys[index]?];
+                Some((&key_left[..frames], &key_right[..frames]))
+            });
+        let (balance_left, balance_right) = PanLaw::Balance.gains(graph.master_pan);
+        for i in 0..frames {
+            left[i] *= graph.master_gain * balance_left;
+            right[i] *= graph.master_gain * balance_right;
+        }
+        if let Some(limiter) = &mut graph.master_limiter {
+            limiter.process(&mut left[..frames], &mut right[..frames], None);
+        }
+    }
+}
+
+// Fills `out` with the lane's value at each frame from `position`, or with
+// `value` when there's no lane.
+fn fill_lane(
+    lane: Option<&track::automation::AutomationLane>,
+    value: f32,
+    position: u64,
+    out: &mut [f32],
+) {
+    match lane {
+        Some(lane) => {
+            for (i, sample) in out.iter_mut().enumerate() {
+                *sample = lane.value_at(position + i as u64).unwrap_or(value);
+            }
+        }
+        None => out.fill(value),
+    }
+}
+
+// Reads one block of interleaved input as stereo. Anything queued beyond a
+// couple of blocks is skipped so monitoring latency can't build up.
+fn read_live_input(input: &track::engine::LiveInput, left: &mut [f32], right: &mut [f32]) {
+    let channels = input.channels;
+    let backlog = input.ring_buffer.len();
+    let wanted = left.len() * channels;
+    if backlog > wanted * 2 {
+        input.ring_buffer.skip(backlog - wanted);
+    }
+
+    for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+        let first = input.ring_buffer.pop().unwrap_or(0.0);
+        let second = if channels > 1 {
+            input.ring_buffer.pop().unwrap_or(0.0)
+        } else {
+            first
+        };
+        for _ in 2..channels {
+            input.ring_buffer.pop();
+        }
+        *l = first;
+        *r = second;
+    }
+}
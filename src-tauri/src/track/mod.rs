@@ -1,5 +1,14 @@
+pub mod automation;
+pub mod clips;
 pub mod commands;
+pub mod engine;
+pub mod metronome;
+pub mod mixer;
+pub mod processors;
 pub mod raw;
+pub mod routing;
+pub mod sends;
 pub mod sources;
+pub mod takes;
 pub mod track_list;
 pub mod tracks;
@@ -0,0 +1,145 @@
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    LowShelf,
+    HighShelf,
+    Peak,
+}
+
+// Normalised by a0, from the RBJ audio EQ cookbook.
+#[derive(Clone, Copy, Debug)]
+pub struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    pub fn identity() -> Self {
+        Coefficients {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+        }
+    }
+
+    pub fn new(
+        filter_type: FilterType,
+        sample_rate: u32,
+        frequency: f32,
+        q: f32,
+        gain_db: f32,
+    ) -> Self {
+        let sample_rate = sample_rate.max(1) as f32;
+        let frequency = frequency.clamp(1.0, sample_rate * 0.49);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+        let a = 10.0_f32.powf(gain_db / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match filter_type {
+            FilterType::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterType::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterType::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+            FilterType::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+            FilterType::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+        };
+
+        Coefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    // Gain in dB this filter applies at `frequency`.
+    pub fn magnitude_db(&self, frequency: f32, sample_rate: u32) -> f32 {
+        let w = 2.0 * PI * frequency / sample_rate.max(1) as f32;
+        let (sin1, cos1) = (-w).sin_cos();
+        let (sin2, cos2) = (-2.0 * w).sin_cos();
+
+        let num_re = self.b0 + self.b1 * cos1 + self.b2 * cos2;
+        let num_im = self.b1 * sin1 + self.b2 * sin2;
+        let den_re = 1.0 + self.a1 * cos1 + self.a2 * cos2;
+        let den_im = self.a1 * sin1 + self.a2 * sin2;
+
+        let magnitude = ((num_re * num_re + num_im * num_im)
+            / (den_re * den_re + den_im * den_im).max(f32::MIN_POSITIVE))
+        .sqrt();
+        20.0 * magnitude.max(1e-9).log10()
+    }
+}
+
+// Stereo biquad in transposed direct form II.
+#[derive(Clone, Copy, Debug)]
+pub struct Biquad {
+    pub coefficients: Coefficients,
+    state: [[f32; 2]; 2],
+}
+
+impl Biquad {
+    pub fn new() -> Self {
+        Biquad {
+            coefficients: Coefficients::identity(),
+            state: [[0.0; 2]; 2],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = [[0.0; 2]; 2];
+    }
+
+    pub fn process_sample(&mut self, channel: usize, input: f32) -> f32 {
+        let c = &self.coefficients;
+        let state = &mut self.state[channel];
+        let output = c.b0 * input + state[0];
+        state[0] = c.b1 * input - c.a1 * output + state[1];
+        state[1] = c.b2 * input - c.a2 * output;
+        output
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::track::{
+    self,
+    processors::{
+        dynamics::GainReductionMeter,
+        processor::{Parameter, Processor, ProcessorKind},
+    },
+};
+
+static NEXT_INSERT_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_INSTANCE: AtomicU64 = AtomicU64::new(1);
+
+fn next_insert_id() -> u64 {
+    NEXT_INSERT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn next_instance() -> u64 {
+    NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed)
+}
+
+// Parameter values set from the UI side, the audio thread applies the ones
+// that changed before its next block. Values are f32 bits.
+struct Controls {
+    values: Vec<AtomicU32>,
+    changed: Vec<AtomicBool>,
+    any_changed: AtomicBool,
+}
+
+impl Controls {
+    fn new(parameters: &[Parameter]) -> Self {
+        Controls {
+            values: parameters
+                .iter()
+                .map(|p| AtomicU32::new(p.value.to_bits()))
+                .collect(),
+            changed: parameters.iter().map(|_| AtomicBool::new(false)).collect(),
+            any_changed: AtomicBool::new(false),
+        }
+    }
+
+    fn store(&self, index: usize, value: f32) {
+        if self.values[index].swap(value.to_bits(), Ordering::Relaxed) != value.to_bits() {
+            self.changed[index].store(true, Ordering::Release);
+            self.any_changed.store(true, Ordering::Release);
+        }
+    }
+
+    fn apply(&self, processor: &mut dyn Processor) {
+        if !self.any_changed.swap(false, Ordering::Acquire) {
+            return;
+        }
+        for (index, parameter) in processor.parameters_mut().iter_mut().enumerate() {
+            if self.changed[index].swap(false, Ordering::Acquire) {
+                parameter.set(f32::from_bits(self.values[index].load(Ordering::Relaxed)));
+            }
+        }
+    }
+}
+
+// A processor and the sample rate it's prepared for.
+type Prepared = (u32, Box<dyn Processor>);
+
+// The UI side of an insert, a snapshot of its settings. The processor that
+// runs it belongs to the audio thread and only ever hears about changes
+// through `controls`.
+#[derive(Clone)]
+pub struct Insert {
+    pub id: u64,
+    pub bypass: bool,
+    pub kind: ProcessorKind,
+    pub parameters: Vec<Parameter>,
+    pub file: Option<PathBuf>,
+    // Read without reaching the processor.
+    pub meter: Option<GainReductionMeter>,
+    // Track whose signal feeds the processor's sidechain.
+    pub key: Option<String>,
+    // The processor the engine runs for this insert, a new instance replaces
+    // it and starts from a clean state.
+    instance: u64,
+    controls: Arc<Controls>,
+    // Processor made up front for `instance`, kept until the first graph
+    // takes it so a loaded file isn't read twice. Only the UI side locks it.
+    ready: Arc<Mutex<Option<Prepared>>>,
+}
+
+impl Insert {
+    pub fn new(kind: ProcessorKind, sample_rate: u32) -> Self {
+        let mut processor = track::processors::processor::create(kind);
+        processor.prepare(sample_rate, track::engine::MAX_BLOCK_SIZE);
+        Insert::from_processor(processor, None, sample_rate)
+    }
+
+    fn from_processor(
+        processor: Box<dyn Processor>,
+        key: Option<String>,
+        sample_rate: u32,
+    ) -> Self {
+        Insert {
+            id: next_insert_id(),
+            bypass: false,
+            kind: processor.kind(),
+            parameters: processor.parameters().to_vec(),
+            file: processor.file().map(PathBuf::from),
+            meter: processor.gain_reduction_meter(),
+            key,
+            instance: next_instance(),
+            controls: Arc::new(Controls::new(processor.parameters())),
+            ready: Arc::new(Mutex::new(Some((sample_rate, processor)))),
+        }
+    }
+
+    pub fn parameter(&self, id: &str) -> Option<f32> {
+        self.parameters.iter().find(|p| p.id == id).map(|p| p.value)
+    }
+
+    // Returns the previous value, `None` when there's no such parameter.
+    pub fn set_parameter(&mut self, id: &str, value: f32) -> Option<f32> {
+        let index = self.parameters.iter().position(|p| p.id == id)?;
+        let parameter = &mut self.parameters[index];
+        let before = parameter.value;
+        parameter.set(value);
+        self.controls.store(index, parameter.value);
+        Some(before)
+    }
+
+    // Builds a processor from the snapshot, reporting into the same meter.
+    fn create_processor(&self, sample_rate: u32) -> Result<Box<dyn Processor>, String> {
+        let mut processor = track::processors::processor::create(self.kind);
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            if let Some(target) = processor.parameters_mut().get_mut(index) {
+                target.set(parameter.value);
+            }
+        }
+        if let Some(meter) = &self.meter {
+            processor.use_gain_reduction_meter(meter.clone());
+        }
+        if let Some(path) = &self.file {
+            processor.load_file(path)?;
+        }
+        processor.prepare(sample_rate, track::engine::MAX_BLOCK_SIZE);
+        Ok(processor)
+    }
+
+    // The audio side of the insert. It only gets a processor when the engine
+    // isn't running this instance already.
+    pub fn node(&self, running: &HashSet<u64>, sample_rate: u32) -> InsertNode {
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            self.controls.store(index, parameter.value);
+        }
+        let processor = if running.contains(&self.instance) {
+            None
+        } else {
+            let ready = self.ready.lock().ok().and_then(|mut ready| ready.take());
+            match ready {
+                Some((rate, processor)) if rate == sample_rate => Some(processor),
+                // A missing file leaves the insert without its data rather
+                // than silencing the track.
+                _ => self
+                    .create_processor(sample_rate)
+                    .or_else(|e| {
+                        eprintln!("{}", e);
+                        let mut insert = self.clone();
+                        insert.file = None;
+                        insert.create_processor(sample_rate)
+                    })
+                    .ok(),
+            }
+        };
+        InsertNode {
+            instance: self.instance,
+            bypass: self.bypass,
+            controls: self.controls.clone(),
+            processor,
+        }
+    }
+
+    // Gain in dB at each frequency, from a processor built for the question so
+    // the live one is left alone.
+    pub fn frequency_response(&self, frequencies: &[f32], sample_rate: u32) -> Option<Vec<f32>> {
+        let mut insert = self.clone();
+        insert.file = None;
+        insert
+            .create_processor(sample_rate)
+            .ok()?
+            .frequency_response(frequencies)
+    }
+
+    pub fn as_info(&self) -> InsertInfo {
+        InsertInfo {
+            id: self.id,
+            kind: self.kind,
+            bypass: self.bypass,
+            parameters: self.parameters.clone(),
+            file: self
+                .file
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string()),
+            key: self.key.clone(),
+        }
+    }
+
+    // A copy of this insert, keeping its id, that reads its data from `path`.
+    // The file is loaded here, so the audio thread never waits on it.
+    pub fn with_file(&self, path: PathBuf, sample_rate: u32) -> Result<Self, String> {
+        let mut raw = ProcessorRaw::from(self);
+        raw.file = None;
+        let mut insert = Insert::from_raw(raw, sample_rate);
+        if let Ok(mut ready) = insert.ready.lock() {
+            if let Some((_, processor)) = ready.as_mut() {
+                processor.load_file(&path)?;
+                processor.prepare(sample_rate, track::engine::MAX_BLOCK_SIZE);
+            }
+        }
+        insert.file = Some(path);
+        insert.id = self.id;
+        Ok(insert)
+    }
+
+    pub fn from_raw(value: ProcessorRaw, sample_rate: u32) -> Self {
+        let mut processor = track::processors::processor::create(value.kind);
+        for (id, parameter_value) in &value.parameters {
+            if !processor.set_parameter(id, *parameter_value) {
+                eprintln!("Unknown parameter {} for {:?}", id, value.kind);
+            }
+        }
+        // A missing file leaves the insert without its data rather than
+        // failing the whole project.
+        if let Some(path) = &value.file {
+            if let Err(e) = processor.load_file(path) {
+                eprintln!("{}", e);
+            }
+        }
+        processor.prepare(sample_rate, track::engine::MAX_BLOCK_SIZE);
+        let mut insert = Insert::from_processor(processor, value.key, sample_rate);
+        insert.bypass = value.bypass;
+        insert
+    }
+}
+
+// The audio thread's side of an insert. It owns its processor, which moves on
+// from graph to graph for as long as the engine keeps running its instance.
+pub struct InsertNode {
+    instance: u64,
+    bypass: bool,
+    controls: Arc<Controls>,
+    processor: Option<Box<dyn Processor>>,
+}
+
+impl InsertNode {
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32], key: Option<(&[f32], &[f32])>) {
+        let Some(processor) = self.processor.as_mut() else {
+            return;
+        };
+        self.controls.apply(processor.as_mut());
+        if self.bypass {
+            return;
+        }
+        match key {
+            Some(key) => processor.process_keyed(left, right, key),
+            None => processor.process(left, right),
+        }
+    }
+
+    // Takes over the processor of the same instance from a graph the engine
+    // is done with, when this node didn't bring its own. Returns whether the
+    // node has a processor now.
+    pub fn adopt(&mut self, old: &mut InsertNode) -> bool {
+        if self.processor.is_none() && old.instance == self.instance {
+            self.processor = old.processor.take();
+        }
+        self.processor.is_some()
+    }
+
+    pub fn instance(&self) -> u64 {
+        self.instance
+    }
+
+    // Sets a parameter from the audio thread, for automation.
+    pub fn automate(&mut self, parameter: &str, value: f32) {
+        if let Some(processor) = self.processor.as_mut() {
+            processor.set_parameter(parameter, value);
+        }
+    }
+}
+
+// Ordered inserts of one track, processed first to last.
+#[derive(Clone, Default)]
+pub struct InsertChain {
+    pub inserts: Vec<Insert>,
+}
+
+impl InsertChain {
+    pub fn new() -> Self {
+        InsertChain::default()
+    }
+
+    pub fn add(&mut self, insert: Insert, index: Option<usize>) {
+        let index = index.unwrap_or(self.inserts.len()).min(self.inserts.len());
+        self.inserts.insert(index, insert);
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Insert> {
+        let index = self.inserts.iter().position(|insert| insert.id == id)?;
+        Some(self.inserts.remove(index))
+    }
+
+    pub fn move_to(&mut self, id: u64, index: usize) -> bool {
+        match self.remove(id) {
+            Some(insert) => {
+                self.add(insert, Some(index));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Insert> {
+        self.inserts.iter().find(|insert| insert.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Insert> {
+        self.inserts.iter_mut().find(|insert| insert.id == id)
+    }
+
+    // `running` holds the instances the engine already has processors for,
+    // every other insert gets a new one prepared for `sample_rate`.
+    pub fn node(&self, running: &HashSet<u64>, sample_rate: u32) -> InsertChainNode {
+        InsertChainNode {
+            inserts: self
+                .inserts
+                .iter()
+                .map(|insert| insert.node(running, sample_rate))
+                .collect(),
+        }
+    }
+
+    pub fn as_info(&self) -> Vec<InsertInfo> {
+        self.inserts.iter().map(Insert::as_info).collect()
+    }
+
+    pub fn to_raw(&self) -> Vec<ProcessorRaw> {
+        self.inserts.iter().map(ProcessorRaw::from).collect()
+    }
+
+    pub fn from_raw(value: Vec<ProcessorRaw>, sample_rate: u32) -> Self {
+        InsertChain {
+            inserts: value
+                .into_iter()
+                .map(|raw| Insert::from_raw(raw, sample_rate))
+                .collect(),
+        }
+    }
+}
+
+// An insert chain as the audio thread runs it.
+#[derive(Default)]
+pub struct InsertChainNode {
+    pub inserts: Vec<InsertNode>,
+}
+
+impl InsertChainNode {
+    // `key` gives the sidechain signal of an insert, by its index.
+    pub fn process<'a>(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        key: impl Fn(usize) -> Option<(&'a [f32], &'a [f32])>,
+    ) {
+        for (index, insert) in self.inserts.iter_mut().enumerate() {
+            insert.process(left, right, key(index));
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProcessorRaw {
+    pub kind: ProcessorKind,
+    pub bypass: bool,
+    pub parameters: Vec<(String, f32)>,
+    pub file: Option<PathBuf>,
+    pub key: Option<String>,
+}
+
+impl From<&Insert> for ProcessorRaw {
+    fn from(value: &Insert) -> Self {
+        ProcessorRaw {
+            kind: value.kind,
+            bypass: value.bypass,
+            parameters: value
+                .parameters
+                .iter()
+                .map(|p| (p.id.clone(), p.value))
+                .collect(),
+            file: value.file.clone(),
+            key: value.key.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InsertInfo {
+    pub id: u64,
+    pub kind: ProcessorKind,
+    pub bypass: bool,
+    pub parameters: Vec<Parameter>,
+    pub file: Option<String>,
+    pub key: Option<String>,
+}
@@ -0,0 +1,226 @@
+use std::path::{Path, PathBuf};
+
+use crate::track::{
+    processors::{
+        fft::{Complex, Fft},
+        processor::{db_to_gain, Parameter, Processor, ProcessorKind},
+    },
+    sources::source::{read_wav, resample_band_limited},
+};
+
+// Partition size in frames, the wet signal comes out this much late.
+const BLOCK_SIZE: usize = 256;
+const BINS: usize = BLOCK_SIZE + 1;
+const MAX_IMPULSE_SECONDS: f32 = 20.0;
+
+// One input to output channel pair and the spectra of its impulse response,
+// cut into `BLOCK_SIZE` partitions.
+struct ConvolutionPath {
+    input: usize,
+    output: usize,
+    partitions: Vec<Vec<Complex>>,
+}
+
+// Uniformly partitioned overlap-save convolution with an impulse response
+// WAV. Mono responses are used on both sides, stereo ones per side and four
+// channel ones as true stereo in LL, LR, RL, RR order.
+pub struct ConvolutionReverb {
+    parameters: Vec<Parameter>,
+    file: Option<PathBuf>,
+    // The response as read from the file, resampled in `prepare`.
+    impulse: Option<(u32, Vec<Vec<f32>>)>,
+    fft: Fft,
+    paths: Vec<ConvolutionPath>,
+    // Input spectra of the last blocks per channel, newest at `position`.
+    spectra: [Vec<Vec<Complex>>; 2],
+    position: usize,
+    history: [Vec<f32>; 2],
+    wet: [Vec<f32>; 2],
+    fill: usize,
+    scratch: Vec<Complex>,
+    accumulator: Vec<Complex>,
+}
+
+impl ConvolutionReverb {
+    pub fn new() -> Self {
+        ConvolutionReverb {
+            parameters: vec![
+                Parameter::new("gain", "Wet gain (dB)", -48.0, 12.0, 0.0),
+                Parameter::new("mix", "Mix", 0.0, 1.0, 0.3),
+            ],
+            file: None,
+            impulse: None,
+            fft: Fft::new(BLOCK_SIZE * 2),
+            paths: Vec::new(),
+            spectra: [Vec::new(), Vec::new()],
+            position: 0,
+            history: [vec![0.0; BLOCK_SIZE * 2], vec![0.0; BLOCK_SIZE * 2]],
+            wet: [vec![0.0; BLOCK_SIZE], vec![0.0; BLOCK_SIZE]],
+            fill: 0,
+            scratch: vec![Complex::default(); BLOCK_SIZE * 2],
+            accumulator: vec![Complex::default(); BINS],
+        }
+    }
+
+    fn partition(&mut self, response: &[f32], scale: f32) -> Vec<Vec<Complex>> {
+        response
+            .chunks(BLOCK_SIZE)
+            .map(|chunk| {
+                self.scratch.fill(Complex::default());
+                for (value, sample) in self.scratch.iter_mut().zip(chunk) {
+                    value.re = sample * scale;
+                }
+                self.fft.forward(&mut self.scratch);
+                self.scratch[..BINS].to_vec()
+            })
+            .collect()
+    }
+
+    fn process_block(&mut self, gain: f32) {
+        let slots = self.spectra[0].len();
+        for channel in 0..2 {
+            for (value, sample) in self.scratch.iter_mut().zip(&self.history[channel]) {
+                *value = Complex::new(*sample, 0.0);
+            }
+            self.fft.forward(&mut self.scratch);
+            self.spectra[channel][self.position].copy_from_slice(&self.scratch[..BINS]);
+            self.history[channel].copy_within(BLOCK_SIZE.., 0);
+        }
+
+        for output in 0..2 {
+            self.accumulator.fill(Complex::default());
+            for path in self.paths.iter().filter(|path| path.output == output) {
+                for (age, partition) in path.partitions.iter().enumerate() {
+                    let spectrum = &self.spectra[path.input][(self.position + slots - age) % slots];
+                    for ((sum, x), h) in self.accumulator.iter_mut().zip(spectrum).zip(partition) {
+                        *sum = *sum + *x * *h;
+                    }
+                }
+            }
+
+            // The input is real, so the upper half mirrors the lower one.
+            let size = self.fft.size();
+            self.scratch[..BINS].copy_from_slice(&self.accumulator);
+            for bin in BINS..size {
+                self.scratch[bin] = self.accumulator[size - bin].conj();
+            }
+            self.fft.inverse(&mut self.scratch);
+            for (wet, value) in self.wet[output].iter_mut().zip(&self.scratch[BLOCK_SIZE..]) {
+                *wet = value.re * gain;
+            }
+        }
+        self.position = (self.position + 1) % slots;
+    }
+}
+
+impl Processor for ConvolutionReverb {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::ConvolutionReverb
+    }
+
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.paths.clear();
+        self.history = [vec![0.0; BLOCK_SIZE * 2], vec![0.0; BLOCK_SIZE * 2]];
+        self.wet = [vec![0.0; BLOCK_SIZE], vec![0.0; BLOCK_SIZE]];
+        self.fill = 0;
+        self.position = 0;
+
+        let Some((file_rate, channels)) = self.impulse.take() else {
+            self.spectra = [Vec::new(), Vec::new()];
+            return;
+        };
+        let max_length = (MAX_IMPULSE_SECONDS * sample_rate as f32) as usize;
+        let responses = channels
+            .iter()
+            .map(|channel| {
+                let mut response = resample_band_limited(channel, file_rate, sample_rate);
+                response.truncate(max_length);
+                response
+            })
+            .collect::<Vec<_>>();
+
+        // Normalised to the loudest channel's energy so responses recorded at
+        // different levels come out comparable.
+        let energy = responses
+            .iter()
+            .map(|response| response.iter().map(|s| s * s).sum::<f32>())
+            .fold(0.0_f32, f32::max);
+        let scale = if energy > 0.0 {
+            1.0 / energy.sqrt()
+        } else {
+            0.0
+        };
+
+        let routing: &[(usize, usize, usize)] = match responses.len() {
+            0 => &[],
+            1 => &[(0, 0, 0), (1, 1, 0)],
+            2 | 3 => &[(0, 0, 0), (1, 1, 1)],
+            _ => &[(0, 0, 0), (0, 1, 1), (1, 0, 2), (1, 1, 3)],
+        };
+        for (input, output, channel) in routing {
+            let partitions = self.partition(&responses[*channel], scale);
+            self.paths.push(ConvolutionPath {
+                input: *input,
+                output: *output,
+                partitions,
+            });
+        }
+
+        let slots = self
+            .paths
+            .iter()
+            .map(|path| path.partitions.len())
+            .max()
+            .unwrap_or(0);
+        self.spectra = [
+            vec![vec![Complex::default(); BINS]; slots],
+            vec![vec![Complex::default(); BINS]; slots],
+        ];
+        self.impulse = Some((file_rate, channels));
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.spectra[0].is_empty() {
+            return;
+        }
+        let gain = db_to_gain(self.parameter("gain"));
+        let mix = self.parameter("mix");
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            self.history[0][BLOCK_SIZE + self.fill] = *l;
+            self.history[1][BLOCK_SIZE + self.fill] = *r;
+            let (wet_left, wet_right) = (self.wet[0][self.fill], self.wet[1][self.fill]);
+            self.fill += 1;
+            if self.fill == BLOCK_SIZE {
+                self.process_block(gain);
+                self.fill = 0;
+            }
+
+            *l = *l * (1.0 - mix) + wet_left * mix;
+            *r = *r * (1.0 - mix) + wet_right * mix;
+        }
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        &mut self.parameters
+    }
+
+    fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let (file_rate, channels) = read_wav(path)
+            .map_err(|e| format!("Failed to read impulse response {}: {}", path.display(), e))?;
+        if channels.iter().all(|channel| channel.is_empty()) {
+            return Err(format!("Impulse response {} is empty", path.display()));
+        }
+        self.impulse = Some((file_rate, channels));
+        self.file = Some(path.to_path_buf());
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use crate::track::processors::{
+    biquad::{Biquad, Coefficients, FilterType},
+    processor::{Parameter, Processor, ProcessorKind},
+};
+
+const MAX_DELAY_SECONDS: f32 = 4.0;
+
+// Name and length in beats of the note values synced times can use, indexed by
+// the "division" parameter.
+pub const DIVISIONS: [(&str, f32); 11] = [
+    ("1/1", 4.0),
+    ("1/2", 2.0),
+    ("1/4 dotted", 1.5),
+    ("1/4", 1.0),
+    ("1/4 triplet", 2.0 / 3.0),
+    ("1/8 dotted", 0.75),
+    ("1/8", 0.5),
+    ("1/8 triplet", 1.0 / 3.0),
+    ("1/16 dotted", 0.375),
+    ("1/16", 0.25),
+    ("1/32", 0.125),
+];
+
+// Stereo delay with filtered feedback. Synced times follow the "tempo"
+// parameter, the right side can be offset for a wider image.
+pub struct Delay {
+    parameters: Vec<Parameter>,
+    sample_rate: u32,
+    buffer: Vec<[f32; 2]>,
+    index: usize,
+    // Smoothed delay times in frames, so changing the time doesn't click.
+    times: [f32; 2],
+    low_cut: Biquad,
+    high_cut: Biquad,
+    dirty: bool,
+}
+
+impl Delay {
+    pub fn new() -> Self {
+        Delay {
+            parameters: vec![
+                Parameter::new("sync", "Tempo sync", 0.0, 1.0, 1.0),
+                Parameter::new("tempo", "Tempo (BPM)", 20.0, 300.0, 120.0),
+                Parameter::new(
+                    "division",
+                    "Division",
+                    0.0,
+                    (DIVISIONS.len() - 1) as f32,
+                    6.0,
+                ),
+                Parameter::new("time", "Time (ms)", 1.0, MAX_DELAY_SECONDS * 1000.0, 375.0),
+                Parameter::new("offset", "Right offset (%)", -50.0, 50.0, 0.0),
+                Parameter::new("feedback", "Feedback", 0.0, 0.98, 0.35),
+                Parameter::new("low_cut", "Low cut (Hz)", 20.0, 2000.0, 100.0),
+                Parameter::new("high_cut", "High cut (Hz)", 1000.0, 20000.0, 8000.0),
+                Parameter::new("ping_pong", "Ping-pong", 0.0, 1.0, 0.0),
+                Parameter::new("mix", "Mix", 0.0, 1.0, 0.3),
+            ],
+            sample_rate: 48000,
+            buffer: vec![[0.0; 2]],
+            index: 0,
+            times: [0.0; 2],
+            low_cut: Biquad::new(),
+            high_cut: Biquad::new(),
+            dirty: true,
+        }
+    }
+
+    fn time_ms(&self) -> f32 {
+        if self.parameter("sync") >= 0.5 {
+            let division = self.parameter("division").round() as usize;
+            let beats = DIVISIONS[division.min(DIVISIONS.len() - 1)].1;
+            60000.0 / self.parameter("tempo") * beats
+        } else {
+            self.parameter("time")
+        }
+    }
+
+    fn target_times(&self) -> [f32; 2] {
+        let frames = self.time_ms() * 0.001 * self.sample_rate as f32;
+        let offset = 1.0 + self.parameter("offset") / 100.0;
+        let max = (self.buffer.len() - 2) as f32;
+        [frames.clamp(1.0, max), (frames * offset).clamp(1.0, max)]
+    }
+
+    fn update_filters(&mut self) {
+        self.low_cut.coefficients = Coefficients::new(
+            FilterType::HighPass,
+            self.sample_rate,
+            self.parameter("low_cut"),
+            0.707,
+            0.0,
+        );
+        self.high_cut.coefficients = Coefficients::new(
+            FilterType::LowPass,
+            self.sample_rate,
+            self.parameter("high_cut"),
+            0.707,
+            0.0,
+        );
+        self.dirty = false;
+    }
+
+    // Linear interpolation between the two frames around `delay` frames ago.
+    fn read(&self, channel: usize, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        let whole = delay as usize;
+        let fraction = delay - whole as f32;
+        let a = self.buffer[(self.index + len - whole) % len][channel];
+        let b = self.buffer[(self.index + len - whole - 1) % len][channel];
+        a + (b - a) * fraction
+    }
+}
+
+impl Processor for Delay {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::Delay
+    }
+
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        self.buffer = vec![[0.0; 2]; (MAX_DELAY_SECONDS * sample_rate as f32) as usize + 2];
+        self.index = 0;
+        self.times = self.target_times();
+        self.low_cut.reset();
+        self.high_cut.reset();
+        self.dirty = true;
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.dirty {
+            self.update_filters();
+        }
+        let targets = self.target_times();
+        let feedback = self.parameter("feedback");
+        let ping_pong = self.parameter("ping_pong") >= 0.5;
+        let mix = self.parameter("mix");
+        let smoothing = 1.0 - (-1.0 / (0.05 * self.sample_rate as f32)).exp();
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            for (time, target) in self.times.iter_mut().zip(targets) {
+                *time += (target - *time) * smoothing;
+            }
+            let wet_left = self.read(0, self.times[0]);
+            let wet_right = self.read(1, self.times[1]);
+
+            let filtered_left = self
+                .high_cut
+                .process_sample(0, self.low_cut.process_sample(0, wet_left));
+            let filtered_right = self
+                .high_cut
+                .process_sample(1, self.low_cut.process_sample(1, wet_right));
+
+            // Ping-pong feeds the input into the left side only and crosses
+            // the feedback, so repeats alternate between the sides.
+            self.buffer[self.index] = if ping_pong {
+                [
+                    (*l + *r) * 0.5 + filtered_right * feedback,
+                    filtered_left * feedback,
+                ]
+            } else {
+                [
+                    *l + filtered_left * feedback,
+                    *r + filtered_right * feedback,
+                ]
+            };
+            self.index = (self.index + 1) % self.buffer.len();
+
+            *l = *l * (1.0 - mix) + wet_left * mix;
+            *r = *r * (1.0 - mix) + wet_right * mix;
+        }
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        self.dirty = true;
+        &mut self.parameters
+    }
+}
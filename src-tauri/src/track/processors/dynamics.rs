@@ -0,0 +1,589 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+    project,
+    track::processors::processor::{db_to_gain, Parameter, Processor, ProcessorKind},
+};
+
+const GAIN_REDUCTION_EVENT_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_LOOKAHEAD_MS: f32 = 10.0;
+
+// One-pole smoothing coefficient reaching ~63% in `ms` milliseconds.
+fn time_coefficient(ms: f32, sample_rate: u32) -> f32 {
+    let samples = ms.max(0.01) * 0.001 * sample_rate.max(1) as f32;
+    (-1.0 / samples).exp()
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-9).log10()
+}
+
+// The level dynamics react to, the key signal when there is one.
+fn detector_peak(key: Option<(&[f32], &[f32])>, index: usize, left: f32, right: f32) -> f32 {
+    match key {
+        Some((key_left, key_right)) => key_left[index].abs().max(key_right[index].abs()),
+        None => left.abs().max(right.abs()),
+    }
+}
+
+fn lookahead_samples(ms: f32, sample_rate: u32) -> usize {
+    (ms.max(0.0) * 0.001 * sample_rate as f32) as usize
+}
+
+// Gain reduction in dB of the last processed block, shared with the UI side
+// as f32 bits.
+#[derive(Clone, Default)]
+pub struct GainReductionMeter(Arc<AtomicU32>);
+
+impl GainReductionMeter {
+    pub fn set(&self, db: f32) {
+        self.0.store(db.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+// Delays the signal by a fixed number of frames, allocated in `prepare`.
+pub struct DelayLine {
+    buffer: Vec<[f32; 2]>,
+    index: usize,
+    delay: usize,
+}
+
+impl DelayLine {
+    pub fn new() -> Self {
+        DelayLine {
+            buffer: vec![[0.0; 2]],
+            index: 0,
+            delay: 0,
+        }
+    }
+
+    pub fn prepare(&mut self, max_delay: usize) {
+        self.buffer = vec![[0.0; 2]; max_delay + 1];
+        self.index = 0;
+    }
+
+    pub fn set_delay(&mut self, delay: usize) {
+        self.delay = delay.min(self.buffer.len() - 1);
+    }
+
+    pub fn process(&mut self, frame: [f32; 2]) -> [f32; 2] {
+        let len = self.buffer.len();
+        self.buffer[self.index] = frame;
+        let out = self.buffer[(self.index + len - self.delay) % len];
+        self.index = (self.index + 1) % len;
+        out
+    }
+}
+
+pub struct Compressor {
+    parameters: Vec<Parameter>,
+    sample_rate: u32,
+    envelope: f32,
+    delay: DelayLine,
+    meter: GainReductionMeter,
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        Compressor {
+            parameters: vec![
+                Parameter::new("threshold", "Threshold (dB)", -60.0, 0.0, -18.0),
+                Parameter::new("ratio", "Ratio", 1.0, 20.0, 4.0),
+                Parameter::new("attack", "Attack (ms)", 0.1, 200.0, 10.0),
+                Parameter::new("release", "Release (ms)", 5.0, 2000.0, 150.0),
+                Parameter::new("knee", "Knee (dB)", 0.0, 24.0, 6.0),
+                Parameter::new("makeup", "Makeup (dB)", 0.0, 24.0, 0.0),
+                Parameter::new("lookahead", "Lookahead (ms)", 0.0, MAX_LOOKAHEAD_MS, 0.0),
+            ],
+            sample_rate: 48000,
+            envelope: 0.0,
+            delay: DelayLine::new(),
+            meter: GainReductionMeter::default(),
+        }
+    }
+
+    fn compress(&mut self, left: &mut [f32], right: &mut [f32], key: Option<(&[f32], &[f32])>) {
+        let threshold = self.parameter("threshold");
+        let ratio = self.parameter("ratio");
+        let knee = self.parameter("knee");
+        let makeup = self.parameter("makeup");
+        let attack = time_coefficient(self.parameter("attack"), self.sample_rate);
+        let release = time_coefficient(self.parameter("release"), self.sample_rate);
+        self.delay.set_delay(lookahead_samples(
+            self.parameter("lookahead"),
+            self.sample_rate,
+        ));
+
+        let mut max_reduction: f32 = 0.0;
+        for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let level = gain_to_db(detector_peak(key, i, *l, *r));
+            let target = compressor_curve(level, threshold, ratio, knee);
+            let coefficient = if target > self.envelope {
+                attack
+            } else {
+                release
+            };
+            self.envelope = coefficient * self.envelope + (1.0 - coefficient) * target;
+            max_reduction = max_reduction.max(self.envelope);
+
+            let gain = db_to_gain(makeup - self.envelope);
+            let [delayed_left, delayed_right] = self.delay.process([*l, *r]);
+            *l = delayed_left * gain;
+            *r = delayed_right * gain;
+        }
+        self.meter.set(max_reduction);
+    }
+}
+
+// Gain reduction in dB for a level of `level` dB, with a soft knee of `knee`
+// dB centred on the threshold.
+fn compressor_curve(level: f32, threshold: f32, ratio: f32, knee: f32) -> f32 {
+    let over = level - threshold;
+    let slope = 1.0 - 1.0 / ratio.max(1.0);
+    if knee > 0.0 && over.abs() <= knee / 2.0 {
+        slope * (over + knee / 2.0).powi(2) / (2.0 * knee)
+    } else if over > 0.0 {
+        slope * over
+    } else {
+        0.0
+    }
+}
+
+impl Processor for Compressor {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::Compressor
+    }
+
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        self.envelope = 0.0;
+        self.delay
+            .prepare(lookahead_samples(MAX_LOOKAHEAD_MS, sample_rate));
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.compress(left, right, None);
+    }
+
+    fn process_keyed(&mut self, left: &mut [f32], right: &mut [f32], key: (&[f32], &[f32])) {
+        self.compress(left, right, Some(key));
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        &mut self.parameters
+    }
+
+    fn gain_reduction_meter(&self) -> Option<GainReductionMeter> {
+        Some(self.meter.clone())
+    }
+
+    fn use_gain_reduction_meter(&mut self, meter: GainReductionMeter) {
+        self.meter = meter;
+    }
+}
+
+// Brickwall limiter for the master. Peaks are estimated between samples with
+// cubic interpolation at 4x, and the gain is ramped down over the lookahead so
+// it has fully reached the needed reduction when the peak comes out.
+pub struct Limiter {
+    parameters: Vec<Parameter>,
+    sample_rate: u32,
+    delay: DelayLine,
+    history: [[f32; 3]; 2],
+    targets: Vec<f32>,
+    holds: Vec<f32>,
+    hold_sum: f32,
+    index: usize,
+    window: usize,
+    release_gain: f32,
+    meter: GainReductionMeter,
+}
+
+impl Limiter {
+    pub fn new() -> Self {
+        Limiter {
+            parameters: vec![
+                Parameter::new("ceiling", "Ceiling (dBTP)", -12.0, 0.0, -1.0),
+                Parameter::new("input", "Input gain (dB)", 0.0, 24.0, 0.0),
+                Parameter::new("release", "Release (ms)", 1.0, 1000.0, 100.0),
+                Parameter::new("lookahead", "Lookahead (ms)", 0.5, 5.0, 2.0),
+            ],
+            sample_rate: 48000,
+            delay: DelayLine::new(),
+            history: [[0.0; 3]; 2],
+            targets: vec![1.0],
+            holds: vec![1.0],
+            hold_sum: 1.0,
+            index: 0,
+            window: 1,
+            release_gain: 1.0,
+            meter: GainReductionMeter::default(),
+        }
+    }
+
+    // Largest absolute value of the signal between the previous sample and
+    // `sample`, including three interpolated points.
+    fn true_peak(&mut self, channel: usize, sample: f32) -> f32 {
+        let [p0, p1, p2] = self.history[channel];
+        self.history[channel] = [p1, p2, sample];
+        let p3 = sample;
+
+        let mut peak = p2.abs().max(p1.abs());
+        for t in [0.25_f32, 0.5, 0.75] {
+            // Catmull-Rom between p1 and p2.
+            let value = 0.5
+                * ((2.0 * p1)
+                    + (-p0 + p2) * t
+                    + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+                    + (-p0 + 3.0 * p1 - 3.0 * p2 + p3) * t * t * t);
+            peak = peak.max(value.abs());
+        }
+        peak
+    }
+
+    fn set_window(&mut self, window: usize) {
+        let window = window.clamp(1, self.targets.len());
+        if window != self.window {
+            self.window = window;
+            self.targets[..window].fill(self.release_gain);
+            self.holds[..window].fill(self.release_gain);
+            self.hold_sum = self.release_gain * window as f32;
+            self.index = 0;
+        }
+    }
+}
+
+impl Processor for Limiter {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::Limiter
+    }
+
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        let max_window = lookahead_samples(5.0, sample_rate).max(1);
+        self.delay.prepare(max_window);
+        self.targets = vec![1.0; max_window];
+        self.holds = vec![1.0; max_window];
+        self.history = [[0.0; 3]; 2];
+        self.release_gain = 1.0;
+        self.window = 0;
+        self.set_window(1);
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let ceiling = db_to_gain(self.parameter("ceiling"));
+        let input = db_to_gain(self.parameter("input"));
+        let release = time_coefficient(self.parameter("release"), self.sample_rate);
+        self.set_window(lookahead_samples(
+            self.parameter("lookahead"),
+            self.sample_rate,
+        ));
+        let window = self.window;
+        // The box filter reaches the minimum `window - 1` frames late and the
+        // true peak estimate trails the input by one more.
+        self.delay.set_delay(window);
+
+        let mut min_gain: f32 = 1.0;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let (in_left, in_right) = (*l * input, *r * input);
+            let peak = self.true_peak(0, in_left).max(self.true_peak(1, in_right));
+            let target = if peak > ceiling { ceiling / peak } else { 1.0 };
+
+            // Drops at once and recovers with the release time.
+            self.release_gain = if target < self.release_gain {
+                target
+            } else {
+                release * self.release_gain + (1.0 - release) * target
+            };
+            self.targets[self.index] = self.release_gain;
+            let hold = self.targets[..window]
+                .iter()
+                .fold(1.0_f32, |min, gain| min.min(*gain));
+            self.hold_sum += hold - self.holds[self.index];
+            self.holds[self.index] = hold;
+            self.index = (self.index + 1) % window;
+            let gain = (self.hold_sum / window as f32).min(1.0);
+            min_gain = min_gain.min(gain);
+
+            let [delayed_left, delayed_right] = self.delay.process([in_left, in_right]);
+            *l = (delayed_left * gain).clamp(-ceiling, ceiling);
+            *r = (delayed_right * gain).clamp(-ceiling, ceiling);
+        }
+        self.meter.set(-gain_to_db(min_gain));
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        &mut self.parameters
+    }
+
+    fn gain_reduction_meter(&self) -> Option<GainReductionMeter> {
+        Some(self.meter.clone())
+    }
+
+    fn use_gain_reduction_meter(&mut self, meter: GainReductionMeter) {
+        self.meter = meter;
+    }
+}
+
+// Downward expander, with a high ratio and full range it works as a gate.
+pub struct Gate {
+    parameters: Vec<Parameter>,
+    sample_rate: u32,
+    level: f32,
+    reduction: f32,
+    hold_counter: usize,
+    meter: GainReductionMeter,
+}
+
+impl Gate {
+    pub fn new() -> Self {
+        Gate {
+            parameters: vec![
+                Parameter::new("threshold", "Threshold (dB)", -80.0, 0.0, -40.0),
+                Parameter::new("ratio", "Ratio", 1.0, 20.0, 20.0),
+                Parameter::new("range", "Range (dB)", 0.0, 80.0, 80.0),
+                Parameter::new("attack", "Attack (ms)", 0.05, 50.0, 0.5),
+                Parameter::new("hold", "Hold (ms)", 0.0, 500.0, 50.0),
+                Parameter::new("release", "Release (ms)", 5.0, 2000.0, 150.0),
+            ],
+            sample_rate: 48000,
+            level: -120.0,
+            reduction: 0.0,
+            hold_counter: 0,
+            meter: GainReductionMeter::default(),
+        }
+    }
+
+    fn gate(&mut self, left: &mut [f32], right: &mut [f32], key: Option<(&[f32], &[f32])>) {
+        let threshold = self.parameter("threshold");
+        let ratio = self.parameter("ratio");
+        let range = self.parameter("range");
+        let attack = time_coefficient(self.parameter("attack"), self.sample_rate);
+        let release = time_coefficient(self.parameter("release"), self.sample_rate);
+        let hold = lookahead_samples(self.parameter("hold"), self.sample_rate);
+        let detector_release = time_coefficient(10.0, self.sample_rate);
+
+        let mut max_reduction: f32 = 0.0;
+        for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let peak = gain_to_db(detector_peak(key, i, *l, *r));
+            self.level = if peak > self.level {
+                peak
+            } else {
+                detector_release * self.level + (1.0 - detector_release) * peak
+            };
+
+            let target = if self.level >= threshold {
+                self.hold_counter = hold;
+                0.0
+            } else if self.hold_counter > 0 {
+                self.hold_counter -= 1;
+                0.0
+            } else {
+                ((threshold - self.level) * (ratio - 1.0)).min(range)
+            };
+
+            // Opening uses the attack time, closing the release time.
+            let coefficient = if target < self.reduction {
+                attack
+            } else {
+                release
+            };
+            self.reduction = coefficient * self.reduction + (1.0 - coefficient) * target;
+            max_reduction = max_reduction.max(self.reduction);
+
+            let gain = db_to_gain(-self.reduction);
+            *l *= gain;
+            *r *= gain;
+        }
+        self.meter.set(max_reduction);
+    }
+}
+
+impl Processor for Gate {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::Gate
+    }
+
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        self.level = -120.0;
+        self.reduction = 0.0;
+        self.hold_counter = 0;
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.gate(left, right, None);
+    }
+
+    fn process_keyed(&mut self, left: &mut [f32], right: &mut [f32], key: (&[f32], &[f32])) {
+        self.gate(left, right, Some(key));
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        &mut self.parameters
+    }
+
+    fn gain_reduction_meter(&self) -> Option<GainReductionMeter> {
+        Some(self.meter.clone())
+    }
+
+    fn use_gain_reduction_meter(&mut self, meter: GainReductionMeter) {
+        self.meter = meter;
+    }
+}
+
+// Pulls the signal down by a fixed depth while the key is above the
+// threshold, for ducking music under a voice. Without a key it reacts to its
+// own input.
+pub struct Ducker {
+    parameters: Vec<Parameter>,
+    sample_rate: u32,
+    reduction: f32,
+    hold_counter: usize,
+    meter: GainReductionMeter,
+}
+
+impl Ducker {
+    pub fn new() -> Self {
+        Ducker {
+            parameters: vec![
+                Parameter::new("threshold", "Threshold (dB)", -60.0, 0.0, -30.0),
+                Parameter::new("depth", "Depth (dB)", 0.0, 40.0, 12.0),
+                Parameter::new("attack", "Attack (ms)", 0.1, 100.0, 5.0),
+                Parameter::new("hold", "Hold (ms)", 0.0, 1000.0, 100.0),
+                Parameter::new("release", "Release (ms)", 10.0, 2000.0, 250.0),
+            ],
+            sample_rate: 48000,
+            reduction: 0.0,
+            hold_counter: 0,
+            meter: GainReductionMeter::default(),
+        }
+    }
+
+    fn duck(&mut self, left: &mut [f32], right: &mut [f32], key: Option<(&[f32], &[f32])>) {
+        let threshold = db_to_gain(self.parameter("threshold"));
+        let depth = self.parameter("depth");
+        let attack = time_coefficient(self.parameter("attack"), self.sample_rate);
+        let release = time_coefficient(self.parameter("release"), self.sample_rate);
+        let hold = lookahead_samples(self.parameter("hold"), self.sample_rate);
+
+        let mut max_reduction: f32 = 0.0;
+        for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let target = if detector_peak(key, i, *l, *r) >= threshold {
+                self.hold_counter = hold;
+                depth
+            } else if self.hold_counter > 0 {
+                self.hold_counter -= 1;
+                depth
+            } else {
+                0.0
+            };
+
+            let coefficient = if target > self.reduction {
+                attack
+            } else {
+                release
+            };
+            self.reduction = coefficient * self.reduction + (1.0 - coefficient) * target;
+            max_reduction = max_reduction.max(self.reduction);
+
+            let gain = db_to_gain(-self.reduction);
+            *l *= gain;
+            *r *= gain;
+        }
+        self.meter.set(max_reduction);
+    }
+}
+
+impl Processor for Ducker {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::Ducker
+    }
+
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        self.reduction = 0.0;
+        self.hold_counter = 0;
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.duck(left, right, None);
+    }
+
+    fn process_keyed(&mut self, left: &mut [f32], right: &mut [f32], key: (&[f32], &[f32])) {
+        self.duck(left, right, Some(key));
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        &mut self.parameters
+    }
+
+    fn gain_reduction_meter(&self) -> Option<GainReductionMeter> {
+        Some(self.meter.clone())
+    }
+
+    fn use_gain_reduction_meter(&mut self, meter: GainReductionMeter) {
+        self.meter = meter;
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GainReduction {
+    pub track: String,
+    pub insert_id: u64,
+    pub gain_reduction: f32,
+}
+
+// Emits "gain-reduction" with the reduction of every dynamics insert, only
+// while some of them are reducing or just stopped.
+pub fn spawn_gain_reduction_emitter(app_handle: AppHandle) {
+    thread::spawn(move || {
+        let mut was_reducing = false;
+        loop {
+            thread::sleep(GAIN_REDUCTION_EVENT_INTERVAL);
+            let state_mixer_guard = app_handle.state::<project::states::StateMixerGuard>();
+            let reductions = match state_mixer_guard.0.lock() {
+                Ok(state_mixer) => state_mixer.gain_reductions(),
+                Err(_) => continue,
+            };
+
+            let reducing = reductions.iter().any(|r| r.gain_reduction > 0.01);
+            if reducing || was_reducing {
+                if let Err(e) = app_handle.emit("gain-reduction", reductions) {
+                    eprintln!("Failed to emit gain reduction: {}", e);
+                }
+            }
+            was_reducing = reducing;
+        }
+    });
+}
@@ -0,0 +1,176 @@
+use crate::track::processors::{
+    biquad::{Biquad, Coefficients, FilterType},
+    processor::{Parameter, Processor, ProcessorKind},
+};
+
+// Id prefix, display name, filter type and default frequency of every band,
+// in processing order. Pass filters start switched off.
+const BANDS: [(&str, &str, FilterType, f32); 8] = [
+    ("hp", "High pass", FilterType::HighPass, 30.0),
+    ("ls", "Low shelf", FilterType::LowShelf, 100.0),
+    ("p1", "Band 1", FilterType::Peak, 250.0),
+    ("p2", "Band 2", FilterType::Peak, 1000.0),
+    ("p3", "Band 3", FilterType::Peak, 3000.0),
+    ("p4", "Band 4", FilterType::Peak, 6000.0),
+    ("hs", "High shelf", FilterType::HighShelf, 10000.0),
+    ("lp", "Low pass", FilterType::LowPass, 18000.0),
+];
+
+// Where a band's parameters sit in `parameters`, so processing never has to
+// look them up by id.
+#[derive(Clone, Copy)]
+struct BandParameters {
+    on: usize,
+    frequency: usize,
+    gain: Option<usize>,
+    q: usize,
+}
+
+pub struct Equalizer {
+    parameters: Vec<Parameter>,
+    bands: Vec<BandParameters>,
+    filters: [Biquad; 8],
+    enabled: [bool; 8],
+    sample_rate: u32,
+    dirty: bool,
+}
+
+impl Equalizer {
+    pub fn new() -> Self {
+        let mut parameters = Vec::new();
+        let mut bands = Vec::new();
+        for (id, name, filter_type, frequency) in BANDS {
+            let pass = matches!(filter_type, FilterType::HighPass | FilterType::LowPass);
+            let on = parameters.len();
+            parameters.push(Parameter::new(
+                &format!("{}_on", id),
+                &format!("{} on", name),
+                0.0,
+                1.0,
+                if pass { 0.0 } else { 1.0 },
+            ));
+            let frequency_index = parameters.len();
+            parameters.push(Parameter::new(
+                &format!("{}_freq", id),
+                &format!("{} frequency (Hz)", name),
+                20.0,
+                20000.0,
+                frequency,
+            ));
+            let gain = (!pass).then_some(parameters.len());
+            if !pass {
+                parameters.push(Parameter::new(
+                    &format!("{}_gain", id),
+                    &format!("{} gain (dB)", name),
+                    -24.0,
+                    24.0,
+                    0.0,
+                ));
+            }
+            let q = parameters.len();
+            parameters.push(Parameter::new(
+                &format!("{}_q", id),
+                &format!("{} Q", name),
+                0.1,
+                18.0,
+                0.707,
+            ));
+            bands.push(BandParameters {
+                on,
+                frequency: frequency_index,
+                gain,
+                q,
+            });
+        }
+
+        Equalizer {
+            parameters,
+            bands,
+            filters: [Biquad::new(); 8],
+            enabled: [false; 8],
+            sample_rate: 48000,
+            dirty: true,
+        }
+    }
+
+    fn band_enabled(&self, band: usize) -> bool {
+        self.parameters[self.bands[band].on].value >= 0.5
+    }
+
+    fn band_coefficients(&self, band: usize) -> Coefficients {
+        let indices = self.bands[band];
+        Coefficients::new(
+            BANDS[band].2,
+            self.sample_rate,
+            self.parameters[indices.frequency].value,
+            self.parameters[indices.q].value,
+            indices.gain.map_or(0.0, |gain| self.parameters[gain].value),
+        )
+    }
+
+    fn update_filters(&mut self) {
+        for band in 0..BANDS.len() {
+            self.enabled[band] = self.band_enabled(band);
+            self.filters[band].coefficients = self.band_coefficients(band);
+        }
+        self.dirty = false;
+    }
+}
+
+impl Processor for Equalizer {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::Equalizer
+    }
+
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        for filter in &mut self.filters {
+            filter.reset();
+        }
+        self.dirty = true;
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.dirty {
+            self.update_filters();
+        }
+
+        for (band, filter) in self.filters.iter_mut().enumerate() {
+            if !self.enabled[band] {
+                continue;
+            }
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                *l = filter.process_sample(0, *l);
+                *r = filter.process_sample(1, *r);
+            }
+        }
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        self.dirty = true;
+        &mut self.parameters
+    }
+
+    fn frequency_response(&self, frequencies: &[f32]) -> Option<Vec<f32>> {
+        let bands = (0..BANDS.len())
+            .filter(|band| self.band_enabled(*band))
+            .map(|band| self.band_coefficients(band))
+            .collect::<Vec<_>>();
+
+        Some(
+            frequencies
+                .iter()
+                .map(|frequency| {
+                    bands
+                        .iter()
+                        .map(|c| c.magnitude_db(*frequency, self.sample_rate))
+                        .sum()
+                })
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,10 @@
+pub mod biquad;
+pub mod chain;
+pub mod convolution;
+pub mod delay;
+pub mod dynamics;
+pub mod equalizer;
+pub mod fft;
+pub mod processor;
+pub mod reverb;
+pub mod utility;
@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::track::{self, processors::dynamics::GainReductionMeter};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProcessorKind {
+    Utility,
+    Equalizer,
+    Compressor,
+    Limiter,
+    Gate,
+    Ducker,
+    Delay,
+    Reverb,
+    ConvolutionReverb,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Parameter {
+    pub id: String,
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub value: f32,
+}
+
+impl Parameter {
+    pub fn new(id: &str, name: &str, min: f32, max: f32, default: f32) -> Self {
+        Parameter {
+            id: id.to_string(),
+            name: name.to_string(),
+            min,
+            max,
+            default,
+            value: default,
+        }
+    }
+
+    pub fn set(&mut self, value: f32) {
+        self.value = value.clamp(self.min, self.max);
+    }
+}
+
+// Insert effects run on the audio thread between `prepare` calls, `process`
+// must not allocate or block and gets at most `max_block` frames.
+pub trait Processor: Send {
+    fn kind(&self) -> ProcessorKind;
+    fn prepare(&mut self, sample_rate: u32, max_block: usize);
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]);
+    fn parameters(&self) -> &[Parameter];
+    fn parameters_mut(&mut self) -> &mut [Parameter];
+
+    // Gets the sidechain key instead of `process` when the insert has one,
+    // with as many frames as `left` and `right`. Processors that don't listen
+    // to a key ignore it.
+    fn process_keyed(&mut self, left: &mut [f32], right: &mut [f32], _key: (&[f32], &[f32])) {
+        self.process(left, right);
+    }
+
+    // Returns false when the processor has no such parameter.
+    fn set_parameter(&mut self, id: &str, value: f32) -> bool {
+        match self.parameters_mut().iter_mut().find(|p| p.id == id) {
+            Some(parameter) => {
+                parameter.set(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn parameter(&self, id: &str) -> f32 {
+        self.parameters()
+            .iter()
+            .find(|p| p.id == id)
+            .map_or(0.0, |p| p.value)
+    }
+
+    // Gain in dB at each frequency, for processors that are linear filters.
+    fn frequency_response(&self, _frequencies: &[f32]) -> Option<Vec<f32>> {
+        None
+    }
+
+    // File a processor reads its data from, like an impulse response. A
+    // loaded file is used from the next `prepare`.
+    fn file(&self) -> Option<&Path> {
+        None
+    }
+
+    fn load_file(&mut self, _path: &Path) -> Result<(), String> {
+        Err(format!("{:?} doesn't load files", self.kind()))
+    }
+
+    // Shared gain reduction readout, for dynamics processors.
+    fn gain_reduction_meter(&self) -> Option<GainReductionMeter> {
+        None
+    }
+
+    // Reports into `meter` from now on, so a new instance of an insert keeps
+    // the readout the UI already holds.
+    fn use_gain_reduction_meter(&mut self, _meter: GainReductionMeter) {}
+}
+
+pub fn create(kind: ProcessorKind) -> Box<dyn Processor> {
+    match kind {
+        ProcessorKind::Utility => Box::new(track::processors::utility::Utility::new()),
+        ProcessorKind::Equalizer => Box::new(track::processors::equalizer::Equalizer::new()),
+        ProcessorKind::Compressor => Box::new(track::processors::dynamics::Compressor::new()),
+        ProcessorKind::Limiter => Box::new(track::processors::dynamics::Limiter::new()),
+        ProcessorKind::Gate => Box::new(track::processors::dynamics::Gate::new()),
+        ProcessorKind::Ducker => Box::new(track::processors::dynamics::Ducker::new()),
+        ProcessorKind::Delay => Box::new(track::processors::delay::Delay::new()),
+        ProcessorKind::Reverb => Box::new(track::processors::reverb::Reverb::new()),
+        ProcessorKind::ConvolutionReverb => {
+            Box::new(track::processors::convolution::ConvolutionReverb::new())
+        }
+    }
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
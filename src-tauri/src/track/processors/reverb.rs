@@ -0,0 +1,203 @@
+use crate::track::processors::{
+    dynamics::DelayLine,
+    processor::{Parameter, Processor, ProcessorKind},
+};
+
+// Freeverb tunings at 44.1kHz, the right channel is spread a little longer.
+const COMB_LENGTHS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_LENGTHS: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const MAX_ROOM_SCALE: f32 = 1.5;
+const MAX_PRE_DELAY_MS: f32 = 200.0;
+const INPUT_GAIN: f32 = 0.015;
+const WET_GAIN: f32 = 3.0;
+
+fn room_scale(size: f32) -> f32 {
+    0.4 + size * (MAX_ROOM_SCALE - 0.4)
+}
+
+// Feedback comb with a one-pole lowpass in the loop for damping.
+struct Comb {
+    buffer: Vec<f32>,
+    length: usize,
+    index: usize,
+    feedback: f32,
+    store: f32,
+}
+
+impl Comb {
+    fn new() -> Self {
+        Comb {
+            buffer: vec![0.0],
+            length: 1,
+            index: 0,
+            feedback: 0.0,
+            store: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.buffer[self.index] = input + self.store * self.feedback;
+        self.index = (self.index + 1) % self.length;
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    length: usize,
+    index: usize,
+}
+
+impl Allpass {
+    fn new() -> Self {
+        Allpass {
+            buffer: vec![0.0],
+            length: 1,
+            index: 0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.index];
+        self.buffer[self.index] = input + delayed * 0.5;
+        self.index = (self.index + 1) % self.length;
+        delayed - input
+    }
+}
+
+// Algorithmic reverb after Freeverb: parallel damped combs into series
+// allpasses per side. Room size scales the delay lengths, the comb feedback
+// is derived from the decay time so the two can be set independently.
+pub struct Reverb {
+    parameters: Vec<Parameter>,
+    sample_rate: u32,
+    pre_delay: DelayLine,
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+    dirty: bool,
+}
+
+impl Reverb {
+    pub fn new() -> Self {
+        Reverb {
+            parameters: vec![
+                Parameter::new("size", "Room size", 0.0, 1.0, 0.5),
+                Parameter::new("decay", "Decay (s)", 0.1, 20.0, 2.0),
+                Parameter::new("pre_delay", "Pre-delay (ms)", 0.0, MAX_PRE_DELAY_MS, 10.0),
+                Parameter::new("damping", "Damping", 0.0, 1.0, 0.5),
+                Parameter::new("mix", "Mix", 0.0, 1.0, 0.25),
+            ],
+            sample_rate: 48000,
+            pre_delay: DelayLine::new(),
+            combs: [
+                COMB_LENGTHS.iter().map(|_| Comb::new()).collect(),
+                COMB_LENGTHS.iter().map(|_| Comb::new()).collect(),
+            ],
+            allpasses: [
+                ALLPASS_LENGTHS.iter().map(|_| Allpass::new()).collect(),
+                ALLPASS_LENGTHS.iter().map(|_| Allpass::new()).collect(),
+            ],
+            dirty: true,
+        }
+    }
+
+    fn scaled_length(&self, length: usize, channel: usize, scale: f32) -> usize {
+        let length = length + channel * STEREO_SPREAD;
+        ((length as f32 * scale * self.sample_rate as f32 / 44100.0) as usize).max(1)
+    }
+
+    // Only changes lengths within the buffers allocated in `prepare`.
+    fn update_lengths(&mut self) {
+        let scale = room_scale(self.parameter("size"));
+        let decay = self.parameter("decay");
+        for channel in 0..2 {
+            for (i, length) in COMB_LENGTHS.iter().enumerate() {
+                let length = self
+                    .scaled_length(*length, channel, scale)
+                    .min(self.combs[channel][i].buffer.len());
+                let comb = &mut self.combs[channel][i];
+                comb.length = length;
+                comb.index %= length;
+                // -60dB after `decay` seconds.
+                comb.feedback =
+                    10.0_f32.powf(-3.0 * length as f32 / (decay * self.sample_rate as f32));
+            }
+            for (i, length) in ALLPASS_LENGTHS.iter().enumerate() {
+                let length = self
+                    .scaled_length(*length, channel, scale)
+                    .min(self.allpasses[channel][i].buffer.len());
+                let allpass = &mut self.allpasses[channel][i];
+                allpass.length = length;
+                allpass.index %= length;
+            }
+        }
+        let pre_delay = self.parameter("pre_delay") * 0.001 * self.sample_rate as f32;
+        self.pre_delay.set_delay(pre_delay as usize);
+        self.dirty = false;
+    }
+}
+
+impl Processor for Reverb {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::Reverb
+    }
+
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.sample_rate = sample_rate;
+        for channel in 0..2 {
+            for (i, length) in COMB_LENGTHS.iter().enumerate() {
+                let max = self.scaled_length(*length, channel, MAX_ROOM_SCALE);
+                self.combs[channel][i] = Comb {
+                    buffer: vec![0.0; max],
+                    ..Comb::new()
+                };
+            }
+            for (i, length) in ALLPASS_LENGTHS.iter().enumerate() {
+                let max = self.scaled_length(*length, channel, MAX_ROOM_SCALE);
+                self.allpasses[channel][i] = Allpass {
+                    buffer: vec![0.0; max],
+                    ..Allpass::new()
+                };
+            }
+        }
+        self.pre_delay
+            .prepare((MAX_PRE_DELAY_MS * 0.001 * sample_rate as f32) as usize);
+        self.dirty = true;
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.dirty {
+            self.update_lengths();
+        }
+        let damping = self.parameter("damping");
+        let mix = self.parameter("mix");
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let [delayed, _] = self.pre_delay.process([(*l + *r) * INPUT_GAIN, 0.0]);
+            let mut wet = [0.0; 2];
+            for (channel, out) in wet.iter_mut().enumerate() {
+                for comb in &mut self.combs[channel] {
+                    *out += comb.process(delayed, damping);
+                }
+                for allpass in &mut self.allpasses[channel] {
+                    *out = allpass.process(*out);
+                }
+            }
+
+            *l = *l * (1.0 - mix) + wet[0] * WET_GAIN * mix;
+            *r = *r * (1.0 - mix) + wet[1] * WET_GAIN * mix;
+        }
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        self.dirty = true;
+        &mut self.parameters
+    }
+}
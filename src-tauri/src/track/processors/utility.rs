@@ -0,0 +1,57 @@
+use crate::track::processors::processor::{db_to_gain, Parameter, Processor, ProcessorKind};
+
+// Trim, stereo width and polarity, the simplest insert there is.
+pub struct Utility {
+    parameters: Vec<Parameter>,
+}
+
+impl Utility {
+    pub fn new() -> Self {
+        Utility {
+            parameters: vec![
+                Parameter::new("gain", "Gain (dB)", -48.0, 24.0, 0.0),
+                Parameter::new("width", "Width", 0.0, 2.0, 1.0),
+                Parameter::new("invert_left", "Invert left", 0.0, 1.0, 0.0),
+                Parameter::new("invert_right", "Invert right", 0.0, 1.0, 0.0),
+            ],
+        }
+    }
+}
+
+impl Processor for Utility {
+    fn kind(&self) -> ProcessorKind {
+        ProcessorKind::Utility
+    }
+
+    fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {}
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let gain = db_to_gain(self.parameter("gain"));
+        let width = self.parameter("width");
+        let left_gain = if self.parameter("invert_left") >= 0.5 {
+            -gain
+        } else {
+            gain
+        };
+        let right_gain = if self.parameter("invert_right") >= 0.5 {
+            -gain
+        } else {
+            gain
+        };
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let mid = (*l + *r) * 0.5;
+            let side = (*l - *r) * 0.5 * width;
+            *l = (mid + side) * left_gain;
+            *r = (mid - side) * right_gain;
+        }
+    }
+
+    fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    fn parameters_mut(&mut self) -> &mut [Parameter] {
+        &mut self.parameters
+    }
+}
@@ -1,20 +1,89 @@
+use serde::{Deserialize, Serialize};
+
 use crate::track;
 
-#[derive(bincode::Encode, bincode::Decode)]
+#[derive(Serialize, Deserialize)]
 pub struct InputTrackRaw {
     pub name: String,
+    pub kind: track::tracks::TrackKind,
     pub gain: f32,
     pub pan: f32,
-    pub source_type: track::sources::source::AudioSourceRaw,
+    pub mute: bool,
+    pub record: bool,
+    pub monitor: bool,
+    pub solo: bool,
+    pub solo_safe: bool,
+    pub source_type: Option<track::sources::source::AudioSourceRaw>,
+    pub clips: Vec<track::clips::ClipRaw>,
+    pub take_folders: Vec<track::takes::TakeFolderRaw>,
+    pub inserts: Vec<track::processors::chain::ProcessorRaw>,
+    pub sends: Vec<track::sends::AuxSend>,
+    pub output: Option<String>,
+    pub automation_mode: track::automation::AutomationMode,
+    pub automation: Vec<track::automation::AutomationLane>,
+}
+
+impl InputTrackRaw {
+    pub fn rescale(&mut self, from: u32, to: u32) {
+        for clip in &mut self.clips {
+            clip.rescale(from, to);
+        }
+        for folder in &mut self.take_folders {
+            folder.rescale(from, to);
+        }
+        for lane in &mut self.automation {
+            lane.rescale(from, to);
+        }
+    }
 }
 
 impl From<&track::tracks::InputTrack> for InputTrackRaw {
     fn from(value: &track::tracks::InputTrack) -> Self {
         InputTrackRaw {
             name: value.name.clone(),
+            kind: value.kind,
+            gain: value.gain,
+            pan: value.pan,
+            mute: value.mute,
+            record: value.record,
+            monitor: value.monitor,
+            solo: value.solo,
+            solo_safe: value.solo_safe,
+            source_type: value.source.as_ref().map(|source| source.kind()),
+            clips: value
+                .clips
+                .iter()
+                .map(track::clips::ClipRaw::from)
+                .collect(),
+            take_folders: value
+                .take_folders
+                .iter()
+                .map(track::takes::TakeFolderRaw::from)
+                .collect(),
+            inserts: value.inserts.to_raw(),
+            sends: value.sends.clone(),
+            output: value.output.clone(),
+            automation_mode: value.automation.mode,
+            automation: value.automation.lanes.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OutputTrackRaw {
+    pub gain: f32,
+    pub pan: f32,
+    pub inserts: Vec<track::processors::chain::ProcessorRaw>,
+    pub limiter: track::processors::chain::ProcessorRaw,
+}
+
+impl From<&track::tracks::OutputTrack> for OutputTrackRaw {
+    fn from(value: &track::tracks::OutputTrack) -> Self {
+        OutputTrackRaw {
             gain: value.gain,
             pan: value.pan,
-            source_type: value.source.kind(),
+            inserts: value.inserts.to_raw(),
+            limiter: track::processors::chain::ProcessorRaw::from(&value.limiter),
         }
     }
 }
@@ -0,0 +1,136 @@
+use crate::track;
+
+// A track, the buses it feeds through its output and sends, and the tracks
+// its inserts take a sidechain key from.
+pub struct RoutingNode {
+    pub name: String,
+    pub targets: Vec<String>,
+    pub keys: Vec<String>,
+}
+
+impl RoutingNode {
+    pub fn from_track(track: &track::tracks::InputTrack) -> Self {
+        RoutingNode {
+            name: track.name.clone(),
+            targets: track
+                .output
+                .iter()
+                .cloned()
+                .chain(track.sends.iter().map(|send| send.bus.clone()))
+                .collect(),
+            keys: track
+                .inserts
+                .inserts
+                .iter()
+                .filter_map(|insert| insert.key.clone())
+                .collect(),
+        }
+    }
+}
+
+// Orders the tracks so every bus comes after all tracks feeding it and every
+// track after its keys, keeping list order where the routing allows. Names
+// that aren't in `nodes` are ignored. Fails with the names of the tracks a
+// cycle keeps from being ordered.
+pub fn processing_order(nodes: &[RoutingNode]) -> Result<Vec<usize>, Vec<String>> {
+    let position = |name: &String| nodes.iter().position(|other| other.name == *name);
+    let mut edges = nodes
+        .iter()
+        .map(|node| node.targets.iter().filter_map(position).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    for (index, node) in nodes.iter().enumerate() {
+        for key in node.keys.iter().filter_map(position) {
+            edges[key].push(index);
+        }
+    }
+    let mut feeders = vec![0; nodes.len()];
+    for targets in &edges {
+        for target in targets {
+            feeders[*target] += 1;
+        }
+    }
+
+    let mut order = Vec::with_capacity(nodes.len());
+    let mut done = vec![false; nodes.len()];
+    while let Some(next) = (0..nodes.len()).find(|i| !done[*i] && feeders[*i] == 0) {
+        done[next] = true;
+        order.push(next);
+        for target in &edges[next] {
+            feeders[*target] -= 1;
+        }
+    }
+
+    if order.len() == nodes.len() {
+        Ok(order)
+    } else {
+        Err(nodes
+            .iter()
+            .zip(done)
+            .filter(|(_, done)| !done)
+            .map(|(node, _)| node.name.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, targets: &[&str], keys: &[&str]) -> RoutingNode {
+        RoutingNode {
+            name: name.to_string(),
+            targets: targets.iter().map(|target| target.to_string()).collect(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn buses_come_after_their_feeders() {
+        let nodes = [
+            node("Drums", &["Master Bus"], &[]),
+            node("Master Bus", &[], &[]),
+            node("Reverb", &["Drums"], &[]),
+            node("Vocal", &["Reverb", "Master Bus"], &[]),
+        ];
+        assert_eq!(processing_order(&nodes), Ok(vec![3, 2, 0, 1]));
+    }
+
+    #[test]
+    fn list_order_is_kept_where_routing_allows() {
+        let nodes = [
+            node("A", &[], &[]),
+            node("B", &[], &[]),
+            node("C", &[], &[]),
+        ];
+        assert_eq!(processing_order(&nodes), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn keyed_tracks_come_after_their_keys() {
+        let nodes = [node("Bass", &[], &["Kick"]), node("Kick", &[], &[])];
+        assert_eq!(processing_order(&nodes), Ok(vec![1, 0]));
+    }
+
+    #[test]
+    fn unknown_targets_are_ignored() {
+        let nodes = [node("A", &["Gone"], &["Also Gone"])];
+        assert_eq!(processing_order(&nodes), Ok(vec![0]));
+    }
+
+    #[test]
+    fn cycles_name_the_tracks_in_them() {
+        let nodes = [
+            node("Guitar", &["Bus A"], &[]),
+            node("Bus A", &["Bus B"], &[]),
+            node("Bus B", &["Bus A"], &[]),
+        ];
+        assert_eq!(
+            processing_order(&nodes),
+            Err(vec!["Bus A".to_string(), "Bus B".to_string()])
+        );
+
+        // A track can't key from the bus it feeds.
+        let nodes = [node("Bus", &[], &[]), node("Track", &["Bus"], &["Bus"])];
+        assert!(processing_order(&nodes).is_err());
+    }
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+// A send from a track to a bus, by the bus' name. Pre-fader sends tap the
+// track after its inserts but before gain and pan.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuxSend {
+    pub bus: String,
+    pub level: f32,
+    pub pre_fader: bool,
+}
+
+impl AuxSend {
+    pub fn new(bus: &str, level: f32, pre_fader: bool) -> Self {
+        AuxSend {
+            bus: bus.to_string(),
+            level,
+            pre_fader,
+        }
+    }
+}
+
+// Adds the send or updates the one already going to the same bus.
+pub fn set_send(sends: &mut Vec<AuxSend>, send: AuxSend) {
+    match sends.iter_mut().find(|existing| existing.bus == send.bus) {
+        Some(existing) => *existing = send,
+        None => sends.push(send),
+    }
+}
+
+pub fn remove_send(sends: &mut Vec<AuxSend>, bus: &str) -> bool {
+    let length = sends.len();
+    sends.retain(|send| send.bus != bus);
+    sends.len() != length
+}
@@ -6,7 +6,7 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
-    thread,
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
@@ -14,9 +14,9 @@ use cpal::{
     traits::{DeviceTrait, StreamTrait},
     Device, OutputCallbackInfo, Stream, SupportedStreamConfig,
 };
-use hound::{WavSpec, WavWriter};
+use hound::{SampleFormat, WavSpec, WavWriter};
 
-use crate::track;
+use crate::{project, track, types};
 
 pub struct StreamSink {
     stream: Arc<Stream>,
@@ -27,36 +27,83 @@ pub struct StreamSink {
 impl StreamSink {
     pub fn new(
         device: Arc<Device>,
-        track_list: Arc<Mutex<track::track_list::TrackList>>,
-        master_out: Arc<Mutex<track::tracks::OutputTrack>>,
+        mut engine: track::engine::Engine,
+        transport: Arc<project::transport::Transport>,
     ) -> Self {
         if !device.supports_output() {
             panic!("Device doesn't support output");
         }
         let streaming = Arc::new(AtomicBool::new(false));
         let config = device.default_output_config().unwrap();
+        let channels = config.channels().max(1) as usize;
+        engine.set_output_channels(channels);
+        transport.set_sample_rate(config.sample_rate().0);
+        let mut left = vec![0.0; track::engine::MAX_BLOCK_SIZE];
+        let mut right = vec![0.0; track::engine::MAX_BLOCK_SIZE];
+        let mut last_loop_range = None;
         let stream = Arc::new(
             device
                 .build_output_stream(
                     &config.config(),
                     move |data: &mut [f32], _: &OutputCallbackInfo| {
-                        if let Ok(master_out) = master_out.lock() {
-                            for sample in data.iter_mut() {
-                                let mut sum = 0.0;
-                                if let Ok(tracks) = track_list.lock() {
-                                    for track in tracks.get_tracks() {
-                                        if let Ok(t) = track.lock() {
-                                            if t.monitor {
-                                                let ring_buffer = t.source.get_ring_buffer();
-                                                if let Ok(mut rb) = ring_buffer.lock() {
-                                                    sum += rb.pop().unwrap_or(0.0) * t.gain;
-                                                };
-                                            }
-                                        }
-                                    }
-                                    *sample = sum * master_out.gain;
+                        let playing = transport.is_playing();
+                        let start = transport.position();
+                        let mut position = start;
+                        let mut count_in = if playing { transport.count_in() } else { 0 };
+                        let counted = count_in;
+                        let loop_range = transport
+                            .poll_loop_range(&mut last_loop_range)
+                            .filter(|_| playing);
+
+                        let mut done = 0;
+                        while done < data.len() {
+                            // Blocks end with the count-in and at the loop end,
+                            // so the timeline starts on time and wraps without
+                            // a gap.
+                            let mut frames =
+                                ((data.len() - done) / channels).min(track::engine::MAX_BLOCK_SIZE);
+                            if count_in > 0 {
+                                frames = frames.min(count_in as usize);
+                            } else if let Some(range) = loop_range.filter(|r| position < r.end) {
+                                frames = frames.min((range.end - position) as usize);
+                            }
+                            let block = &mut data[done..done + frames * channels];
+                            let (left, right) = (&mut left[..frames], &mut right[..frames]);
+                            engine.process(
+                                position,
+                                playing && count_in == 0,
+                                count_in,
+                                left,
+                                right,
+                            );
+                            let click = engine.routed_click();
+
+                            for (i, frame) in block.chunks_exact_mut(channels).enumerate() {
+                                if channels == 1 {
+                                    frame[0] = (left[i] + right[i]) * 0.5;
+                                } else {
+                                    frame[0] = left[i];
+                                    frame[1] = right[i];
+                                    frame[2..].fill(0.0);
+                                }
+                                if let Some((first, click_left, click_right)) = click {
+                                    frame[first] += click_left[i];
+                                    frame[first + 1] += click_right[i];
                                 }
                             }
+                            if playing && count_in > 0 {
+                                count_in -= frames as u64;
+                            } else if playing {
+                                position += frames as u64;
+                                if let Some(range) = loop_range.filter(|r| position == r.end) {
+                                    position = range.start;
+                                }
+                            }
+                            done += frames * channels;
+                        }
+
+                        if playing {
+                            transport.advance(start, position, counted - count_in);
                         }
                     },
                     move |err| eprintln!("Sink stream error: {}", err),
@@ -95,18 +142,13 @@ impl StreamSink {
     }
 }
 
-pub struct FileSink {
+struct WavFile {
     writer: Option<WavWriter<BufWriter<File>>>,
     config: WavSpec,
 }
 
-impl FileSink {
-    pub fn new(path: PathBuf, config: WavSpec) -> Self {
-        let writer = WavWriter::create(path, config).ok();
-        FileSink { writer, config }
-    }
-
-    pub fn save_to_wav(&mut self, data: Vec<f32>, count: usize) {
+impl WavFile {
+    fn save_to_wav(&mut self, data: Vec<f32>, count: usize) -> Result<(), hound::Error> {
         if let Some(writer) = &mut self.writer {
             let ch = self.config.channels as usize;
             let mut to_write = data.into_iter().take(count).collect::<Vec<f32>>();
@@ -123,21 +165,160 @@ impl FileSink {
             }
 
             for sample in to_write.into_iter() {
-                let s = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
-                writer.write_sample(s).expect("Failed to write sample");
+                let sample = sample.clamp(-1.0, 1.0);
+                let result = match (self.config.sample_format, self.config.bits_per_sample) {
+                    (SampleFormat::Float, _) => writer.write_sample(sample),
+                    (SampleFormat::Int, 16) => {
+                        writer.write_sample((sample * i16::MAX as f32) as i16)
+                    }
+                    (SampleFormat::Int, bits) => {
+                        let max = ((1i64 << (bits - 1)) - 1) as f32;
+                        writer.write_sample((sample * max) as i32)
+                    }
+                };
+                result?;
             }
         } else {
             eprintln!("Track does not have a writer");
         }
+        Ok(())
     }
 
-    pub fn close_file(&mut self) {
-        if let Some(writer) = self.writer.take() {
-            if let Err(e) = writer.finalize() {
-                eprintln!("Failed to finalize WAV file: {}", e);
+    fn close_file(&mut self) -> Result<(), hound::Error> {
+        match self.writer.take() {
+            Some(writer) => writer.finalize(),
+            None => {
+                eprintln!("No writer to finalize");
+                Ok(())
+            }
+        }
+    }
+}
+
+// Called from the worker thread with a message when writing fails.
+pub type ErrorHandler = Box<dyn Fn(String) + Send>;
+
+// Writes whatever is pushed into `ring_buffer` to a WAV file from a worker
+// thread while streaming, so the audio callbacks never touch the disk.
+pub struct FileSink {
+    pub path: PathBuf,
+    file: Arc<Mutex<WavFile>>,
+    ring_buffer: Arc<types::RingBuffer>,
+    streaming: Arc<AtomicBool>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+    // Frames dropped before writing starts and how many are written after
+    // that, `None` for no limit.
+    skip: u64,
+    limit: Option<u64>,
+    on_error: Mutex<Option<ErrorHandler>>,
+}
+
+impl FileSink {
+    pub fn new(
+        path: PathBuf,
+        config: WavSpec,
+        ring_buffer: Arc<types::RingBuffer>,
+    ) -> Result<Self, hound::Error> {
+        let writer = WavWriter::create(&path, config)?;
+        Ok(FileSink {
+            path,
+            file: Arc::new(Mutex::new(WavFile {
+                writer: Some(writer),
+                config,
+            })),
+            ring_buffer,
+            streaming: Arc::new(AtomicBool::new(false)),
+            worker: Mutex::new(None),
+            skip: 0,
+            limit: None,
+            on_error: Mutex::new(None),
+        })
+    }
+
+    // Hears about write errors, the take stops and keeps what was written.
+    pub fn set_error_handler(&mut self, handler: ErrorHandler) {
+        self.on_error = Mutex::new(Some(handler));
+    }
+
+    // Only writes the frames in the window, set before the stream starts.
+    pub fn set_window(&mut self, skip: u64, limit: Option<u64>) {
+        self.skip = skip;
+        self.limit = limit;
+    }
+
+    fn start(&self) {
+        let file = self.file.clone();
+        let ring_buffer = self.ring_buffer.clone();
+        let streaming = self.streaming.clone();
+        streaming.store(true, Ordering::Relaxed);
+        let channels = self
+            .file
+            .lock()
+            .map_or(1, |file| file.config.channels.max(1) as u64);
+        let first = self.skip * channels;
+        let last = self
+            .limit
+            .map_or(u64::MAX, |limit| first + limit * channels);
+        let on_error = self
+            .on_error
+            .lock()
+            .ok()
+            .and_then(|mut handler| handler.take());
+        let name = self
+            .path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let worker = thread::spawn(move || {
+            let report = |message: String| match &on_error {
+                Some(on_error) => on_error(message),
+                None => eprintln!("{}", message),
+            };
+            let mut block = Vec::new();
+            let mut index = 0u64;
+            loop {
+                let running = streaming.load(Ordering::Relaxed);
+                while let Some(sample) = ring_buffer.pop() {
+                    if (first..last).contains(&index) {
+                        block.push(sample);
+                    }
+                    index += 1;
+                }
+                if !block.is_empty() {
+                    if let Ok(mut file) = file.lock() {
+                        let count = block.len();
+                        if let Err(e) = file.save_to_wav(std::mem::take(&mut block), count) {
+                            report(format!("Failed to write {}: {}", name, e));
+                            break;
+                        }
+                    }
+                }
+                if !running {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            // Whatever made it to the file is kept.
+            if let Ok(mut file) = file.lock() {
+                if let Err(e) = file.close_file() {
+                    report(format!("Failed to finalize {}: {}", name, e));
+                }
+            }
+        });
+
+        if let Ok(mut handle) = self.worker.lock() {
+            *handle = Some(worker);
+        }
+    }
+
+    fn stop(&self) {
+        self.streaming.store(false, Ordering::Relaxed);
+        if let Some(worker) = self.worker.lock().ok().and_then(|mut w| w.take()) {
+            if worker.join().is_err() {
+                eprintln!("File sink worker panicked");
             }
-        } else {
-            eprintln!("No writer to finalize");
         }
     }
 }
@@ -159,10 +340,10 @@ impl AudioSink for StreamSink {
 
 impl AudioSink for FileSink {
     fn start_stream(&self) {
-        todo!()
+        self.start();
     }
 
     fn stop_stream(&self) {
-        todo!()
+        self.stop();
     }
 }
@@ -1,8 +1,9 @@
 use std::{
-    path::PathBuf,
+    f64::consts::PI,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
+        Arc,
     },
     thread,
     time::Duration,
@@ -12,15 +13,20 @@ use cpal::{
     traits::{DeviceTrait, StreamTrait},
     Device, InputCallbackInfo, Stream, SupportedStreamConfig,
 };
-use hound::{WavReader, WavSpec};
+use hound::{SampleFormat, WavReader, WavSpec};
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter, Manager};
 
 use crate::types;
 
+const RECORD_BUFFER_SECONDS: usize = 2;
+
 pub struct StreamSource {
     pub device_name: String,
     streaming: Arc<AtomicBool>,
-    ring_buffer: Arc<Mutex<types::RingBuffer>>,
+    recording: Arc<AtomicBool>,
+    ring_buffer: Arc<types::RingBuffer>,
+    record_buffer: Arc<types::RingBuffer>,
     stream: Arc<Stream>,
     config: SupportedStreamConfig,
 }
@@ -33,36 +39,46 @@ impl StreamSource {
         let window = app.get_webview_window("main").unwrap();
         let device_name = device.name().unwrap();
         let streaming = Arc::new(AtomicBool::new(false));
-        let ring_buffer = Arc::new(Mutex::new(types::RingBuffer::new()));
+        let ring_buffer = Arc::new(types::RingBuffer::new());
         let ring_buffer_clone = ring_buffer.clone();
         let config = device.default_input_config().unwrap();
+        let recording = Arc::new(AtomicBool::new(false));
+        let recording_clone = recording.clone();
+        let record_buffer = Arc::new(types::RingBuffer::with_capacity(
+            config.sample_rate().0 as usize * config.channels() as usize * RECORD_BUFFER_SECONDS,
+        ));
+        let record_buffer_clone = record_buffer.clone();
         let stream = Arc::new(
             device
                 .build_input_stream(
                     &config.config(),
                     move |data: &[f32], _: &InputCallbackInfo| {
-                        if let Ok(mut rb) = ring_buffer_clone.lock() {
-                            let mut left_sum = 0.0;
-                            let mut right_sum = 0.0;
-                            let mut channel = true;
-                            let count = data.len() as f32;
-
+                        if recording_clone.load(Ordering::Relaxed) {
                             for &sample in data {
-                                rb.push(sample);
-                                if channel {
-                                    left_sum += sample;
-                                } else {
-                                    right_sum += sample;
-                                }
-                                channel = !channel;
+                                record_buffer_clone.push(sample);
                             }
-                            window
-                                .emit(
-                                    &format!("{}-audio-samples", track_name),
-                                    (left_sum / (count / 2.0), right_sum / (count / 2.0)),
-                                )
-                                .unwrap();
                         }
+
+                        let mut left_sum = 0.0;
+                        let mut right_sum = 0.0;
+                        let mut channel = true;
+                        let count = data.len() as f32;
+
+                        for &sample in data {
+                            ring_buffer_clone.push(sample);
+                            if channel {
+                                left_sum += sample;
+                            } else {
+                                right_sum += sample;
+                            }
+                            channel = !channel;
+                        }
+                        window
+                            .emit(
+                                &format!("{}-audio-samples", track_name),
+                                (left_sum / (count / 2.0), right_sum / (count / 2.0)),
+                            )
+                            .unwrap();
                     },
                     move |err| eprintln!("Source stream error: {}", err),
                     None,
@@ -73,7 +89,9 @@ impl StreamSource {
         StreamSource {
             device_name,
             streaming,
+            recording,
             ring_buffer,
+            record_buffer,
             stream,
             config,
         }
@@ -101,80 +119,165 @@ impl StreamSource {
     pub fn stop(&self) {
         self.streaming.store(false, Ordering::Relaxed);
     }
+
+    pub fn wav_spec(&self) -> WavSpec {
+        WavSpec {
+            channels: self.config.channels(),
+            sample_rate: self.config.sample_rate().0,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        }
+    }
 }
 
 pub struct FileSource {
     pub path: PathBuf,
-    streaming: Arc<AtomicBool>,
-    ring_buffer: Arc<Mutex<types::RingBuffer>>,
-    config: WavSpec,
+    channels: Vec<Vec<f32>>,
 }
 
 impl FileSource {
-    pub fn new(path: PathBuf) -> Self {
-        let streaming = Arc::new(AtomicBool::new(false));
-        let streaming_clone = Arc::clone(&streaming);
-        let ring_buffer = Arc::new(Mutex::new(types::RingBuffer::new()));
-        let ring_buffer_clone = Arc::clone(&ring_buffer);
-
-        let reader = WavReader::open(&path).expect("Failed to open file source");
-        let config = reader.spec();
-        println!("{}", reader.duration());
-        let p = path.clone();
-
-        thread::spawn(move || loop {
-            if !streaming_clone.load(Ordering::Relaxed) {
-                thread::sleep(std::time::Duration::from_millis(10));
-                continue;
-            }
+    pub fn new(path: PathBuf, sample_rate: u32) -> Result<Self, hound::Error> {
+        let (file_rate, channels) = read_wav(&path)?;
+        let channels = channels
+            .iter()
+            .map(|channel| resample(channel, file_rate, sample_rate))
+            .collect();
+
+        Ok(FileSource { path, channels })
+    }
 
-            let mut reader = WavReader::open(&p).expect("Failed to open file source");
-            let mut samples = reader.samples::<f32>();
-
-            while streaming_clone.load(Ordering::Relaxed) {
-                if let Some(Ok(s)) = samples.next() {
-                    if let Ok(mut rb) = ring_buffer_clone.lock() {
-                        rb.push(s * 100.0);
-                    }
-                } else {
-                    println!("EOF reached");
-                    break;
-                }
+    // A source made up in memory, for tests that don't read a file.
+    #[cfg(test)]
+    pub fn from_channels(path: &str, channels: Vec<Vec<f32>>) -> Self {
+        FileSource {
+            path: PathBuf::from(path),
+            channels,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.channels.first().map_or(0, |c| c.len() as u64)
+    }
+
+    pub fn frame(&self, index: u64) -> (f32, f32) {
+        let index = index as usize;
+        match self.channels.as_slice() {
+            [mono] => {
+                let sample = mono.get(index).copied().unwrap_or(0.0);
+                (sample, sample)
             }
-        });
+            [left, right, ..] => (
+                left.get(index).copied().unwrap_or(0.0),
+                right.get(index).copied().unwrap_or(0.0),
+            ),
+            [] => (0.0, 0.0),
+        }
+    }
 
-        Self {
-            path,
-            streaming,
-            ring_buffer,
-            config,
+    pub fn kind(&self) -> AudioSourceRaw {
+        AudioSourceRaw::File(self.path.to_string_lossy().to_string())
+    }
+}
+
+pub fn read_wav(path: &Path) -> Result<(u32, Vec<Vec<f32>>), hound::Error> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let channel_count = spec.channels.max(1) as usize;
+    let interleaved = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let mut channels = vec![Vec::with_capacity(interleaved.len() / channel_count); channel_count];
+    for frame in interleaved.chunks_exact(channel_count) {
+        for (channel, &sample) in channels.iter_mut().zip(frame) {
+            channel.push(sample);
         }
     }
+    Ok((spec.sample_rate, channels))
+}
 
-    pub fn start(&self) {
-        self.streaming.store(true, Ordering::Relaxed);
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || from_rate == 0 || to_rate == 0 || samples.is_empty() {
+        return samples.to_vec();
     }
 
-    pub fn stop(&self) {
-        self.streaming.store(false, Ordering::Relaxed);
+    let ratio = from_rate as f64 / to_rate as f64;
+    let length = (samples.len() as f64 / ratio).ceil() as usize;
+    (0..length)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            let a = samples[index.min(samples.len() - 1)];
+            let b = samples[(index + 1).min(samples.len() - 1)];
+            a + (b - a) * fraction
+        })
+        .collect()
+}
+
+// Zero crossings of the windowed sinc on each side, at the lower of the two
+// rates.
+const SINC_ZERO_CROSSINGS: f64 = 16.0;
+
+// Windowed sinc interpolation cut off at the lower rate's Nyquist, so content
+// above it is filtered out instead of folding back when downsampling. Slower
+// than `resample`, for short material.
+pub fn resample_band_limited(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || from_rate == 0 || to_rate == 0 || samples.is_empty() {
+        return samples.to_vec();
     }
+
+    let ratio = from_rate as f64 / to_rate as f64;
+    // Cutoff as a fraction of the source Nyquist.
+    let cutoff = (1.0 / ratio).min(1.0);
+    let half_width = SINC_ZERO_CROSSINGS / cutoff;
+    let length = (samples.len() as f64 / ratio).ceil() as usize;
+    (0..length)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let first = (position - half_width).ceil().max(0.0) as usize;
+            let last = ((position + half_width).floor() as usize).min(samples.len() - 1);
+            (first..=last)
+                .map(|j| {
+                    let x = j as f64 - position;
+                    let t = PI * cutoff * x;
+                    let sinc = if t == 0.0 { 1.0 } else { t.sin() / t };
+                    // Blackman window over the kernel's width.
+                    let w = PI * (x / half_width + 1.0);
+                    let window = 0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+                    samples[j] as f64 * cutoff * sinc * window
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
 }
 
-#[derive(bincode::Encode, bincode::Decode)]
+#[derive(Serialize, Deserialize, bincode::Decode)]
 pub enum AudioSourceRaw {
     File(String),
     Stream(String),
 }
 
 pub trait AudioSource: Send {
-    fn get_ring_buffer(&self) -> Arc<Mutex<types::RingBuffer>>;
+    fn get_ring_buffer(&self) -> Arc<types::RingBuffer>;
     fn start_stream(&self);
     fn stop_stream(&self);
+    fn get_record_buffer(&self) -> Arc<types::RingBuffer>;
+    fn set_recording(&self, recording: bool);
+    fn wav_spec(&self) -> WavSpec;
+    fn channels(&self) -> u16;
     fn kind(&self) -> AudioSourceRaw;
 }
 
 impl AudioSource for StreamSource {
-    fn get_ring_buffer(&self) -> Arc<Mutex<types::RingBuffer>> {
+    fn get_ring_buffer(&self) -> Arc<types::RingBuffer> {
         self.ring_buffer.clone()
     }
 
@@ -186,25 +289,60 @@ impl AudioSource for StreamSource {
         self.stop();
     }
 
+    fn get_record_buffer(&self) -> Arc<types::RingBuffer> {
+        self.record_buffer.clone()
+    }
+
+    fn set_recording(&self, recording: bool) {
+        if recording {
+            self.record_buffer.skip(self.record_buffer.len());
+        }
+        self.recording.store(recording, Ordering::Relaxed);
+    }
+
+    fn wav_spec(&self) -> WavSpec {
+        StreamSource::wav_spec(self)
+    }
+
+    fn channels(&self) -> u16 {
+        self.config.channels()
+    }
+
     fn kind(&self) -> AudioSourceRaw {
         AudioSourceRaw::Stream(self.device_name.clone())
     }
 }
 
-impl AudioSource for FileSource {
-    fn get_ring_buffer(&self) -> Arc<Mutex<types::RingBuffer>> {
-        self.ring_buffer.clone()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f64, sample_rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin() as f32)
+            .collect()
     }
 
-    fn start_stream(&self) {
-        self.start();
+    // Peak level away from the edges, where the kernel runs out of input.
+    fn peak(samples: &[f32]) -> f32 {
+        let edge = samples.len() / 8;
+        samples[edge..samples.len() - edge]
+            .iter()
+            .fold(0.0, |peak, sample| peak.max(sample.abs()))
     }
 
-    fn stop_stream(&self) {
-        self.stop();
+    #[test]
+    fn band_limited_resample_keeps_the_passband() {
+        let resampled = resample_band_limited(&sine(1_000.0, 96_000, 9_600), 96_000, 44_100);
+        assert_eq!(resampled.len(), 4_410);
+        assert!((peak(&resampled) - 1.0).abs() < 0.01);
     }
 
-    fn kind(&self) -> AudioSourceRaw {
-        AudioSourceRaw::File(self.path.to_string_lossy().to_string())
+    #[test]
+    fn band_limited_resample_filters_out_what_would_alias() {
+        // 30 kHz folds back to 18 kHz at 48 kHz.
+        let tone = sine(30_000.0, 96_000, 9_600);
+        assert!(peak(&resample(&tone, 96_000, 48_000)) > 0.5);
+        assert!(peak(&resample_band_limited(&tone, 96_000, 48_000)) < 0.01);
     }
 }
@@ -0,0 +1,217 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{project, track};
+
+static NEXT_FOLDER_ID: AtomicU64 = AtomicU64::new(1);
+
+// Part of the comp played from the take in lane `take`, in timeline frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompRegion {
+    pub take: usize,
+    pub start: u64,
+    pub end: u64,
+}
+
+// Takes recorded over the same range, one per lane with the oldest first. The
+// track plays the comp, regions of the takes put together into one clip.
+#[derive(Clone)]
+pub struct TakeFolder {
+    pub id: u64,
+    pub range: project::transport::TimeRange,
+    pub takes: Vec<track::clips::Clip>,
+    pub comp: Vec<CompRegion>,
+}
+
+impl TakeFolder {
+    pub fn new(range: project::transport::TimeRange) -> Self {
+        TakeFolder {
+            id: NEXT_FOLDER_ID.fetch_add(1, Ordering::Relaxed),
+            range,
+            takes: Vec::new(),
+            comp: Vec::new(),
+        }
+    }
+
+    // New takes go in new lanes and the newest one is selected.
+    pub fn add_takes(&mut self, takes: Vec<track::clips::Clip>) {
+        self.takes.extend(takes);
+        if let Some(last) = self.takes.len().checked_sub(1) {
+            let _ = self.select(last);
+        }
+    }
+
+    // Plays the whole range from one take.
+    pub fn select(&mut self, take: usize) -> Result<(), String> {
+        self.set_comp(vec![CompRegion {
+            take,
+            start: self.range.start,
+            end: self.range.end,
+        }])
+    }
+
+    // Regions are cut to the folder's range and can't overlap.
+    pub fn set_comp(&mut self, mut regions: Vec<CompRegion>) -> Result<(), String> {
+        for region in &mut regions {
+            if region.take >= self.takes.len() {
+                return Err(format!("Take {} doesn't exist", region.take + 1));
+            }
+            region.start = region.start.max(self.range.start);
+            region.end = region.end.min(self.range.end);
+        }
+        regions.retain(|region| region.start < region.end);
+        regions.sort_by_key(|region| region.start);
+        if regions.windows(2).any(|pair| pair[0].end > pair[1].start) {
+            return Err("Comp regions can't overlap".to_string());
+        }
+        self.comp = regions;
+        Ok(())
+    }
+
+    // The comp as clips cut from the takes, for the engine.
+    pub fn comp_clips(&self) -> Vec<track::clips::Clip> {
+        self.comp
+            .iter()
+            .filter_map(|region| self.takes[region.take].trimmed(region.start, region.end))
+            .collect()
+    }
+
+    pub fn as_info(&self) -> TakeFolderInfo {
+        TakeFolderInfo {
+            id: self.id,
+            range: self.range,
+            takes: self.takes.iter().map(|take| take.as_info()).collect(),
+            comp: self.comp.clone(),
+        }
+    }
+
+    // Takes whose file is gone are dropped along with their comp regions.
+    pub fn from_raw(value: TakeFolderRaw, sample_rate: u32) -> Self {
+        let mut folder = TakeFolder::new(value.range);
+        let mut lanes = Vec::new();
+        for raw in value.takes {
+            lanes.push(track::clips::Clip::from_raw(raw, sample_rate).map(|clip| {
+                folder.takes.push(clip);
+                folder.takes.len() - 1
+            }));
+        }
+        folder.comp = value
+            .comp
+            .into_iter()
+            .filter_map(|region| {
+                let take = (*lanes.get(region.take)?)?;
+                Some(CompRegion { take, ..region })
+            })
+            .collect();
+        folder
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TakeFolderRaw {
+    pub range: project::transport::TimeRange,
+    pub takes: Vec<track::clips::ClipRaw>,
+    pub comp: Vec<CompRegion>,
+}
+
+impl TakeFolderRaw {
+    pub fn rescale(&mut self, from: u32, to: u32) {
+        self.range = self.range.rescale(from, to);
+        for take in &mut self.takes {
+            take.rescale(from, to);
+        }
+        for region in &mut self.comp {
+            region.start = project::transport::rescale_frames(region.start, from, to);
+            region.end = project::transport::rescale_frames(region.end, from, to);
+        }
+    }
+}
+
+impl From<&TakeFolder> for TakeFolderRaw {
+    fn from(value: &TakeFolder) -> Self {
+        TakeFolderRaw {
+            range: value.range,
+            takes: value
+                .takes
+                .iter()
+                .map(track::clips::ClipRaw::from)
+                .collect(),
+            comp: value.comp.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TakeFolderInfo {
+    pub id: u64,
+    pub range: project::transport::TimeRange,
+    pub takes: Vec<track::clips::ClipInfo>,
+    pub comp: Vec<CompRegion>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::sources::source::FileSource;
+    use std::sync::Arc;
+
+    fn folder(takes: usize) -> TakeFolder {
+        let mut folder = TakeFolder::new(project::transport::TimeRange {
+            start: 1_000,
+            end: 2_000,
+        });
+        let source = Arc::new(FileSource::from_channels(
+            "take.wav",
+            vec![vec![0.0; 3_000]],
+        ));
+        folder.add_takes(
+            (0..takes)
+                .map(|_| track::clips::Clip::new("take", source.clone(), 0))
+                .collect(),
+        );
+        folder
+    }
+
+    fn region(take: usize, start: u64, end: u64) -> CompRegion {
+        CompRegion { take, start, end }
+    }
+
+    #[test]
+    fn newest_take_is_selected() {
+        let folder = folder(3);
+        assert_eq!(folder.comp, vec![region(2, 1_000, 2_000)]);
+    }
+
+    #[test]
+    fn set_comp_cuts_and_sorts_regions() {
+        let mut folder = folder(2);
+        folder
+            .set_comp(vec![
+                region(1, 1_500, 2_500),
+                region(0, 0, 1_500),
+                region(0, 2_500, 3_000),
+            ])
+            .unwrap();
+        assert_eq!(
+            folder.comp,
+            vec![region(0, 1_000, 1_500), region(1, 1_500, 2_000)]
+        );
+
+        let clips = folder.comp_clips();
+        assert_eq!(clips.len(), 2);
+        assert_eq!((clips[0].start, clips[0].length), (1_000, 500));
+        assert_eq!((clips[1].start, clips[1].offset), (1_500, 1_500));
+    }
+
+    #[test]
+    fn set_comp_rejects_overlaps_and_missing_takes() {
+        let mut folder = folder(2);
+        let comp = folder.comp.clone();
+        assert!(folder
+            .set_comp(vec![region(0, 1_000, 1_600), region(1, 1_500, 2_000)])
+            .is_err());
+        assert!(folder.set_comp(vec![region(2, 1_000, 2_000)]).is_err());
+        assert_eq!(folder.comp, comp);
+    }
+}
@@ -4,10 +4,11 @@ use std::{
 };
 
 use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
 
-use crate::track;
+use crate::{project, track};
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub enum TrackUpdate {
     Name(String),
     Monitor(bool),
@@ -15,131 +16,638 @@ pub enum TrackUpdate {
     Pan(f32),
     Gain(f32),
     Mute(bool),
+    Solo(bool),
+    SoloSafe(bool),
+    // `None` is the master.
+    Output(Option<String>),
+    AutomationMode(track::automation::AutomationMode),
 }
 
+// The range of the channel faders, linked gains are kept inside it.
+const FADER_MIN_DB: f32 = -60.0;
+const FADER_MAX_DB: f32 = 0.0;
+
+pub fn fader_db(gain: f32) -> f32 {
+    (20.0 * gain.max(1e-9).log10()).clamp(FADER_MIN_DB, FADER_MAX_DB)
+}
+
+// Tracks whose gain, mute and solo move together. A track is in at most one
+// group. `level` is the group's own gain in dB, it follows whichever member
+// is moved. `offsets` holds each member's gain in dB against `level`, it
+// doesn't change when a member is held at the end of the fader.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrackGroup {
+    pub name: String,
+    pub members: Vec<String>,
+    pub level: f32,
+    pub offsets: HashMap<String, f32>,
+}
+
+impl TrackGroup {
+    pub fn new(name: &str) -> Self {
+        TrackGroup {
+            name: name.to_string(),
+            members: Vec::new(),
+            level: 0.0,
+            offsets: HashMap::new(),
+        }
+    }
+
+    // A new member joins at its current gain.
+    pub fn join(&mut self, member: &str, gain: f32) {
+        self.leave(member);
+        self.members.push(member.to_string());
+        self.offsets
+            .insert(member.to_string(), fader_db(gain) - self.level);
+    }
+
+    pub fn leave(&mut self, member: &str) {
+        self.members.retain(|name| name != member);
+        self.offsets.remove(member);
+    }
+
+    fn rename(&mut self, member: &str, new_name: &str) {
+        for name in &mut self.members {
+            if name == member {
+                *name = new_name.to_string();
+            }
+        }
+        if let Some(offset) = self.offsets.remove(member) {
+            self.offsets.insert(new_name.to_string(), offset);
+        }
+    }
+
+    fn offset(&self, member: &str, gain: f32) -> f32 {
+        self.offsets
+            .get(member)
+            .copied()
+            .unwrap_or_else(|| fader_db(gain) - self.level)
+    }
+}
+
+// `order` keeps the tracks in the order they were added, which is the order
+// they are shown and saved in.
 pub struct TrackList {
     tracks: HashMap<String, Arc<Mutex<track::tracks::InputTrack>>>,
+    order: Vec<String>,
+    pub groups: Vec<TrackGroup>,
+    pub pan_law: track::mixer::PanLaw,
+    pub solo_mode: track::mixer::SoloMode,
+    pub tempo_map: project::tempo::TempoMap,
+    pub metronome: track::metronome::Metronome,
 }
 
 impl TrackList {
     pub fn new() -> Self {
         TrackList {
             tracks: HashMap::new(),
+            order: Vec::new(),
+            groups: Vec::new(),
+            pan_law: track::mixer::PanLaw::ConstantPower,
+            solo_mode: track::mixer::SoloMode::Additive,
+            tempo_map: project::tempo::TempoMap::new(),
+            metronome: track::metronome::Metronome::new(),
         }
     }
 
     pub fn get_tracks(&self) -> Vec<Arc<Mutex<track::tracks::InputTrack>>> {
-        self.tracks.values().cloned().collect()
+        self.order
+            .iter()
+            .filter_map(|name| self.tracks.get(name).cloned())
+            .collect()
     }
 
     pub fn add_track(&mut self, name: &str, track: track::tracks::InputTrack) {
-        self.tracks.insert(name.into(), Arc::new(Mutex::new(track)));
+        self.add_arc_mut_track(name, Arc::new(Mutex::new(track)));
     }
 
     pub fn add_arc_mut_track(&mut self, name: &str, track: Arc<Mutex<track::tracks::InputTrack>>) {
-        self.tracks.insert(name.into(), track);
+        if self.tracks.insert(name.into(), track).is_none() {
+            self.order.push(name.into());
+        }
     }
 
     pub fn remove_track(&mut self, name: &str) -> Option<Arc<Mutex<track::tracks::InputTrack>>> {
+        self.order.retain(|track_name| track_name != name);
         self.tracks.remove(name)
     }
 
+    // Keeps the track in its place, returns false when the new name is taken.
+    fn rename_track(&mut self, name: &str, new_name: &str) -> bool {
+        if self.tracks.contains_key(new_name) {
+            return false;
+        }
+        let index = self.order.iter().position(|track_name| track_name == name);
+        match (index, self.remove_track(name)) {
+            (Some(index), Some(track)) => {
+                self.tracks.insert(new_name.into(), track);
+                self.order.insert(index, new_name.into());
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn get_track(&self, name: &str) -> Option<Arc<Mutex<track::tracks::InputTrack>>> {
         self.tracks.get(name).cloned()
     }
 
+    pub fn group_of(&self, name: &str) -> Option<&TrackGroup> {
+        self.groups
+            .iter()
+            .find(|group| group.members.iter().any(|member| member == name))
+    }
+
+    pub fn is_bus(&self, name: &str) -> bool {
+        self.tracks
+            .get(name)
+            .and_then(|track| track.lock().ok())
+            .is_some_and(|track| track.kind == track::tracks::TrackKind::Bus)
+    }
+
+    // Every track with the buses it feeds. `change` routes one track to an
+    // output and sends instead of what it has now.
+    fn routing_nodes(
+        &self,
+        change: Option<(&str, Option<&str>, &[track::sends::AuxSend])>,
+    ) -> Vec<track::routing::RoutingNode> {
+        self.get_tracks()
+            .iter()
+            .filter_map(|track| track.lock().ok())
+            .map(|track| {
+                let mut node = track::routing::RoutingNode::from_track(&track);
+                if let Some((name, output, sends)) = change {
+                    if track.name == name {
+                        node.targets = output
+                            .into_iter()
+                            .map(str::to_string)
+                            .chain(sends.iter().map(|send| send.bus.clone()))
+                            .collect();
+                    }
+                }
+                node
+            })
+            .collect()
+    }
+
+    pub fn processing_order(&self) -> Result<Vec<usize>, Vec<String>> {
+        track::routing::processing_order(&self.routing_nodes(None))
+    }
+
+    // Whether `name` can feed `output` and the buses of `sends` without
+    // making a loop. Must not be called with any track locked.
+    pub fn check_routing(
+        &self,
+        name: &str,
+        output: Option<&str>,
+        sends: &[track::sends::AuxSend],
+    ) -> Result<(), String> {
+        for bus in output
+            .into_iter()
+            .chain(sends.iter().map(|send| send.bus.as_str()))
+        {
+            if bus == name {
+                return Err(format!("{} can't feed itself", name));
+            }
+            if !self.is_bus(bus) {
+                return Err(format!("{} is not a bus", bus));
+            }
+        }
+        track::routing::processing_order(&self.routing_nodes(Some((name, output, sends))))
+            .map(|_| ())
+            .map_err(|names| {
+                format!(
+                    "Routing {} would make a loop through {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+
+    // Whether an insert on `name` can take its key from `key`, the master can
+    // be keyed from any track. Must not be called with any track locked.
+    pub fn check_key(&self, name: &str, key: &str) -> Result<(), String> {
+        if key == name {
+            return Err(format!("{} can't key itself", name));
+        }
+        if self.get_track(key).is_none() {
+            return Err(format!("Track {} not found", key));
+        }
+        if name == "master-out" {
+            return Ok(());
+        }
+        let mut nodes = self.routing_nodes(None);
+        if let Some(node) = nodes.iter_mut().find(|node| node.name == name) {
+            node.keys.push(key.to_string());
+        }
+        track::routing::processing_order(&nodes)
+            .map(|_| ())
+            .map_err(|names| {
+                format!(
+                    "Keying {} from {} would make a loop through {}",
+                    name,
+                    key,
+                    names.join(", ")
+                )
+            })
+    }
+
     pub fn track_list(&self) -> Vec<&String> {
-        let mut track_names = self.tracks.keys().collect::<Vec<_>>();
-        track_names.sort();
-        track_names
+        self.order.iter().collect()
     }
 
-    pub fn update_track(&mut self, track_name: &str, update: TrackUpdate) {
+    pub fn length(&self, sample_rate: u32) -> u64 {
+        self.tracks
+            .values()
+            .filter_map(|track| track.lock().ok())
+            .flat_map(|track| {
+                track
+                    .playback_clips(sample_rate)
+                    .iter()
+                    .map(|clip| clip.end())
+                    .max()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Returns the updates that undo this one. `link` also applies the changes
+    // other tracks follow, like exclusive solo, and is off when undoing.
+    pub fn update_track(
+        &mut self,
+        track_name: &str,
+        update: TrackUpdate,
+        link: bool,
+    ) -> Vec<(String, TrackUpdate)> {
+        let mut undo = Vec::new();
+        if let TrackUpdate::Output(output) = &update {
+            let sends = self
+                .get_track(track_name)
+                .and_then(|track| track.lock().ok().map(|track| track.sends.clone()))
+                .unwrap_or_default();
+            if let Err(e) = self.check_routing(track_name, output.as_deref(), &sends) {
+                eprintln!("{}", e);
+                return undo;
+            }
+        }
         if let Some(track_arc) = self.get_track(track_name) {
             let mut track = track_arc.lock().expect("Failed to lock track");
+            // Other members of the track's group follow gain, mute and solo.
+            let group = self.group_of(track_name).filter(|_| link).cloned();
+            let linked = match &group {
+                Some(group) => group
+                    .members
+                    .iter()
+                    .filter(|member| *member != track_name)
+                    .filter_map(|member| Some((member.clone(), self.tracks.get(member)?.clone())))
+                    .collect::<Vec<_>>(),
+                None => Vec::new(),
+            };
 
             match update {
                 TrackUpdate::Name(name) => {
-                    if let Some(track) = self.remove_track(track_name) {
-                        self.add_arc_mut_track(&name, track);
+                    if self.rename_track(track_name, &name) {
+                        undo.push((name.clone(), TrackUpdate::Name(track_name.to_string())));
+                        for group in &mut self.groups {
+                            group.rename(track_name, &name);
+                        }
+                        // Sends follow a renamed bus, the renamed track itself
+                        // is already locked.
+                        for (other_name, other) in &self.tracks {
+                            if *other_name == name {
+                                continue;
+                            }
+                            if let Ok(mut other) = other.lock() {
+                                for send in &mut other.sends {
+                                    if send.bus == track_name {
+                                        send.bus = name.clone();
+                                    }
+                                }
+                                if other.output.as_deref() == Some(track_name) {
+                                    other.output = Some(name.clone());
+                                }
+                                rename_keys(&mut other.inserts, track_name, &name);
+                            }
+                        }
+                        track.name = name;
                     } else {
-                        eprintln!("No existing track with that name");
+                        eprintln!("Can't rename {} to {}", track_name, name);
+                    }
+                }
+                TrackUpdate::Record(record) => {
+                    undo.push((track_name.to_string(), TrackUpdate::Record(track.record)));
+                    track.record = record;
+                }
+                TrackUpdate::Monitor(monitor) => {
+                    undo.push((track_name.to_string(), TrackUpdate::Monitor(track.monitor)));
+                    track.monitor = monitor;
+                }
+                TrackUpdate::Pan(pan) => {
+                    undo.push((track_name.to_string(), TrackUpdate::Pan(track.pan)));
+                    track.pan = pan;
+                }
+                TrackUpdate::Gain(gain) => {
+                    undo.push((track_name.to_string(), TrackUpdate::Gain(track.gain)));
+                    // Members keep their offsets from the group level, VCA
+                    // style. Unlinked moves, like undo, still set the level
+                    // so members joining later line up with it.
+                    if let Some(group) = self
+                        .groups
+                        .iter_mut()
+                        .find(|group| group.members.iter().any(|member| member == track_name))
+                    {
+                        group.level = fader_db(gain) - group.offset(track_name, track.gain);
+                    }
+                    if let Some(group) = &group {
+                        let level = fader_db(gain) - group.offset(track_name, track.gain);
+                        for (name, other) in &linked {
+                            if let Ok(mut other) = other.lock() {
+                                undo.push((name.clone(), TrackUpdate::Gain(other.gain)));
+                                let db = level + group.offset(name, other.gain);
+                                other.gain = track::processors::processor::db_to_gain(
+                                    db.clamp(FADER_MIN_DB, FADER_MAX_DB),
+                                );
+                            }
+                        }
+                    }
+                    track.gain = gain;
+                }
+                TrackUpdate::Mute(mute) => {
+                    undo.push((track_name.to_string(), TrackUpdate::Mute(track.mute)));
+                    track.mute = mute;
+                    for (name, other) in &linked {
+                        if let Ok(mut other) = other.lock() {
+                            undo.push((name.clone(), TrackUpdate::Mute(other.mute)));
+                            other.mute = mute;
+                        }
                     }
                 }
-                TrackUpdate::Record(record) => track.record = record,
-                TrackUpdate::Monitor(monitor) => track.monitor = monitor,
-                TrackUpdate::Pan(pan) => track.pan = pan,
-                TrackUpdate::Gain(gain) => track.gain = gain,
-                TrackUpdate::Mute(mute) => track.mute = mute,
+                TrackUpdate::Solo(solo) => {
+                    undo.push((track_name.to_string(), TrackUpdate::Solo(track.solo)));
+                    track.solo = solo;
+                    for (name, other) in &linked {
+                        if let Ok(mut other) = other.lock() {
+                            undo.push((name.clone(), TrackUpdate::Solo(other.solo)));
+                            other.solo = solo;
+                        }
+                    }
+                    if link && solo && self.solo_mode == track::mixer::SoloMode::Exclusive {
+                        for (name, other) in &self.tracks {
+                            if name != track_name
+                                && !linked.iter().any(|(linked, _)| linked == name)
+                            {
+                                if let Ok(mut other) = other.lock() {
+                                    if other.solo {
+                                        undo.push((name.clone(), TrackUpdate::Solo(true)));
+                                        other.solo = false;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                TrackUpdate::SoloSafe(solo_safe) => {
+                    undo.push((
+                        track_name.to_string(),
+                        TrackUpdate::SoloSafe(track.solo_safe),
+                    ));
+                    track.solo_safe = solo_safe;
+                }
+                TrackUpdate::Output(output) => {
+                    undo.push((
+                        track_name.to_string(),
+                        TrackUpdate::Output(track.output.clone()),
+                    ));
+                    track.output = output;
+                }
+                TrackUpdate::AutomationMode(mode) => {
+                    undo.push((
+                        track_name.to_string(),
+                        TrackUpdate::AutomationMode(track.automation.mode),
+                    ));
+                    track.automation.mode = mode;
+                }
             }
 
-            if track.monitor || track.record {
-                track.source.start_stream();
-            } else {
-                track.source.stop_stream();
+            if let Some(source) = &track.source {
+                if track.monitor || track.record {
+                    source.start_stream();
+                } else {
+                    source.stop_stream();
+                }
             }
         } else {
             eprintln!("Track {} not found", track_name);
         }
+        undo
     }
 
-    pub fn from_raw(raw_track_list: HashMap<String, track::raw::InputTrackRaw>) -> Self {
-        let mut tracks = HashMap::new();
-
-        for (track_name, raw_track) in raw_track_list {
-            tracks.insert(
-                track_name,
-                Arc::new(Mutex::new(track::tracks::InputTrack::from(raw_track))),
+    pub fn from_raw(
+        raw_tracks: Vec<track::raw::InputTrackRaw>,
+        sample_rate: u32,
+        app_handle: &AppHandle,
+    ) -> Self {
+        let mut track_list = TrackList::new();
+        for raw_track in raw_tracks {
+            let name = raw_track.name.clone();
+            track_list.add_track(
+                &name,
+                track::tracks::InputTrack::from_raw(raw_track, sample_rate, app_handle),
             );
         }
 
-        TrackList { tracks }
+        // Projects edited by hand can loop, those tracks go straight to the
+        // master.
+        if let Err(names) = track_list.processing_order() {
+            eprintln!("Routing loops through {}, resetting it", names.join(", "));
+            for name in names {
+                if let Some(Ok(mut track)) = track_list.get_track(&name).as_ref().map(|t| t.lock())
+                {
+                    track.output = None;
+                    track.sends.clear();
+                }
+            }
+        }
+        track_list
     }
 
-    pub fn to_raw(&self) -> HashMap<String, track::raw::InputTrackRaw> {
-        self.tracks
+    pub fn to_raw(&self) -> Vec<track::raw::InputTrackRaw> {
+        self.get_tracks()
             .iter()
-            .map(|(key, value)| {
-                let audio_track = value.lock().expect("Failed to lock track");
-                (key.clone(), track::raw::InputTrackRaw::from(&*audio_track))
+            .map(|track| {
+                let audio_track = track.lock().expect("Failed to lock track");
+                track::raw::InputTrackRaw::from(&*audio_track)
             })
             .collect()
     }
 
-    pub fn as_response(&self) -> TrackListResponse {
+    pub fn as_response(&self, sample_rate: u32) -> TrackListResponse {
         let mut tracks = Vec::new();
 
-        for (name, track_mutex) in &self.tracks {
-            if let Ok(track) = track_mutex.lock() {
+        for name in &self.order {
+            if let Some(Ok(track)) = self.tracks.get(name).map(|track| track.lock()) {
                 tracks.push(TrackInfo {
                     name: name.clone(),
+                    kind: track.kind,
                     gain: track.gain,
                     pan: track.pan,
                     monitor: track.monitor,
                     mute: track.mute,
-                    solo: false,
+                    solo: track.solo,
+                    solo_safe: track.solo_safe,
                     record: track.record,
+                    clips: track.clips.iter().map(|clip| clip.as_info()).collect(),
+                    take_folders: track
+                        .take_folders
+                        .iter()
+                        .map(|folder| folder.as_info())
+                        .collect(),
+                    crossfades: track.crossfades(sample_rate),
+                    inserts: track.inserts.as_info(),
+                    limiter: None,
+                    sends: track.sends.clone(),
+                    output: track.output.clone(),
+                    group: self.group_of(name).map(|group| group.name.clone()),
+                    automation_mode: track.automation.mode,
+                    automation: track.automation.lanes.clone(),
                 });
             }
         }
 
-        tracks.sort_by(|a, b| a.name.cmp(&b.name));
-        TrackListResponse { tracks }
+        TrackListResponse {
+            tracks,
+            groups: self.groups.clone(),
+            pan_law: self.pan_law,
+            solo_mode: self.solo_mode,
+            tempo_map: self.tempo_map.clone(),
+            metronome: self.metronome.settings.clone(),
+        }
+    }
+}
+
+// Points inserts keyed from `from` at the track's new name.
+pub fn rename_keys(chain: &mut track::processors::chain::InsertChain, from: &str, to: &str) {
+    for insert in &mut chain.inserts {
+        if insert.key.as_deref() == Some(from) {
+            insert.key = Some(to.to_string());
+        }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TrackInfo {
     pub name: String,
+    pub kind: track::tracks::TrackKind,
     pub record: bool,
     pub gain: f32,
     pub pan: f32,
     pub monitor: bool,
     pub solo: bool,
+    pub solo_safe: bool,
     pub mute: bool,
+    pub clips: Vec<track::clips::ClipInfo>,
+    pub take_folders: Vec<track::takes::TakeFolderInfo>,
+    pub crossfades: Vec<track::clips::Crossfade>,
+    pub inserts: Vec<track::processors::chain::InsertInfo>,
+    // The master's fixed limiter, `None` on other tracks.
+    pub limiter: Option<track::processors::chain::InsertInfo>,
+    pub sends: Vec<track::sends::AuxSend>,
+    pub output: Option<String>,
+    pub group: Option<String>,
+    pub automation_mode: track::automation::AutomationMode,
+    pub automation: Vec<track::automation::AutomationLane>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TrackListResponse {
     pub tracks: Vec<TrackInfo>,
+    pub groups: Vec<TrackGroup>,
+    pub pan_law: track::mixer::PanLaw,
+    pub solo_mode: track::mixer::SoloMode,
+    pub tempo_map: project::tempo::TempoMap,
+    pub metronome: track::metronome::MetronomeSettings,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::processors::processor::db_to_gain;
+
+    fn grouped(gains: &[(&str, f32)]) -> TrackList {
+        let mut track_list = TrackList::new();
+        let mut group = TrackGroup::new("Group");
+        for (name, gain) in gains {
+            let mut track = track::tracks::InputTrack::new(name, None);
+            track.gain = *gain;
+            track_list.add_track(name, track);
+            group.join(name, *gain);
+        }
+        track_list.groups.push(group);
+        track_list
+    }
+
+    fn gain_db(track_list: &TrackList, name: &str) -> f32 {
+        fader_db(track_list.get_track(name).unwrap().lock().unwrap().gain)
+    }
+
+    #[test]
+    fn group_members_keep_their_offsets() {
+        let mut track_list = grouped(&[("Kick", 1.0), ("Snare", db_to_gain(-6.0))]);
+
+        track_list.update_track("Kick", TrackUpdate::Gain(db_to_gain(-10.0)), true);
+        assert!((gain_db(&track_list, "Snare") + 16.0).abs() < 1e-3);
+
+        // Held at the bottom of the fader, then back with the same offset.
+        track_list.update_track("Kick", TrackUpdate::Gain(db_to_gain(-58.0)), true);
+        assert!((gain_db(&track_list, "Snare") + 60.0).abs() < 1e-3);
+        track_list.update_track("Kick", TrackUpdate::Gain(db_to_gain(-10.0)), true);
+        assert!((gain_db(&track_list, "Snare") + 16.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn members_join_against_the_group_level() {
+        let mut track_list = grouped(&[("Kick", db_to_gain(-10.0))]);
+        track_list.update_track("Kick", TrackUpdate::Gain(db_to_gain(-20.0)), true);
+
+        let mut snare = track::tracks::InputTrack::new("Snare", None);
+        snare.gain = db_to_gain(-6.0);
+        track_list.add_track("Snare", snare);
+        track_list.groups[0].join("Snare", db_to_gain(-6.0));
+
+        // The group is at -10 dB, nudging Snare up 1 dB moves Kick up 1 dB.
+        track_list.update_track("Snare", TrackUpdate::Gain(db_to_gain(-5.0)), true);
+        assert!((gain_db(&track_list, "Kick") + 19.0).abs() < 1e-3);
+        assert!((gain_db(&track_list, "Snare") + 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn undo_keeps_the_group_level() {
+        let mut track_list = grouped(&[("Kick", db_to_gain(-10.0)), ("Snare", db_to_gain(-6.0))]);
+        let undo = track_list.update_track("Kick", TrackUpdate::Gain(db_to_gain(-20.0)), true);
+        for (name, update) in undo.into_iter().rev() {
+            track_list.update_track(&name, update, false);
+        }
+        assert!(track_list.groups[0].level.abs() < 1e-3);
+    }
+
+    #[test]
+    fn group_gains_stay_on_the_fader() {
+        let mut track_list = grouped(&[("Kick", db_to_gain(-12.0)), ("Snare", 1.0)]);
+
+        track_list.update_track("Kick", TrackUpdate::Gain(1.0), true);
+        assert_eq!(gain_db(&track_list, "Snare"), 0.0);
+        assert!(track_list.get_track("Snare").unwrap().lock().unwrap().gain <= 1.0);
+
+        track_list.update_track("Kick", TrackUpdate::Gain(db_to_gain(-12.0)), true);
+        assert!(gain_db(&track_list, "Snare").abs() < 1e-3);
+    }
+
+    #[test]
+    fn unlinked_updates_move_one_track() {
+        let mut track_list = grouped(&[("Kick", 1.0), ("Snare", 1.0)]);
+        let undo = track_list.update_track("Kick", TrackUpdate::Gain(0.5), false);
+
+        assert_eq!(undo.len(), 1);
+        assert_eq!(gain_db(&track_list, "Snare"), 0.0);
+    }
 }
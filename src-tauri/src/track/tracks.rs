@@ -1,53 +1,352 @@
-use std::path::PathBuf;
+use std::{path::Path, sync::Arc};
 
-use crate::track;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    project,
+    track::{self, sources::sink::AudioSink},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrackKind {
+    Audio,
+    // Aux return, fed only by the sends of other tracks.
+    Bus,
+    // Only used for the master out in track info.
+    Master,
+}
+
+// Where a take goes, in frames at the engine rate.
+#[derive(Clone, Copy, Default)]
+pub struct TakeWindow {
+    pub start: u64,
+    // Frames of input dropped before the take starts.
+    pub skip: u64,
+    pub limit: Option<u64>,
+    // The loop range when cycle recording, each pass becomes a take lane.
+    pub cycle: Option<project::transport::TimeRange>,
+    pub punch: Option<project::transport::TimeRange>,
+}
+
+// What a finished take turned into.
+pub enum RecordedTake {
+    Clip(track::clips::Clip),
+    // Passes were added to a take folder, these are the folders from before.
+    Folders(Vec<track::takes::TakeFolder>),
+}
 
 pub struct InputTrack {
-    pub source: Box<dyn track::sources::source::AudioSource>,
+    pub kind: TrackKind,
+    pub source: Option<Box<dyn track::sources::source::AudioSource>>,
+    pub clips: Vec<track::clips::Clip>,
+    pub take_folders: Vec<track::takes::TakeFolder>,
+    pub inserts: track::processors::chain::InsertChain,
+    pub sends: Vec<track::sends::AuxSend>,
+    // The bus this track feeds instead of the master.
+    pub output: Option<String>,
+    pub automation: track::automation::Automation,
+    pub take: Option<track::sources::sink::FileSink>,
+    pub take_window: TakeWindow,
     pub name: String,
     pub pan: f32,
     pub mute: bool,
     pub gain: f32,
     pub record: bool,
     pub monitor: bool,
-    // TODO implement solo as feature
+    pub solo: bool,
+    pub solo_safe: bool,
 }
 
 impl InputTrack {
-    pub fn new(name: &str, source: Box<dyn track::sources::source::AudioSource>) -> Self {
+    pub fn new(name: &str, source: Option<Box<dyn track::sources::source::AudioSource>>) -> Self {
         InputTrack {
+            kind: TrackKind::Audio,
             source,
+            clips: Vec::new(),
+            take_folders: Vec::new(),
+            inserts: track::processors::chain::InsertChain::new(),
+            sends: Vec::new(),
+            output: None,
+            automation: track::automation::Automation::new(),
+            take: None,
+            take_window: TakeWindow::default(),
             name: name.to_string(),
             pan: 0.0,
             mute: false,
             gain: 1.0,
             record: false,
             monitor: false,
+            solo: false,
+            solo_safe: false,
         }
     }
-}
 
-impl From<track::raw::InputTrackRaw> for InputTrack {
-    fn from(value: track::raw::InputTrackRaw) -> Self {
+    pub fn bus(name: &str) -> Self {
         InputTrack {
-            source: {
-                match value.source_type {
-                    track::sources::source::AudioSourceRaw::File(path) => {
-                        Box::new(track::sources::source::FileSource::new(PathBuf::from(path)))
+            kind: TrackKind::Bus,
+            ..InputTrack::new(name, None)
+        }
+    }
+
+    pub fn add_clip(&mut self, clip: track::clips::Clip) {
+        self.clips.push(clip);
+        self.clips.sort_by_key(|clip| clip.start);
+    }
+
+    pub fn remove_clip(&mut self, clip_id: u64) -> Option<track::clips::Clip> {
+        let index = self.clips.iter().position(|clip| clip.id == clip_id)?;
+        Some(self.clips.remove(index))
+    }
+
+    // Clips and the comps of the take folders, as the engine plays them with
+    // their crossfades.
+    pub fn playback_clips(&self, sample_rate: u32) -> Vec<track::clips::Clip> {
+        self.playback(sample_rate).0
+    }
+
+    pub fn crossfades(&self, sample_rate: u32) -> Vec<track::clips::Crossfade> {
+        self.playback(sample_rate).1
+    }
+
+    fn playback(
+        &self,
+        sample_rate: u32,
+    ) -> (Vec<track::clips::Clip>, Vec<track::clips::Crossfade>) {
+        let mut clips = self.clips.clone();
+        clips.extend(
+            self.take_folders
+                .iter()
+                .flat_map(track::takes::TakeFolder::comp_clips),
+        );
+        clips.sort_by_key(|clip| clip.start);
+        track::clips::apply_crossfades(&clips, sample_rate)
+    }
+
+    // Returns where the clip was before.
+    pub fn move_clip(&mut self, clip_id: u64, start: u64) -> Option<u64> {
+        let clip = self.clips.iter_mut().find(|clip| clip.id == clip_id)?;
+        let from = clip.start;
+        clip.start = start;
+        self.clips.sort_by_key(|clip| clip.start);
+        Some(from)
+    }
+
+    // Current value of an automatable control, `None` when the insert or
+    // parameter is gone.
+    pub fn automation_value(&self, target: &track::automation::AutomationTarget) -> Option<f32> {
+        match target {
+            track::automation::AutomationTarget::Gain => Some(self.gain),
+            track::automation::AutomationTarget::Pan => Some(self.pan),
+            track::automation::AutomationTarget::Mute => Some(if self.mute { 1.0 } else { 0.0 }),
+            track::automation::AutomationTarget::Parameter {
+                insert_id,
+                parameter,
+            } => self.inserts.get(*insert_id)?.parameter(parameter),
+        }
+    }
+
+    pub fn start_take(
+        &mut self,
+        folder: &Path,
+        window: TakeWindow,
+        sample_rate: u32,
+        on_error: track::sources::sink::ErrorHandler,
+    ) -> Result<(), hound::Error> {
+        if let Some(source) = &self.source {
+            let mut take_number = 1;
+            let mut path = folder.join(format!("{}-take-{}.wav", self.name, take_number));
+            while path.exists() {
+                take_number += 1;
+                path = folder.join(format!("{}-take-{}.wav", self.name, take_number));
+            }
+
+            let spec = source.wav_spec();
+            let to_input =
+                |frames: u64| frames * spec.sample_rate as u64 / sample_rate.max(1) as u64;
+            let mut sink =
+                track::sources::sink::FileSink::new(path, spec, source.get_record_buffer())?;
+            sink.set_window(to_input(window.skip), window.limit.map(to_input));
+            sink.set_error_handler(on_error);
+            source.set_recording(true);
+            sink.start_stream();
+            self.take = Some(sink);
+            self.take_window = window;
+        }
+        Ok(())
+    }
+
+    pub fn finish_take(&mut self, sample_rate: u32) -> Option<RecordedTake> {
+        let sink = self.take.take()?;
+        if let Some(source) = &self.source {
+            source.set_recording(false);
+        }
+        sink.stop_stream();
+
+        let name = sink
+            .path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match track::sources::source::FileSource::new(sink.path.clone(), sample_rate) {
+            Ok(source) => {
+                // Stopped before anything was written.
+                if source.len() == 0 {
+                    if let Err(e) = std::fs::remove_file(&sink.path) {
+                        eprintln!("Failed to remove empty take {}: {}", name, e);
+                    }
+                    return None;
+                }
+                let source = Arc::new(source);
+                let window = self.take_window;
+                let Some(cycle) = window.cycle else {
+                    let clip = track::clips::Clip::new(&name, source, window.start);
+                    self.add_clip(clip.clone());
+                    return Some(RecordedTake::Clip(clip));
+                };
+
+                // Only the punch range of each pass is kept.
+                let punch = window.punch.unwrap_or(cycle);
+                let range = project::transport::TimeRange {
+                    start: cycle.start.max(punch.start),
+                    end: cycle.end.min(punch.end),
+                };
+                let passes = cycle_passes(&name, &source, window.start, cycle)
+                    .into_iter()
+                    .filter_map(|pass| pass.trimmed(range.start, range.end))
+                    .collect::<Vec<_>>();
+                if passes.is_empty() {
+                    return None;
+                }
+
+                let before = self.take_folders.clone();
+                match self
+                    .take_folders
+                    .iter_mut()
+                    .find(|folder| folder.range == range)
+                {
+                    Some(folder) => folder.add_takes(passes),
+                    None => {
+                        let mut folder = track::takes::TakeFolder::new(range);
+                        folder.add_takes(passes);
+                        self.take_folders.push(folder);
                     }
-                    track::sources::source::AudioSourceRaw::Stream(_) => todo!(),
                 }
-            },
+                Some(RecordedTake::Folders(before))
+            }
+            Err(e) => {
+                eprintln!("Failed to load recorded take {}: {}", name, e);
+                None
+            }
+        }
+    }
+
+    pub fn from_raw(
+        value: track::raw::InputTrackRaw,
+        sample_rate: u32,
+        app_handle: &AppHandle,
+    ) -> Self {
+        let mut clips = value
+            .clips
+            .into_iter()
+            .filter_map(|clip| track::clips::Clip::from_raw(clip, sample_rate))
+            .collect::<Vec<_>>();
+        clips.sort_by_key(|clip| clip.start);
+        let take_folders = value
+            .take_folders
+            .into_iter()
+            .map(|folder| track::takes::TakeFolder::from_raw(folder, sample_rate))
+            .collect();
+
+        let source = match value.source_type {
+            Some(track::sources::source::AudioSourceRaw::Stream(device_name)) => {
+                bind_input_device(&device_name, &value.name, app_handle)
+            }
+            _ => None,
+        };
+        if let Some(source) = &source {
+            if value.monitor || value.record {
+                source.start_stream();
+            }
+        }
+
+        InputTrack {
+            kind: value.kind,
+            source,
+            clips,
+            take_folders,
+            inserts: track::processors::chain::InsertChain::from_raw(value.inserts, sample_rate),
+            sends: value.sends,
+            output: value.output,
+            automation: track::automation::Automation::from_raw(
+                value.automation_mode,
+                value.automation,
+            ),
+            take: None,
+            take_window: TakeWindow::default(),
             name: value.name,
             pan: value.pan,
-            mute: false,
+            mute: value.mute,
             gain: value.gain,
-            record: false,
-            monitor: false,
+            record: value.record,
+            monitor: value.monitor,
+            solo: value.solo,
+            solo_safe: value.solo_safe,
         }
     }
 }
 
+// Splits a recording that started at `start` into one clip per pass over the
+// loop, the first pass is cut short when it started inside the loop.
+fn cycle_passes(
+    name: &str,
+    source: &Arc<track::sources::source::FileSource>,
+    start: u64,
+    cycle: project::transport::TimeRange,
+) -> Vec<track::clips::Clip> {
+    let mut passes = Vec::new();
+    let mut offset = 0;
+    let mut start = start;
+    while offset < source.len() && start < cycle.end {
+        let mut pass = track::clips::Clip::new(name, source.clone(), start);
+        pass.offset = offset;
+        pass.length = (cycle.end - start).min(source.len() - offset);
+        offset += pass.length;
+        start = cycle.start;
+        passes.push(pass);
+    }
+    passes
+}
+
+// Live input tracks are saved with the name of their device. When that device
+// is gone the currently selected input is used instead.
+fn bind_input_device(
+    device_name: &str,
+    track_name: &str,
+    app_handle: &AppHandle,
+) -> Option<Box<dyn track::sources::source::AudioSource>> {
+    let audio_context = app_handle.state::<project::states::StateAudioContext>();
+    let device = match audio_context
+        .input_device_registry
+        .get_from_name(device_name)
+    {
+        Some(device) => Some(device),
+        None => {
+            eprintln!(
+                "Input device {} not found for {}, using the selected input",
+                device_name, track_name
+            );
+            audio_context.input_device()
+        }
+    }?;
+
+    Some(Box::new(track::sources::source::StreamSource::new(
+        device,
+        app_handle.clone(),
+        track_name.to_string(),
+    )))
+}
+
 struct DummySink;
 impl track::sources::sink::AudioSink for DummySink {
     fn start_stream(&self) {
@@ -62,15 +361,24 @@ impl track::sources::sink::AudioSink for DummySink {
 pub struct OutputTrack {
     pub gain: f32,
     pub pan: f32,
+    pub inserts: track::processors::chain::InsertChain,
+    // True-peak limiter after the master fader, it can't be moved, removed or
+    // bypassed.
+    pub limiter: track::processors::chain::Insert,
     pub sink: Box<dyn track::sources::sink::AudioSink>,
 }
 
 impl OutputTrack {
-    pub fn new() -> Self {
+    pub fn new(sample_rate: u32) -> Self {
         OutputTrack {
             sink: Box::new(DummySink),
             gain: 1.0,
             pan: 0.0,
+            inserts: track::processors::chain::InsertChain::new(),
+            limiter: track::processors::chain::Insert::new(
+                track::processors::processor::ProcessorKind::Limiter,
+                sample_rate,
+            ),
         }
     }
 
@@ -81,12 +389,24 @@ impl OutputTrack {
     pub fn as_response(&self) -> track::track_list::TrackInfo {
         track::track_list::TrackInfo {
             name: "master-out".to_string(),
+            kind: TrackKind::Master,
             record: false,
             gain: self.gain,
-            pan: 0.0,
+            pan: self.pan,
             monitor: false,
             solo: false,
+            solo_safe: true,
             mute: false,
+            clips: Vec::new(),
+            take_folders: Vec::new(),
+            crossfades: Vec::new(),
+            inserts: self.inserts.as_info(),
+            limiter: Some(self.limiter.as_info()),
+            sends: Vec::new(),
+            output: None,
+            group: None,
+            automation_mode: track::automation::AutomationMode::Off,
+            automation: Vec::new(),
         }
     }
 }
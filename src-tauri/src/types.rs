@@ -1,4 +1,10 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use cpal::{
     traits::{DeviceTrait, HostTrait},
@@ -7,41 +13,65 @@ use cpal::{
 
 const RINGBUFFER_SIZE: usize = 48000;
 
+// Lock-free single producer, single consumer queue of samples. One thread may
+// push and one other thread may pop, neither ever blocks. A push into a full
+// buffer drops the sample.
 pub struct RingBuffer {
-    buffer: Vec<f32>,
-    read_idx: usize,
-    write_idx: usize,
-    full: bool,
+    buffer: Box<[AtomicU32]>,
+    read_idx: AtomicUsize,
+    write_idx: AtomicUsize,
 }
 
 impl RingBuffer {
     pub fn new() -> Self {
+        Self::with_capacity(RINGBUFFER_SIZE)
+    }
+
+    pub fn with_capacity(size: usize) -> Self {
         Self {
-            buffer: vec![0.0; RINGBUFFER_SIZE],
-            read_idx: 0,
-            write_idx: 0,
-            full: false,
+            buffer: (0..size.max(1) + 1).map(|_| AtomicU32::new(0)).collect(),
+            read_idx: AtomicUsize::new(0),
+            write_idx: AtomicUsize::new(0),
         }
     }
 
-    pub fn push(&mut self, sample: f32) {
-        self.buffer[self.write_idx] = sample;
-        self.write_idx = (self.write_idx + 1) % self.buffer.len();
-        if self.full {
-            self.read_idx = (self.read_idx + 1) % self.buffer.len();
+    pub fn push(&self, sample: f32) -> bool {
+        let write_idx = self.write_idx.load(Ordering::Relaxed);
+        let next = (write_idx + 1) % self.buffer.len();
+        if next == self.read_idx.load(Ordering::Acquire) {
+            return false;
         }
-        self.full = self.write_idx == self.read_idx;
+
+        self.buffer[write_idx].store(sample.to_bits(), Ordering::Relaxed);
+        self.write_idx.store(next, Ordering::Release);
+        true
     }
-    pub fn pop(&mut self) -> Option<f32> {
-        if !self.full && self.read_idx == self.write_idx {
+
+    pub fn pop(&self) -> Option<f32> {
+        let read_idx = self.read_idx.load(Ordering::Relaxed);
+        if read_idx == self.write_idx.load(Ordering::Acquire) {
             return None;
         }
 
-        let sample = self.buffer[self.read_idx];
-        self.read_idx = (self.read_idx + 1) % self.buffer.len();
-        self.full = false;
+        let sample = f32::from_bits(self.buffer[read_idx].load(Ordering::Relaxed));
+        self.read_idx
+            .store((read_idx + 1) % self.buffer.len(), Ordering::Release);
         Some(sample)
     }
+
+    pub fn len(&self) -> usize {
+        let read_idx = self.read_idx.load(Ordering::Acquire);
+        let write_idx = self.write_idx.load(Ordering::Acquire);
+        (write_idx + self.buffer.len() - read_idx) % self.buffer.len()
+    }
+
+    // Consumer side only.
+    pub fn skip(&self, count: usize) {
+        let count = count.min(self.len());
+        let read_idx = self.read_idx.load(Ordering::Relaxed);
+        self.read_idx
+            .store((read_idx + count) % self.buffer.len(), Ordering::Release);
+    }
 }
 
 #[derive(Clone)]
@@ -102,10 +132,60 @@ impl OutputDeviceRegistry {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_pops_in_order() {
+        let buffer = RingBuffer::with_capacity(4);
+        assert_eq!(buffer.pop(), None);
+        for sample in [0.1, 0.2, 0.3] {
+            assert!(buffer.push(sample));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop(), Some(0.1));
+        assert_eq!(buffer.pop(), Some(0.2));
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn ring_buffer_drops_pushes_when_full() {
+        let buffer = RingBuffer::with_capacity(2);
+        assert!(buffer.push(1.0));
+        assert!(buffer.push(2.0));
+        assert!(!buffer.push(3.0));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop(), Some(1.0));
+        assert!(buffer.push(4.0));
+        assert_eq!(buffer.pop(), Some(2.0));
+        assert_eq!(buffer.pop(), Some(4.0));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn ring_buffer_wraps_around() {
+        let buffer = RingBuffer::with_capacity(3);
+        for round in 0..10 {
+            let sample = round as f32;
+            assert!(buffer.push(sample));
+            assert!(buffer.push(-sample));
+            assert_eq!(buffer.pop(), Some(sample));
+            assert_eq!(buffer.pop(), Some(-sample));
+        }
+        assert_eq!(buffer.len(), 0);
+    }
 
-    // let _r = app
-    //     .dialog()
-    //     .message("Error")
-    //     .kind(MessageDialogKind::Error)
-    //     .title("Error")
-    //     .buttons(MessageDialogButtons::Ok);
\ No newline at end of file
+    #[test]
+    fn ring_buffer_skip_stops_at_what_is_there() {
+        let buffer = RingBuffer::with_capacity(8);
+        for sample in 0..5 {
+            buffer.push(sample as f32);
+        }
+        buffer.skip(3);
+        assert_eq!(buffer.pop(), Some(3.0));
+        buffer.skip(10);
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.pop(), None);
+    }
+}
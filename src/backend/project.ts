@@ -1,4 +1,13 @@
 import { invoke } from "@tauri-apps/api/core";
+import type {
+    BarBeatTick,
+    BarLine,
+    GridDivision,
+    MetronomeSettings,
+    TempoMap,
+    TimeRange,
+    TransportInfo,
+} from "./types.js";
 
 export async function startStream() {
     try {
@@ -16,4 +25,144 @@ export async function stopStream() {
     } catch (err) {
         console.error("Failed to stop stream:", err);
     }
-}
\ No newline at end of file
+}
+
+/**
+ * Start the transport and record every armed track to a new take
+ */
+export async function recordStream() {
+    try {
+        await invoke("record_stream", {});
+        console.log("Started recording");
+    } catch (err) {
+        console.error("Failed to start recording:", err);
+    }
+}
+
+export async function pauseStream() {
+    try {
+        await invoke("pause_stream", {});
+        console.log("Paused stream");
+    } catch (err) {
+        console.error("Failed to pause stream:", err);
+    }
+}
+
+export async function returnToZero() {
+    try {
+        await invoke("return_to_zero", {});
+    } catch (err) {
+        console.error("Failed to return to zero:", err);
+    }
+}
+
+/**
+ * Move the playhead
+ * @param position the new playhead position in samples
+ */
+export async function locateTransport(position: number) {
+    try {
+        await invoke("locate_transport", { position });
+    } catch (err) {
+        console.error("Failed to locate transport:", err);
+    }
+}
+
+export async function getTransportInfo(): Promise<TransportInfo> {
+    return await invoke<TransportInfo>("get_transport_info");
+}
+
+export async function setLoopRange(range: TimeRange, enabled: boolean) {
+    try {
+        await invoke("set_loop_range", { range, enabled });
+    } catch (err) {
+        console.error("Failed to set loop range:", err);
+    }
+}
+
+/**
+ * Armed tracks only record between punch in and punch out while it's enabled
+ * @param range punch in and punch out in samples
+ */
+export async function setPunchRange(range: TimeRange, enabled: boolean) {
+    try {
+        await invoke("set_punch_range", { range, enabled });
+    } catch (err) {
+        console.error("Failed to set punch range:", err);
+    }
+}
+
+/**
+ * Render the project to a stereo WAV, the backend asks for the destination and
+ * emits `mixdown-progress` with a `MixdownProgress` while it renders
+ * @param start first sample to render, defaults to the project start
+ * @param end last sample to render, defaults to the end of the last clip plus the tail
+ * @param tail samples rendered past the last clip when there's no end, defaults to 5 seconds
+ */
+export async function exportMixdown(start?: number, end?: number, tail?: number) {
+    try {
+        await invoke("export_mixdown", { start, end, tail });
+    } catch (err) {
+        console.error("Failed to export mixdown:", err);
+    }
+}
+
+/**
+ * Undo the last mixer edit, the backend emits `history-changed` with a `HistoryChange`
+ */
+export async function undo() {
+    try {
+        await invoke("undo");
+    } catch (err) {
+        console.error("Failed to undo:", err);
+    }
+}
+
+/**
+ * Redo the last undone mixer edit
+ */
+export async function redo() {
+    try {
+        await invoke("redo");
+    } catch (err) {
+        console.error("Failed to redo:", err);
+    }
+}
+
+/**
+ * Replace the tempo and time signature changes, as one undoable edit
+ */
+export async function setTempoMap(tempoMap: TempoMap) {
+    try {
+        await invoke("set_tempo_map", { tempoMap });
+    } catch (err) {
+        console.error("Failed to set tempo map:", err);
+    }
+}
+
+export async function setMetronome(settings: MetronomeSettings) {
+    try {
+        await invoke("set_metronome", { settings });
+    } catch (err) {
+        console.error("Failed to set metronome:", err);
+    }
+}
+
+export async function samplesToBarBeatTick(position: number): Promise<BarBeatTick> {
+    return await invoke<BarBeatTick>("samples_to_bar_beat_tick", { position });
+}
+
+export async function barBeatTickToSamples(position: BarBeatTick): Promise<number> {
+    return await invoke<number>("bar_beat_tick_to_samples", { position });
+}
+
+/**
+ * Bars starting between two positions in samples, for the ruler
+ */
+export async function getBarLines(start: number, end: number): Promise<BarLine[]> {
+    return await invoke<BarLine[]>("get_bar_lines", { start, end });
+}
+
+export async function snapToGrid(position: number, division: GridDivision): Promise<number> {
+    return await invoke<number>("snap_to_grid", { position, division });
+}
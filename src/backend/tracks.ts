@@ -1,4 +1,4 @@
-import type { TrackInfo, TrackListResponse, TrackUpdate } from "./types.js";
+import type { AutomationTarget, Breakpoint, ClipInfo, CompRegion, Fade, PanLaw, ProcessorKind, ResponsePoint, SoloMode, TrackInfo, TrackListResponse, TrackUpdate } from "./types.js";
 import { invoke } from "@tauri-apps/api/core";
 import { percentToDb, replaceHyphensWithSpaces, replaceSpacesWithHyphens } from "./utils.js";
 import { listen } from "@tauri-apps/api/event";
@@ -41,6 +41,17 @@ export async function addEmptyTrack() {
     }
 }
 
+/**
+ * Add an aux return bus to the track list
+ */
+export async function addBusTrack() {
+    try {
+        await invoke("add_bus_track");
+    } catch (err) {
+        console.error("Failed to add a bus:", err);
+    }
+}
+
 export function addNewTrack(trackTemplate: HTMLTemplateElement, channelTrackTemplate: HTMLTemplateElement, clipTemplate: HTMLTemplateElement, track: TrackInfo, trackContainer: HTMLElement, channelTrackContainer: HTMLElement, clipContainer: HTMLElement) {
     const newTrack = trackTemplate.content.cloneNode(true) as DocumentFragment;
     const newChannel = channelTrackTemplate.content.cloneNode(true) as DocumentFragment;
@@ -372,4 +383,264 @@ export async function updateTrackList() {
             addNewTrack(trackTemplate, channelTrackTemplate, clipTemplate, track, trackContainer, channelTrackContainer, clipContainer);
         });
     }
-}
\ No newline at end of file
+}
+/**
+ * Select how a track's pan position is turned into left and right gains
+ * @param panLaw the new pan law
+ */
+export async function setPanLaw(panLaw: PanLaw) {
+    try {
+        await invoke("set_pan_law", { panLaw });
+    } catch (err) {
+        console.error("Failed to set pan law:", err);
+    }
+}
+
+/**
+ * Select whether soloing a track adds to the soloed tracks or replaces them
+ * @param soloMode the new solo mode
+ */
+export async function setSoloMode(soloMode: SoloMode) {
+    try {
+        await invoke("set_solo_mode", { soloMode });
+    } catch (err) {
+        console.error("Failed to set solo mode:", err);
+    }
+}
+
+/**
+ * Add a processor to a track's insert chain, use "master-out" for the master
+ * @param trackName the track to add to
+ * @param kind the processor to add
+ * @param index position in the chain, defaults to the end
+ * @returns the id of the new insert
+ */
+export async function addInsert(trackName: string, kind: ProcessorKind, index?: number): Promise<number | undefined> {
+    try {
+        return await invoke<number>("add_insert", { trackName, kind, index });
+    } catch (err) {
+        console.error("Failed to add insert:", err);
+    }
+}
+
+export async function removeInsert(trackName: string, insertId: number) {
+    try {
+        await invoke("remove_insert", { trackName, insertId });
+    } catch (err) {
+        console.error("Failed to remove insert:", err);
+    }
+}
+
+export async function moveInsert(trackName: string, insertId: number, index: number) {
+    try {
+        await invoke("move_insert", { trackName, insertId, index });
+    } catch (err) {
+        console.error("Failed to move insert:", err);
+    }
+}
+
+export async function setInsertBypass(trackName: string, insertId: number, bypass: boolean) {
+    try {
+        await invoke("set_insert_bypass", { trackName, insertId, bypass });
+    } catch (err) {
+        console.error("Failed to bypass insert:", err);
+    }
+}
+
+export async function loadInsertFile(trackName: string, insertId: number, path: string) {
+    try {
+        await invoke("load_insert_file", { trackName, insertId, path });
+    } catch (err) {
+        console.error("Failed to load insert file:", err);
+    }
+}
+
+export async function setInsertKey(trackName: string, insertId: number, key: string | null) {
+    try {
+        await invoke("set_insert_key", { trackName, insertId, key });
+    } catch (err) {
+        console.error("Failed to set insert key:", err);
+    }
+}
+
+export async function setInsertParameter(trackName: string, insertId: number, parameter: string, value: number) {
+    try {
+        await invoke("set_insert_parameter", { trackName, insertId, parameter, value });
+    } catch (err) {
+        console.error("Failed to set insert parameter:", err);
+    }
+}
+
+/**
+ * Get the frequency response of an EQ insert for drawing its curve
+ * @param trackName the track the insert is on
+ * @param insertId the EQ insert
+ * @param points how many log spaced points between 20 Hz and 20 kHz
+ */
+export async function getFrequencyResponse(trackName: string, insertId: number, points?: number): Promise<ResponsePoint[]> {
+    return await invoke<ResponsePoint[]>("get_frequency_response", { trackName, insertId, points });
+}
+
+export async function setSend(trackName: string, bus: string, level: number, preFader: boolean) {
+    try {
+        await invoke("set_send", { trackName, bus, level, preFader });
+    } catch (err) {
+        console.error("Failed to set send:", err);
+    }
+}
+
+export async function removeSend(trackName: string, bus: string) {
+    try {
+        await invoke("remove_send", { trackName, bus });
+    } catch (err) {
+        console.error("Failed to remove send:", err);
+    }
+}
+
+/**
+ * Route a track into a bus, or the master when `output` is null
+ */
+export async function setTrackOutput(trackName: string, output: string | null) {
+    try {
+        await invoke("set_track_output", { trackName, output });
+    } catch (err) {
+        console.error("Failed to set track output:", err);
+    }
+}
+
+export async function setAutomationPoints(trackName: string, target: AutomationTarget, points: Breakpoint[]) {
+    try {
+        await invoke("set_automation_points", { trackName, target, points });
+    } catch (err) {
+        console.error("Failed to set automation points:", err);
+    }
+}
+
+export async function releaseAutomation(trackName: string, target: AutomationTarget) {
+    try {
+        await invoke("release_automation", { trackName, target });
+    } catch (err) {
+        console.error("Failed to release automation:", err);
+    }
+}
+
+/**
+ * Split a clip in two
+ * @param position where to cut, in samples on the timeline
+ * @returns the part after the cut
+ */
+export async function splitClip(trackName: string, clipId: number, position: number): Promise<ClipInfo | undefined> {
+    try {
+        return await invoke<ClipInfo>("split_clip", { trackName, clipId, position });
+    } catch (err) {
+        console.error("Failed to split clip:", err);
+    }
+}
+
+export async function trimClip(trackName: string, clipId: number, start: number, end: number) {
+    try {
+        await invoke("trim_clip", { trackName, clipId, start, end });
+    } catch (err) {
+        console.error("Failed to trim clip:", err);
+    }
+}
+
+/**
+ * Copy a clip
+ * @param start where the copy goes, right after the clip when left out
+ * @returns the copy
+ */
+export async function duplicateClip(trackName: string, clipId: number, start?: number): Promise<ClipInfo | undefined> {
+    try {
+        return await invoke<ClipInfo>("duplicate_clip", { trackName, clipId, start });
+    } catch (err) {
+        console.error("Failed to duplicate clip:", err);
+    }
+}
+
+export async function slipClip(trackName: string, clipId: number, offset: number) {
+    try {
+        await invoke("slip_clip", { trackName, clipId, offset });
+    } catch (err) {
+        console.error("Failed to slip clip:", err);
+    }
+}
+
+export async function setClipGain(trackName: string, clipId: number, gain: number) {
+    try {
+        await invoke("set_clip_gain", { trackName, clipId, gain });
+    } catch (err) {
+        console.error("Failed to set clip gain:", err);
+    }
+}
+
+export async function reverseClip(trackName: string, clipId: number, reversed: boolean) {
+    try {
+        await invoke("reverse_clip", { trackName, clipId, reversed });
+    } catch (err) {
+        console.error("Failed to reverse clip:", err);
+    }
+}
+
+export async function setClipFades(trackName: string, clipId: number, fadeIn: Fade, fadeOut: Fade) {
+    try {
+        await invoke("set_clip_fades", { trackName, clipId, fadeIn, fadeOut });
+    } catch (err) {
+        console.error("Failed to set clip fades:", err);
+    }
+}
+
+/**
+ * Set the crossfade from the clip before into this one
+ * @param crossfade length and curve, null for the automatic equal-power one
+ */
+export async function setCrossfade(trackName: string, clipId: number, crossfade: Fade | null) {
+    try {
+        await invoke("set_crossfade", { trackName, clipId, crossfade });
+    } catch (err) {
+        console.error("Failed to set crossfade:", err);
+    }
+}
+
+export async function selectTake(trackName: string, folderId: number, take: number) {
+    try {
+        await invoke("select_take", { trackName, folderId, take });
+    } catch (err) {
+        console.error("Failed to select take:", err);
+    }
+}
+
+export async function setComp(trackName: string, folderId: number, regions: CompRegion[]) {
+    try {
+        await invoke("set_comp", { trackName, folderId, regions });
+    } catch (err) {
+        console.error("Failed to set comp:", err);
+    }
+}
+
+export async function createGroup(name: string, members: string[]) {
+    try {
+        await invoke("create_group", { name, members });
+    } catch (err) {
+        console.error("Failed to create group:", err);
+    }
+}
+
+export async function removeGroup(name: string) {
+    try {
+        await invoke("remove_group", { name });
+    } catch (err) {
+        console.error("Failed to remove group:", err);
+    }
+}
+
+/**
+ * Move a track into a group, or out of its group when `group` is null
+ */
+export async function setTrackGroup(trackName: string, group: string | null) {
+    try {
+        await invoke("set_track_group", { trackName, group });
+    } catch (err) {
+        console.error("Failed to set track group:", err);
+    }
+}
@@ -5,18 +5,235 @@ export type TrackInfo = {
     pan: number;
     monitor: boolean;
     solo: boolean;
+    solo_safe: boolean;
     mute: boolean;
     record: boolean;
+    kind: TrackKind;
+    clips: ClipInfo[];
+    take_folders: TakeFolderInfo[];
+    crossfades: Crossfade[];
+    inserts: InsertInfo[];
+    // The master's fixed limiter, null on other tracks.
+    limiter: InsertInfo | null;
+    sends: AuxSend[];
+    output: string | null;
+    group: string | null;
+    automation_mode: AutomationMode;
+    automation: AutomationLane[];
 };
 
+export type AutomationMode = "Off" | "Read" | "Touch" | "Latch" | "Write";
+
+export type AutomationTarget = "Gain" | "Pan" | "Mute"
+    | { Parameter: { insert_id: number; parameter: string } };
+
+export type Curve = "Linear" | "Step" | "Exponential" | "Logarithmic" | "Smooth";
+
+// Position in frames, value in the units of the control.
+export type Breakpoint = {
+    position: number;
+    value: number;
+    curve: Curve;
+};
+
+export type AutomationLane = {
+    target: AutomationTarget;
+    points: Breakpoint[];
+};
+
+export type TrackGroup = {
+    name: string;
+    members: string[];
+    level: number;
+    offsets: Record<string, number>;
+};
+
+export type TrackKind = "Audio" | "Bus" | "Master";
+
+export type AuxSend = {
+    bus: string;
+    level: number;
+    pre_fader: boolean;
+};
+
+export type FadeCurve = "Linear" | "EqualPower" | "Exponential" | "Logarithmic" | "Smooth";
+
+// Length in samples.
+export type Fade = {
+    length: number;
+    curve: FadeCurve;
+};
+
+export type ClipInfo = {
+    id: number;
+    name: string;
+    start: number;
+    offset: number;
+    length: number;
+    gain: number;
+    reversed: boolean;
+    fade_in: Fade;
+    fade_out: Fade;
+    // Crossfade from the clip before into this one, null for the automatic one.
+    crossfade: Fade | null;
+};
+
+// A crossfade into clip `clip_id` as the engine plays it, in samples.
+export type Crossfade = {
+    clip_id: number;
+    start: number;
+    end: number;
+    curve: FadeCurve;
+};
+
+// `take` is the index of the take lane the region plays from.
+export type CompRegion = {
+    take: number;
+    start: number;
+    end: number;
+};
+
+// Passes of a cycle recording, the track plays the comp.
+export type TakeFolderInfo = {
+    id: number;
+    range: TimeRange;
+    takes: ClipInfo[];
+    comp: CompRegion[];
+};
+
+export type ProcessorKind = "Utility" | "Equalizer" | "Compressor" | "Limiter" | "Gate" | "Ducker" | "Delay" | "Reverb" | "ConvolutionReverb";
+
+export type Parameter = {
+    id: string;
+    name: string;
+    min: number;
+    max: number;
+    default: number;
+    value: number;
+};
+
+export type ResponsePoint = {
+    frequency: number;
+    gain: number;
+};
+
+export type InsertInfo = {
+    id: number;
+    kind: ProcessorKind;
+    bypass: boolean;
+    parameters: Parameter[];
+    file: string | null;
+    key: string | null;
+};
+
+// Payload of the "gain-reduction" event, reduction in positive dB.
+export type GainReduction = {
+    track: string;
+    insert_id: number;
+    gain_reduction: number;
+};
+
+export type PanLaw = "Balance" | "ConstantPower" | "Compromise" | "Linear";
+
+export type SoloMode = "Additive" | "Exclusive";
+
 export type TrackListResponse = {
     tracks: TrackInfo[];
+    groups: TrackGroup[];
+    pan_law: PanLaw;
+    solo_mode: SoloMode;
+    tempo_map: TempoMap;
+    metronome: MetronomeSettings;
+};
+
+// Ticks count 960 to the quarter note, bars in meters count from 0.
+export type TempoMap = {
+    tempos: { tick: number; bpm: number }[];
+    meters: { bar: number; numerator: number; denominator: number }[];
+};
+
+export type BuiltinClick = "Beep" | "Woodblock";
+
+export type ClickSound =
+    | { Builtin: BuiltinClick }
+    | { Files: { accent: string; beat: string } };
+
+// Channels counts from 0, the click goes to that channel and the next.
+export type MetronomeOutput = "Master" | { Channels: number };
+
+export type MetronomeSettings = {
+    enabled: boolean;
+    count_in_bars: number;
+    level: number;
+    sound: ClickSound;
+    output: MetronomeOutput;
+};
+
+// Bar and beat count from 1.
+export type BarBeatTick = {
+    bar: number;
+    beat: number;
+    tick: number;
 };
 
+export type BarLine = {
+    bar: number;
+    position: number;
+    numerator: number;
+    denominator: number;
+};
+
+export type GridDivision = "Bar" | "Beat" | { Note: number };
+
 export type TrackUpdate = { Pan: number }
     | { Name: string }
     | { Gain: number }
     | { Monitor: boolean }
     | { Solo: boolean }
+    | { SoloSafe: boolean }
     | { Mute: boolean }
-    | { Record: boolean };
+    | { Record: boolean }
+    | { Output: string | null }
+    | { AutomationMode: AutomationMode };
+
+
+export type HistoryScope = { Track: string } | { Clips: string } | { Inserts: string } | "TrackList" | "Mixer";
+
+export type HistoryChange = {
+    scope: HistoryScope;
+    can_undo: boolean;
+    can_redo: boolean;
+};
+
+// Samples, end not included.
+export type TimeRange = {
+    start: number;
+    end: number;
+};
+
+export type TransportInfo = {
+    state: "Stopped" | "Playing" | "Paused" | "Recording";
+    position: number;
+    seconds: number;
+    sample_rate: number;
+    count_in: number;
+    loop_range: TimeRange;
+    loop_enabled: boolean;
+    punch_range: TimeRange;
+    punch_enabled: boolean;
+};
+
+// Progress from 0 to 1, `error` is set when a finished render failed.
+export type MixdownProgress = {
+    path: string;
+    progress: number;
+    finished: boolean;
+    error: string | null;
+};
+
+// Sent when a take stops early because its file couldn't be written, the
+// part already recorded is kept.
+export type RecordingError = {
+    track: string;
+    error: string;
+};
@@ -1,6 +1,7 @@
 import { listen } from "@tauri-apps/api/event";
 import { addEmptyTrack, updateTrackList } from "./backend/tracks";
 import { loadTheme } from "./backend/theme";
+import type { RecordingError } from "./backend/types";
 
 async function init() {
   updateTrackList();
@@ -14,6 +15,10 @@ async function init() {
   await listen("updated-track-list", (_) => {
     updateTrackList();
   });
+
+  await listen<RecordingError>("recording-error", (event) => {
+    console.error(`Recording on ${event.payload.track} stopped:`, event.payload.error);
+  });
 }
 
 window.addEventListener("DOMContentLoaded", async () => {